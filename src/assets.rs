@@ -2,6 +2,79 @@ use crate::types::*;
 use macroquad::prelude::*;
 use std::collections::HashMap;
 
+// SVGs are embedded in the binary so the client needs no filesystem access,
+// which the browser build does not have.
+const PIECE_SVGS: [(PieceType, &str, &[u8], &[u8]); 11] = [
+    (
+        PieceType::Pawn,
+        "P",
+        include_bytes!("../assets/wP.svg"),
+        include_bytes!("../assets/bP.svg"),
+    ),
+    (
+        PieceType::Knight,
+        "N",
+        include_bytes!("../assets/wN.svg"),
+        include_bytes!("../assets/bN.svg"),
+    ),
+    (
+        PieceType::Bishop,
+        "B",
+        include_bytes!("../assets/wB.svg"),
+        include_bytes!("../assets/bB.svg"),
+    ),
+    (
+        PieceType::Rook,
+        "R",
+        include_bytes!("../assets/wR.svg"),
+        include_bytes!("../assets/bR.svg"),
+    ),
+    (
+        PieceType::Queen,
+        "Q",
+        include_bytes!("../assets/wQ.svg"),
+        include_bytes!("../assets/bQ.svg"),
+    ),
+    (
+        PieceType::King,
+        "K",
+        include_bytes!("../assets/wK.svg"),
+        include_bytes!("../assets/bK.svg"),
+    ),
+    (
+        PieceType::Hawk,
+        "H",
+        include_bytes!("../assets/wH.svg"),
+        include_bytes!("../assets/bH.svg"),
+    ),
+    (
+        PieceType::Elephant,
+        "E",
+        include_bytes!("../assets/wE.svg"),
+        include_bytes!("../assets/bE.svg"),
+    ),
+    (
+        PieceType::Archbishop,
+        "A",
+        include_bytes!("../assets/wA.svg"),
+        include_bytes!("../assets/bA.svg"),
+    ),
+    (
+        PieceType::Cannon,
+        "C",
+        include_bytes!("../assets/wC.svg"),
+        include_bytes!("../assets/bC.svg"),
+    ),
+    (
+        PieceType::Monarch,
+        "M",
+        include_bytes!("../assets/wM.svg"),
+        include_bytes!("../assets/bM.svg"),
+    ),
+];
+
+const BOARD_SVG: &[u8] = include_bytes!("../assets/board.svg");
+
 pub struct Assets {
     pub textures: HashMap<(PieceType, PlayerColor), Texture2D>,
     pub board_texture: Texture2D,
@@ -11,37 +84,21 @@ impl Assets {
     pub async fn load() -> Self {
         let mut textures = HashMap::new();
 
-        let pieces = [
-            (PieceType::Pawn, "P"),
-            (PieceType::Knight, "N"),
-            (PieceType::Bishop, "B"),
-            (PieceType::Rook, "R"),
-            (PieceType::Queen, "Q"),
-            (PieceType::King, "K"),
-            (PieceType::Hawk, "H"),
-            (PieceType::Elephant, "E"),
-            (PieceType::Archbishop, "A"),
-            (PieceType::Cannon, "C"),
-            (PieceType::Monarch, "M"),
-        ];
-
-        for (pt, suffix) in pieces.iter() {
-            let path = format!("assets/w{}.svg", suffix);
-            if let Some(tex) = load_svg(&path, 80).await {
+        for (pt, suffix, white_svg, black_svg) in PIECE_SVGS.iter() {
+            if let Some(tex) = load_svg(white_svg, 80) {
                 textures.insert((*pt, PlayerColor::White), tex);
             } else {
-                println!("Failed to load {}", path);
+                println!("Failed to load w{}.svg", suffix);
             }
 
-            let path = format!("assets/b{}.svg", suffix);
-            if let Some(tex) = load_svg(&path, 80).await {
+            if let Some(tex) = load_svg(black_svg, 80) {
                 textures.insert((*pt, PlayerColor::Black), tex);
             } else {
-                println!("Failed to load {}", path);
+                println!("Failed to load b{}.svg", suffix);
             }
         }
 
-        let board_tex = load_svg("assets/board.svg", 640).await.unwrap_or_else(|| {
+        let board_tex = load_svg(BOARD_SVG, 640).unwrap_or_else(|| {
             println!("Failed to load board.svg");
             Texture2D::from_image(&Image::gen_image_color(640, 640, WHITE))
         });
@@ -53,14 +110,9 @@ impl Assets {
     }
 }
 
-async fn load_svg(path: &str, target_size: u32) -> Option<Texture2D> {
-    let bytes = match std::fs::read(path) {
-        Ok(b) => b,
-        Err(_) => return None,
-    };
-
+fn load_svg(bytes: &[u8], target_size: u32) -> Option<Texture2D> {
     let opt = usvg::Options::default();
-    let tree = usvg::Tree::from_data(&bytes, &opt).ok()?;
+    let tree = usvg::Tree::from_data(bytes, &opt).ok()?;
 
     let size = tree.size();
     let width = size.width();
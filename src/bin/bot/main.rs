@@ -0,0 +1,332 @@
+use ascension_chess::client::{Client, ClientError, Event, JoinOptions};
+use ascension_chess::game::Game;
+use ascension_chess::network::{ColorPreference, GameAction, GameMessage};
+use ascension_chess::online::HeartbeatSettings;
+use ascension_chess::strategy::{MoveSelector, Strategy};
+use ascension_chess::tls::ClientTls;
+use ascension_chess::types::PlayerColor;
+use clap::Parser;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How long to wait before reconnecting after leaving a room.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Plays Ascension Chess on a server without a window, for practice games
+/// and for exercising the server.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Room code or name to join; the room is created if it does not exist
+    #[arg(short, long)]
+    room: Option<String>,
+
+    /// Password protecting the room
+    #[arg(short, long)]
+    password: Option<String>,
+
+    /// Create a new room with a generated code
+    #[arg(short, long, conflicts_with = "room")]
+    create: bool,
+
+    /// Color to play when creating a room: white, black or random
+    #[arg(long, default_value = "random")]
+    color: ColorPreference,
+
+    /// List the room in the lobby so anyone can pick a game with the bot
+    #[arg(long)]
+    public: bool,
+
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    server: String,
+
+    /// Connect to the server over TLS, trusting the public certificate authorities
+    #[arg(long)]
+    tls: bool,
+
+    /// Connect over TLS, trusting only the certificate in this PEM file
+    #[arg(long)]
+    trust_cert: Option<PathBuf>,
+
+    /// Account name for rated games
+    #[arg(long, requires = "token")]
+    name: Option<String>,
+
+    /// Secret token of the account given by --name
+    #[arg(long, requires = "name")]
+    token: Option<String>,
+
+    /// How the bot picks its moves
+    #[arg(long, value_enum, default_value_t = Strategy::Random)]
+    strategy: Strategy,
+
+    /// Milliseconds to wait before each action; the server throttles clients
+    /// that send more than a few messages per second
+    #[arg(long, default_value_t = 500)]
+    move_delay_ms: u64,
+
+    /// Stop after this many finished games instead of playing forever
+    #[arg(long)]
+    games: Option<u32>,
+
+    /// Seconds between heartbeats sent to the server
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    heartbeat_interval: u64,
+
+    /// Unanswered heartbeats in a row before the connection is considered lost
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    max_missed_heartbeats: u32,
+}
+
+/// Why the bot left its room.
+enum Exit {
+    /// Played the number of games asked for.
+    Done,
+    /// The opponent or the server went away; join again for a fresh game.
+    Rejoin,
+    /// The server is restarting; take the same seat again once it is back.
+    Restarting {
+        room: Option<String>,
+        rejoin_token: Option<String>,
+    },
+    /// The server refused the bot, so rejoining would not help.
+    Refused(String),
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::builder()
+                .with_default_directive(tracing::Level::INFO.into())
+                .from_env_lossy(),
+        )
+        .init();
+    if args.room.is_none() && !args.create {
+        eprintln!("Pass --room <NAME> or --create");
+        std::process::exit(2);
+    }
+
+    let tls = match &args.trust_cert {
+        Some(cert) => match ClientTls::pinned(cert) {
+            Ok(tls) => Some(tls),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        },
+        None => args.tls.then(ClientTls::public),
+    };
+
+    let mut selector = args.strategy.selector();
+    let mut finished = 0;
+    // Room and token of the seat held before a server restart.
+    let mut restored_seat: Option<(String, Option<String>)> = None;
+    loop {
+        let exit = match join(&args, tls.as_ref(), restored_seat.as_ref()).await {
+            Ok((mut client, color)) => {
+                restored_seat = None;
+                let exit = play(&mut client, color, selector.as_mut(), &args, &mut finished).await;
+                client.disconnect().await;
+                exit
+            }
+            // A certificate that is not trusted, or TLS on only one side,
+            // will not fix itself.
+            Err(ClientError::Io(e)) if e.kind() == io::ErrorKind::InvalidData => {
+                Exit::Refused(e.to_string())
+            }
+            // The server is unreachable or went away; it may be restarting.
+            Err(ClientError::Io(e)) => {
+                warn!("{}", e);
+                Exit::Rejoin
+            }
+            Err(ClientError::Disconnected(reason)) => {
+                warn!("{}", reason);
+                Exit::Rejoin
+            }
+            Err(e) => Exit::Refused(e.to_string()),
+        };
+        match exit {
+            Exit::Done => break,
+            Exit::Rejoin => {
+                info!("Reconnecting in {:?}", RECONNECT_DELAY);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+            Exit::Restarting { room, rejoin_token } => {
+                restored_seat = room.map(|room| (room, rejoin_token));
+                info!("Reconnecting in {:?}", RECONNECT_DELAY);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+            Exit::Refused(message) => {
+                eprintln!("Error: {}", message);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Connects, over TLS if `tls` is given, logs in if an account was given
+/// and takes a seat, the one held before a restart if `restored_seat` is set.
+async fn join(
+    args: &Args,
+    tls: Option<&ClientTls>,
+    restored_seat: Option<&(String, Option<String>)>,
+) -> Result<(Client, PlayerColor), ClientError> {
+    let client_name = concat!("ascension-bot ", env!("CARGO_PKG_VERSION"));
+    let heartbeat = Some(HeartbeatSettings {
+        interval: Duration::from_secs(args.heartbeat_interval),
+        max_missed: args.max_missed_heartbeats,
+    });
+    let mut client = match tls {
+        Some(tls) => Client::connect_tls(&args.server, tls, client_name, heartbeat).await?,
+        None => Client::connect(args.server.as_str(), client_name, heartbeat).await?,
+    };
+    if let (Some(name), Some(token)) = (&args.name, &args.token) {
+        let entry = client.login(name, token).await?;
+        info!("Logged in as {} ({:.0})", entry.name, entry.rating);
+    }
+    let joined = client
+        .join(JoinOptions {
+            room: restored_seat
+                .map(|(room, _)| room.clone())
+                .or_else(|| args.room.clone()),
+            password: args.password.clone(),
+            color: Some(args.color),
+            public: args.public,
+            rejoin_token: restored_seat.and_then(|(_, token)| token.clone()),
+        })
+        .await?;
+    info!("Seated as {:?} in room {}", joined.color, joined.room);
+    Ok((client, joined.color))
+}
+
+/// Plays games in one room until the opponent leaves, the connection
+/// drops or enough games have been played. After each game the bot offers a
+/// rematch, which also accepts one the opponent has offered. Take-back
+/// requests are always accepted.
+async fn play(
+    client: &mut Client,
+    color: PlayerColor,
+    selector: &mut dyn MoveSelector,
+    args: &Args,
+    finished: &mut u32,
+) -> Exit {
+    let mut seat = Seat {
+        game: Game::default(),
+        color,
+        game_over_seen: false,
+        awaiting_takeback: false,
+    };
+    while let Some(event) = client.next_event().await {
+        if let Some(exit) = seat.handle(event, client) {
+            return exit;
+        }
+
+        while seat.to_move() {
+            tokio::time::sleep(Duration::from_millis(args.move_delay_ms)).await;
+            // Catch up first, e.g. on the moves of a game joined midway.
+            while let Some(event) = client.try_next_event() {
+                if let Some(exit) = seat.handle(event, client) {
+                    return exit;
+                }
+            }
+            if !seat.to_move() {
+                break;
+            }
+            let Some(action) = selector.choose(&seat.game) else {
+                warn!("No action available");
+                break;
+            };
+            action.apply(&mut seat.game);
+            if client.send_action(action).is_err() {
+                return Exit::Rejoin;
+            }
+        }
+
+        if let Some(result) = seat.game.result
+            && !seat.game_over_seen
+        {
+            seat.game_over_seen = true;
+            info!("Game over: {:?}", result);
+            *finished += 1;
+            if args.games.is_some_and(|games| *finished >= games) {
+                return Exit::Done;
+            }
+            client.send(GameMessage::OfferRematch).ok();
+        }
+    }
+    Exit::Rejoin
+}
+
+/// The bot's view of the room it is playing in.
+struct Seat {
+    game: Game,
+    color: PlayerColor,
+    game_over_seen: bool,
+    /// A take-back was accepted and the game it leaves has not arrived yet.
+    awaiting_takeback: bool,
+}
+
+impl Seat {
+    fn to_move(&self) -> bool {
+        self.game.result.is_none() && self.color == self.game.turn && !self.awaiting_takeback
+    }
+
+    /// Updates the seat from a connection event, answering take-back
+    /// requests, and returns why to leave the room if the bot should.
+    fn handle(&mut self, event: Event, client: &Client) -> Option<Exit> {
+        let msg = match event {
+            Event::Message(msg) => msg,
+            Event::Latency(_) => return None,
+            Event::Disconnected(reason) => {
+                info!("{}", reason);
+                return Some(Exit::Rejoin);
+            }
+        };
+        if let Some(action) = GameAction::from_message(&msg) {
+            action.apply(&mut self.game);
+            return None;
+        }
+        match msg {
+            GameMessage::RematchStarted { color, score } => {
+                info!(
+                    "Rematch started as {:?}, match score {}-{}",
+                    color, score.you, score.opponent
+                );
+                *self = Seat {
+                    game: Game::default(),
+                    color,
+                    game_over_seen: false,
+                    awaiting_takeback: false,
+                };
+            }
+            GameMessage::RequestTakeback => {
+                info!("Accepting a take-back");
+                self.awaiting_takeback = client.send(GameMessage::AcceptTakeback).is_ok();
+            }
+            GameMessage::TakenBack { actions } => {
+                self.game = GameAction::replay(&actions);
+                self.awaiting_takeback = false;
+            }
+            GameMessage::OpponentDisconnected => return Some(Exit::Rejoin),
+            GameMessage::ServerRestarting {
+                message,
+                room,
+                rejoin_token,
+            } => {
+                info!("{}", message);
+                return Some(Exit::Restarting { room, rejoin_token });
+            }
+            GameMessage::Error { message } => {
+                warn!("Server error: {}", message);
+                // Whatever went wrong, do not wait forever for a take-back.
+                self.awaiting_takeback = false;
+            }
+            _ => {}
+        }
+        None
+    }
+}
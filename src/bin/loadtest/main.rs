@@ -0,0 +1,344 @@
+mod stats;
+
+use ascension_chess::client::{Client, ClientError, Event, JoinOptions};
+use ascension_chess::game::Game;
+use ascension_chess::network::{ColorPreference, GameAction, GameMessage};
+use ascension_chess::strategy::{MoveSelector, RandomMover};
+use ascension_chess::types::PlayerColor;
+use clap::Parser;
+use rand::prelude::*;
+use stats::{Percentiles, Stats, resident_kib};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::time::{self, Instant, timeout};
+
+/// Longest wait for the server to relay an action or start a rematch before
+/// the pair gives up.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Pause after the clients leave before the server's memory is sampled again.
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// Opens many pairs of simulated players against a game server, has each
+/// pair play random games and reports throughput, latency, errors and the
+/// server's memory use.
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    server: String,
+
+    /// Pairs of players, each playing in its own room
+    #[arg(long, default_value_t = 100)]
+    pairs: u32,
+
+    /// Pairs started per second while ramping up
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u32).range(1..))]
+    connect_rate: u32,
+
+    /// Seconds to run, including the ramp up
+    #[arg(long, default_value_t = 60)]
+    duration_secs: u64,
+
+    /// Shortest pause in milliseconds before each action
+    #[arg(long, default_value_t = 200)]
+    min_think_ms: u64,
+
+    /// Longest pause in milliseconds before each action
+    #[arg(long, default_value_t = 1000)]
+    max_think_ms: u64,
+
+    /// Seconds between progress reports
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    report_secs: u64,
+
+    /// Process id of a server on this machine whose memory use to report
+    #[arg(long)]
+    server_pid: Option<u32>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if args.min_think_ms > args.max_think_ms {
+        eprintln!("--min-think-ms must not exceed --max-think-ms");
+        std::process::exit(2);
+    }
+    let args = Arc::new(args);
+    let stats = Arc::new(Stats::default());
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(args.duration_secs);
+    let start_rss = args.server_pid.and_then(resident_kib);
+
+    let spawner = {
+        let (args, stats) = (args.clone(), stats.clone());
+        tokio::spawn(async move {
+            let mut pairs = Vec::new();
+            let mut ticker = time::interval(Duration::from_secs(1) / args.connect_rate);
+            for _ in 0..args.pairs {
+                ticker.tick().await;
+                if Instant::now() >= deadline {
+                    break;
+                }
+                pairs.push(tokio::spawn(play_pair(
+                    args.clone(),
+                    stats.clone(),
+                    deadline,
+                )));
+            }
+            for pair in pairs {
+                pair.await.ok();
+            }
+        })
+    };
+
+    let mut peak_rss = start_rss;
+    let mut reported = 0;
+    let mut last_report = start;
+    let mut ticker = time::interval_at(
+        start + Duration::from_secs(args.report_secs),
+        Duration::from_secs(args.report_secs),
+    );
+    while Instant::now() < deadline {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = time::sleep_until(deadline) => break,
+        }
+        let rss = args.server_pid.and_then(resident_kib);
+        peak_rss = peak_rss.max(rss);
+        let (relay, total) = stats.relay_latencies_since(reported);
+        let per_sec = relay.count as f64 / last_report.elapsed().as_secs_f64();
+        reported = total;
+        last_report = Instant::now();
+        println!(
+            "[{:>4}s] pairs {:>5}  games {:>6}  actions {:>7.1}/s  relay {}  errors {}{}",
+            start.elapsed().as_secs(),
+            stats.active_pairs.load(Ordering::Relaxed),
+            stats.games.load(Ordering::Relaxed),
+            per_sec,
+            format_percentiles(&relay),
+            stats.error_count(),
+            format_memory(start_rss, rss),
+        );
+    }
+
+    println!("Stopping, waiting for pairs to finish their current action");
+    timeout(REPLY_TIMEOUT, spawner).await.ok();
+    let end_rss = args.server_pid.and_then(resident_kib);
+    time::sleep(SETTLE_DELAY).await;
+    let settled_rss = args.server_pid.and_then(resident_kib);
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let (relay, total) = stats.relay_latencies_since(0);
+    println!();
+    println!("Pairs started   {}", stats.join_latencies().count);
+    println!("Games finished  {}", stats.games.load(Ordering::Relaxed));
+    println!(
+        "Actions         {} ({:.1}/s)",
+        total,
+        total as f64 / elapsed
+    );
+    println!("Relay latency   {}", format_percentiles(&relay));
+    println!(
+        "Join latency    {}",
+        format_percentiles(&stats.join_latencies())
+    );
+    if args.server_pid.is_some() {
+        println!(
+            "Server memory   start {}  peak {}  end {}  after clients left {}",
+            format_kib(start_rss),
+            format_kib(peak_rss),
+            format_kib(end_rss),
+            format_kib(settled_rss),
+        );
+    }
+    let errors = stats.errors();
+    if errors.is_empty() {
+        println!("Errors          none");
+    } else {
+        println!("Errors          {}", stats.error_count());
+        for (kind, count) in errors {
+            println!("  {:>8}  {}", count, kind);
+        }
+    }
+}
+
+/// Seats two players in a fresh room and has them play random games, with
+/// rematches, until the deadline.
+async fn play_pair(args: Arc<Args>, stats: Arc<Stats>, deadline: Instant) {
+    let joining = Instant::now();
+    let mut players = match seat_pair(&args.server).await {
+        Ok(players) => players,
+        Err(e) => {
+            stats.error(format!("join: {}", e));
+            return;
+        }
+    };
+    stats.joined(joining.elapsed());
+    stats.active_pairs.fetch_add(1, Ordering::Relaxed);
+
+    if let Err(e) = play_games(&mut players, &args, &stats, deadline).await {
+        stats.error(e);
+    }
+
+    stats.active_pairs.fetch_sub(1, Ordering::Relaxed);
+    let [white, black] = players;
+    white.disconnect().await;
+    black.disconnect().await;
+}
+
+/// Connects two players and seats them in a new room, White first.
+async fn seat_pair(server: &str) -> Result<[Client; 2], ClientError> {
+    let mut white = Client::connect(server, "loadtest", None).await?;
+    let room = white
+        .join(JoinOptions {
+            color: Some(ColorPreference::White),
+            ..Default::default()
+        })
+        .await?
+        .room;
+    let mut black = Client::connect(server, "loadtest", None).await?;
+    black
+        .join(JoinOptions {
+            room: Some(room),
+            ..Default::default()
+        })
+        .await?;
+    Ok([white, black])
+}
+
+/// Plays until the deadline, returning a description of the first error.
+/// `players` is kept in seat order, White first.
+async fn play_games(
+    players: &mut [Client; 2],
+    args: &Args,
+    stats: &Stats,
+    deadline: Instant,
+) -> Result<(), String> {
+    let mut rng = StdRng::from_os_rng();
+    let mut selector = RandomMover::default();
+    let mut game = Game::new();
+    loop {
+        if game.result.is_some() {
+            stats.games.fetch_add(1, Ordering::Relaxed);
+            start_rematch(players).await?;
+            game = Game::new();
+        }
+
+        let think = Duration::from_millis(rng.random_range(args.min_think_ms..=args.max_think_ms));
+        if Instant::now() + think >= deadline {
+            return Ok(());
+        }
+        time::sleep(think).await;
+
+        let Some(action) = selector.choose(&game) else {
+            return Err("no legal action".into());
+        };
+        let [white, black] = players;
+        let (mover, opponent) = match game.turn {
+            PlayerColor::White => (white, black),
+            PlayerColor::Black => (black, white),
+        };
+        action.apply(&mut game);
+        let sent = Instant::now();
+        mover.send_action(action).map_err(|e| e.to_string())?;
+        match timeout(REPLY_TIMEOUT, relayed(mover, opponent, action)).await {
+            Ok(Ok(())) => stats.relayed(sent.elapsed()),
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Err("timed out waiting for an action to be relayed".into()),
+        }
+    }
+}
+
+/// Waits until `opponent` receives `action`, failing on an error or a
+/// disconnect seen by either player.
+async fn relayed(
+    mover: &mut Client,
+    opponent: &mut Client,
+    action: GameAction,
+) -> Result<(), String> {
+    loop {
+        let event = tokio::select! {
+            Some(event) = mover.next_event() => event,
+            Some(event) = opponent.next_event() => match event {
+                Event::Message(msg) if GameAction::from_message(&msg).is_some() => {
+                    return if GameAction::from_message(&msg) == Some(action) {
+                        Ok(())
+                    } else {
+                        Err("a different action was relayed".into())
+                    };
+                }
+                event => event,
+            },
+            else => return Err("connection closed".into()),
+        };
+        check(event)?;
+    }
+}
+
+/// Has both players offer a rematch and waits until it starts. The seats
+/// swap, so the players do too.
+async fn start_rematch(players: &mut [Client; 2]) -> Result<(), String> {
+    for player in players.iter_mut() {
+        player
+            .send(GameMessage::OfferRematch)
+            .map_err(|e| e.to_string())?;
+    }
+    for player in players.iter_mut() {
+        let started = timeout(REPLY_TIMEOUT, async {
+            loop {
+                match player.next_event().await {
+                    Some(Event::Message(GameMessage::RematchStarted { .. })) => return Ok(()),
+                    Some(event) => check(event)?,
+                    None => return Err("connection closed".to_string()),
+                }
+            }
+        });
+        match started.await {
+            Ok(result) => result?,
+            Err(_) => return Err("timed out waiting for a rematch".into()),
+        }
+    }
+    players.swap(0, 1);
+    Ok(())
+}
+
+/// Fails on events that end a pair's run; everything else is ignored.
+fn check(event: Event) -> Result<(), String> {
+    match event {
+        Event::Message(GameMessage::Error { message }) => Err(format!("server error: {}", message)),
+        Event::Message(GameMessage::OpponentDisconnected) => Err("opponent disconnected".into()),
+        Event::Message(GameMessage::ServerRestarting { .. }) => Err("server restarting".into()),
+        Event::Disconnected(reason) => Err(reason.to_string()),
+        Event::Message(_) | Event::Latency(_) => Ok(()),
+    }
+}
+
+fn format_percentiles(p: &Percentiles) -> String {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    format!(
+        "p50 {:.1}ms p95 {:.1}ms p99 {:.1}ms max {:.1}ms",
+        ms(p.p50),
+        ms(p.p95),
+        ms(p.p99),
+        ms(p.max)
+    )
+}
+
+fn format_kib(kib: Option<u64>) -> String {
+    match kib {
+        Some(kib) => format!("{:.1} MiB", kib as f64 / 1024.0),
+        None => "unknown".into(),
+    }
+}
+
+fn format_memory(start: Option<u64>, now: Option<u64>) -> String {
+    match (start, now) {
+        (Some(start), Some(now)) => format!(
+            "  server {} ({:+.1} MiB)",
+            format_kib(Some(now)),
+            (now as f64 - start as f64) / 1024.0
+        ),
+        _ => String::new(),
+    }
+}
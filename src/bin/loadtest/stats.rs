@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+/// Counters shared by every simulated pair.
+#[derive(Default)]
+pub struct Stats {
+    pub active_pairs: AtomicU64,
+    pub games: AtomicU64,
+    relay_micros: Mutex<Vec<u64>>,
+    join_micros: Mutex<Vec<u64>>,
+    errors: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Stats {
+    /// Time from sending an action until the opponent received it.
+    pub fn relayed(&self, latency: Duration) {
+        self.relay_micros
+            .lock()
+            .unwrap()
+            .push(latency.as_micros() as u64);
+    }
+
+    /// Time to connect and seat both players of a pair.
+    pub fn joined(&self, latency: Duration) {
+        self.join_micros
+            .lock()
+            .unwrap()
+            .push(latency.as_micros() as u64);
+    }
+
+    pub fn error(&self, kind: impl Into<String>) {
+        *self.errors.lock().unwrap().entry(kind.into()).or_default() += 1;
+    }
+
+    /// Relay latencies recorded after the first `from`, and how many have
+    /// been recorded in all.
+    pub fn relay_latencies_since(&self, from: usize) -> (Percentiles, usize) {
+        let micros = self.relay_micros.lock().unwrap();
+        let recent = micros.get(from..).unwrap_or_default().to_vec();
+        (Percentiles::of(recent), micros.len())
+    }
+
+    pub fn join_latencies(&self) -> Percentiles {
+        Percentiles::of(self.join_micros.lock().unwrap().clone())
+    }
+
+    pub fn error_count(&self) -> u64 {
+        self.errors.lock().unwrap().values().sum()
+    }
+
+    pub fn errors(&self) -> BTreeMap<String, u64> {
+        self.errors.lock().unwrap().clone()
+    }
+}
+
+pub struct Percentiles {
+    pub count: usize,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Percentiles {
+    fn of(mut micros: Vec<u64>) -> Self {
+        micros.sort_unstable();
+        let at = |q: f64| {
+            let index = ((micros.len() as f64 * q).ceil() as usize).saturating_sub(1);
+            Duration::from_micros(micros.get(index).copied().unwrap_or(0))
+        };
+        Percentiles {
+            count: micros.len(),
+            p50: at(0.50),
+            p95: at(0.95),
+            p99: at(0.99),
+            max: Duration::from_micros(micros.last().copied().unwrap_or(0)),
+        }
+    }
+}
+
+/// Resident memory of a local process in KiB, read from procfs.
+pub fn resident_kib(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
@@ -8,6 +8,12 @@ pub struct Board {
     pub en_passant_target: Option<Pos>,
 }
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Board {
     pub fn new() -> Self {
         let mut board = Self {
@@ -136,10 +142,10 @@ impl Board {
                                 if target.color != piece.color {
                                     moves.push(cap);
                                 }
-                            } else if let Some(ep) = self.en_passant_target {
-                                if cap == ep {
-                                    moves.push(cap);
-                                }
+                            } else if let Some(ep) = self.en_passant_target
+                                && cap == ep
+                            {
+                                moves.push(cap);
                             }
                         }
                     }
@@ -188,26 +194,24 @@ impl Board {
                     }
                 }
                 if !attack_mode && !piece.has_moved && !self.is_in_check(piece.color) {
-                    if self.is_path_clear(pos, Pos::new(7, y)) {
-                        if let Some(rook) = self.get_piece(Pos::new(7, y)) {
-                            if rook.piece_type == PieceType::Rook && !rook.has_moved {
-                                let passing_square = Pos::new(x + 1, y);
-                                if !self.is_square_attacked(passing_square, piece.color.opposite())
-                                {
-                                    moves.push(Pos::new(x + 2, y));
-                                }
-                            }
+                    if self.is_path_clear(pos, Pos::new(7, y))
+                        && let Some(rook) = self.get_piece(Pos::new(7, y))
+                        && rook.piece_type == PieceType::Rook
+                        && !rook.has_moved
+                    {
+                        let passing_square = Pos::new(x + 1, y);
+                        if !self.is_square_attacked(passing_square, piece.color.opposite()) {
+                            moves.push(Pos::new(x + 2, y));
                         }
                     }
-                    if self.is_path_clear(pos, Pos::new(0, y)) {
-                        if let Some(rook) = self.get_piece(Pos::new(0, y)) {
-                            if rook.piece_type == PieceType::Rook && !rook.has_moved {
-                                let passing_square = Pos::new(x - 1, y);
-                                if !self.is_square_attacked(passing_square, piece.color.opposite())
-                                {
-                                    moves.push(Pos::new(x - 2, y));
-                                }
-                            }
+                    if self.is_path_clear(pos, Pos::new(0, y))
+                        && let Some(rook) = self.get_piece(Pos::new(0, y))
+                        && rook.piece_type == PieceType::Rook
+                        && !rook.has_moved
+                    {
+                        let passing_square = Pos::new(x - 1, y);
+                        if !self.is_square_attacked(passing_square, piece.color.opposite()) {
+                            moves.push(Pos::new(x - 2, y));
                         }
                     }
                 }
@@ -256,12 +260,11 @@ impl Board {
                 ];
                 for (dx, dy) in capture_offsets {
                     let target = Pos::new(x + dx, y + dy);
-                    if target.is_valid() {
-                        if let Some(p) = self.get_piece(target) {
-                            if attack_mode || p.color != piece.color {
-                                moves.push(target);
-                            }
-                        }
+                    if target.is_valid()
+                        && let Some(p) = self.get_piece(target)
+                        && (attack_mode || p.color != piece.color)
+                    {
+                        moves.push(target);
                     }
                 }
             }
@@ -392,10 +395,10 @@ impl Board {
                         if self.is_empty(curr) {
                             moves.push(curr);
                         } else {
-                            if let Some(p) = self.get_piece(curr) {
-                                if attack_mode || p.color != piece.color {
-                                    moves.push(curr);
-                                }
+                            if let Some(p) = self.get_piece(curr)
+                                && (attack_mode || p.color != piece.color)
+                            {
+                                moves.push(curr);
                             }
 
                             let mut next = Pos::new(curr.x + dx, curr.y + dy);
@@ -457,10 +460,11 @@ impl Board {
         for x in 0..8 {
             for y in 0..8 {
                 let pos = Pos::new(x, y);
-                if let Some(p) = self.get_piece(pos) {
-                    if p.piece_type == PieceType::King && p.color == color {
-                        return Some(pos);
-                    }
+                if let Some(p) = self.get_piece(pos)
+                    && p.piece_type == PieceType::King
+                    && p.color == color
+                {
+                    return Some(pos);
                 }
             }
         }
@@ -471,12 +475,11 @@ impl Board {
         for x in 0..8 {
             for y in 0..8 {
                 let pos = Pos::new(x, y);
-                if let Some(p) = self.get_piece(pos) {
-                    if p.color == by_color {
-                        if self.get_pseudo_legal_moves(pos, p, true).contains(&target) {
-                            return true;
-                        }
-                    }
+                if let Some(p) = self.get_piece(pos)
+                    && p.color == by_color
+                    && self.get_pseudo_legal_moves(pos, p, true).contains(&target)
+                {
+                    return true;
                 }
             }
         }
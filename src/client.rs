@@ -0,0 +1,424 @@
+//! Async client for the game server, shared by the desktop client, the bot
+//! and tests. A background task owns the socket and answers heartbeats; the
+//! `Client` sends messages and reads everything else as `Event`s.
+
+use crate::network::{
+    ColorPreference, Encoding, GameAction, GameMessage, Heartbeat, RatingEntry, RoomInfo,
+};
+use crate::online::HeartbeatSettings;
+use crate::tls::{ClientTls, Stream};
+use crate::types::PlayerColor;
+use futures::{SinkExt, StreamExt};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+type Transport = Framed<Box<dyn Stream>, LengthDelimitedCodec>;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    /// The server sent a frame that is not a message this client knows.
+    Decode(Box<dyn Error + Send + Sync>),
+    /// The server needs a newer protocol version than this client speaks.
+    UpgradeRequired {
+        min_version: u32,
+        message: String,
+    },
+    /// The server turned a request down.
+    Server(String),
+    /// The server answered with a message the request did not expect.
+    UnexpectedReply(&'static str),
+    /// The connection is gone.
+    Disconnected(DisconnectReason),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::Decode(e) => write!(f, "Invalid message from server: {}", e),
+            ClientError::UpgradeRequired { message, .. } => write!(f, "{}", message),
+            ClientError::Server(message) => write!(f, "{}", message),
+            ClientError::UnexpectedReply(kind) => write!(f, "Unexpected {} from server", kind),
+            ClientError::Disconnected(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    /// The server closed the connection.
+    ServerClosed,
+    /// The server stopped answering heartbeats.
+    TimedOut,
+    /// Reading from or writing to the socket failed.
+    Error(String),
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::ServerClosed => write!(f, "The server closed the connection"),
+            DisconnectReason::TimedOut => write!(f, "Connection to the server timed out"),
+            DisconnectReason::Error(e) => write!(f, "Connection error: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A message from the server other than a heartbeat.
+    Message(GameMessage),
+    /// A heartbeat was answered after this round trip time.
+    Latency(Duration),
+    /// The connection ended; no events follow.
+    Disconnected(DisconnectReason),
+}
+
+/// What the server said about itself in its `ServerHello`.
+#[derive(Debug, Clone)]
+pub struct ServerInfo {
+    pub protocol_version: u32,
+    pub server_name: String,
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JoinOptions {
+    /// Room to join or create; `None` creates a room with a generated code.
+    pub room: Option<String>,
+    pub password: Option<String>,
+    /// Seat to take when the room is created.
+    pub color: Option<ColorPreference>,
+    /// List the room in the lobby.
+    pub public: bool,
+    /// Token of a seat held before a server restart.
+    pub rejoin_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Joined {
+    pub room: String,
+    pub color: PlayerColor,
+    /// True if the room did not exist before.
+    pub created: bool,
+    /// Takes the seat back if the server restarts.
+    pub rejoin_token: Option<String>,
+}
+
+pub struct Client {
+    outgoing: mpsc::UnboundedSender<GameMessage>,
+    events: mpsc::UnboundedReceiver<Event>,
+    task: JoinHandle<()>,
+    server: ServerInfo,
+    latency: Arc<Mutex<Option<Duration>>>,
+}
+
+impl Client {
+    /// Connects and completes the `Hello` handshake. Heartbeats are sent with
+    /// `heartbeat` if given and the server supports them.
+    pub async fn connect(
+        addr: impl ToSocketAddrs,
+        client_name: &str,
+        heartbeat: Option<HeartbeatSettings>,
+    ) -> Result<Self, ClientError> {
+        let socket = TcpStream::connect(addr).await?;
+        Self::handshake(Box::new(socket), client_name, heartbeat).await
+    }
+
+    /// Like `connect`, but over TLS to `addr` ("host:port"), accepting the
+    /// server's certificate only if `tls` trusts it.
+    pub async fn connect_tls(
+        addr: &str,
+        tls: &ClientTls,
+        client_name: &str,
+        heartbeat: Option<HeartbeatSettings>,
+    ) -> Result<Self, ClientError> {
+        let socket = TcpStream::connect(addr).await?;
+        let stream = tls.connect(addr, socket).await?;
+        Self::handshake(Box::new(stream), client_name, heartbeat).await
+    }
+
+    async fn handshake(
+        socket: Box<dyn Stream>,
+        client_name: &str,
+        heartbeat: Option<HeartbeatSettings>,
+    ) -> Result<Self, ClientError> {
+        let mut transport = Framed::new(socket, LengthDelimitedCodec::new());
+        write(
+            &mut transport,
+            Encoding::Json,
+            &GameMessage::hello(client_name),
+        )
+        .await?;
+
+        let reply = match transport.next().await {
+            Some(frame) => Encoding::Json
+                .decode(&frame?)
+                .map_err(ClientError::Decode)?,
+            None => {
+                return Err(ClientError::Disconnected(DisconnectReason::ServerClosed));
+            }
+        };
+        let server = match reply {
+            GameMessage::ServerHello {
+                protocol_version,
+                server_name,
+                capabilities,
+            } => ServerInfo {
+                protocol_version,
+                server_name,
+                capabilities,
+            },
+            GameMessage::UpgradeRequired {
+                min_version,
+                message,
+            } => {
+                return Err(ClientError::UpgradeRequired {
+                    min_version,
+                    message,
+                });
+            }
+            GameMessage::Error { message } => return Err(ClientError::Server(message)),
+            other => return Err(ClientError::UnexpectedReply(other.kind())),
+        };
+
+        let heartbeat = heartbeat.filter(|_| server.capabilities.iter().any(|c| c == "heartbeat"));
+        let encoding = Encoding::negotiated(&server.capabilities);
+        let (outgoing, outgoing_rx) = mpsc::unbounded_channel();
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let latency = Arc::new(Mutex::new(None));
+        let task = tokio::spawn(run(
+            transport,
+            encoding,
+            outgoing_rx,
+            events_tx,
+            heartbeat,
+            latency.clone(),
+        ));
+        Ok(Client {
+            outgoing,
+            events,
+            task,
+            server,
+            latency,
+        })
+    }
+
+    pub fn server_info(&self) -> &ServerInfo {
+        &self.server
+    }
+
+    /// Round trip time of the last answered heartbeat.
+    pub fn latency(&self) -> Option<Duration> {
+        *self.latency.lock().unwrap()
+    }
+
+    pub fn send(&self, msg: GameMessage) -> Result<(), ClientError> {
+        self.outgoing.send(msg).map_err(|_| {
+            ClientError::Disconnected(DisconnectReason::Error("connection task stopped".into()))
+        })
+    }
+
+    pub fn send_action(&self, action: GameAction) -> Result<(), ClientError> {
+        self.send(action.to_message())
+    }
+
+    /// Waits for the next event. Returns `None` after `Disconnected`.
+    pub async fn next_event(&mut self) -> Option<Event> {
+        self.events.recv().await
+    }
+
+    /// Returns the next event if one has already arrived.
+    pub fn try_next_event(&mut self) -> Option<Event> {
+        self.events.try_recv().ok()
+    }
+
+    /// Registers a new account and returns its secret token.
+    pub async fn register(&mut self, name: &str) -> Result<String, ClientError> {
+        let name = name.to_string();
+        match self.request(GameMessage::Register { name }).await? {
+            GameMessage::Registered { token, .. } => Ok(token),
+            other => Err(ClientError::UnexpectedReply(other.kind())),
+        }
+    }
+
+    /// Logs in to an account, returning its current rating.
+    pub async fn login(&mut self, name: &str, token: &str) -> Result<RatingEntry, ClientError> {
+        let login = GameMessage::Login {
+            name: name.to_string(),
+            token: token.to_string(),
+        };
+        match self.request(login).await? {
+            GameMessage::Rating { entry } => Ok(entry),
+            other => Err(ClientError::UnexpectedReply(other.kind())),
+        }
+    }
+
+    pub async fn list_rooms(&mut self) -> Result<Vec<RoomInfo>, ClientError> {
+        match self.request(GameMessage::ListRooms).await? {
+            GameMessage::RoomList { rooms } => Ok(rooms),
+            other => Err(ClientError::UnexpectedReply(other.kind())),
+        }
+    }
+
+    /// Joins or creates a room. Actions already played in it follow as
+    /// events.
+    pub async fn join(&mut self, options: JoinOptions) -> Result<Joined, ClientError> {
+        let mut room = options.room.clone();
+        let mut created = false;
+        self.send(GameMessage::Join {
+            room: options.room,
+            password: options.password,
+            color: options.color,
+            public: options.public,
+            rejoin_token: options.rejoin_token,
+        })?;
+        loop {
+            match self.reply().await? {
+                GameMessage::RoomCode { code } => {
+                    room = Some(code);
+                    created = true;
+                }
+                GameMessage::Welcome {
+                    color,
+                    rejoin_token,
+                } => {
+                    return Ok(Joined {
+                        room: room.unwrap_or_default(),
+                        color,
+                        created,
+                        rejoin_token,
+                    });
+                }
+                other => return Err(ClientError::UnexpectedReply(other.kind())),
+            }
+        }
+    }
+
+    /// Closes the connection and waits for the background task to finish.
+    pub async fn disconnect(self) {
+        drop(self.outgoing);
+        self.task.await.ok();
+    }
+
+    async fn request(&mut self, msg: GameMessage) -> Result<GameMessage, ClientError> {
+        self.send(msg)?;
+        self.reply().await
+    }
+
+    /// The next message from the server, turning `Error` into `Err`.
+    async fn reply(&mut self) -> Result<GameMessage, ClientError> {
+        loop {
+            match self.next_event().await {
+                Some(Event::Message(GameMessage::Error { message })) => {
+                    return Err(ClientError::Server(message));
+                }
+                Some(Event::Message(msg)) => return Ok(msg),
+                Some(Event::Latency(_)) => {}
+                Some(Event::Disconnected(reason)) => {
+                    return Err(ClientError::Disconnected(reason));
+                }
+                None => return Err(ClientError::Disconnected(DisconnectReason::ServerClosed)),
+            }
+        }
+    }
+}
+
+async fn write(
+    transport: &mut Transport,
+    encoding: Encoding,
+    msg: &GameMessage,
+) -> Result<(), ClientError> {
+    let bytes = encoding.encode(msg).map_err(io::Error::other)?;
+    transport.send(bytes.into()).await?;
+    Ok(())
+}
+
+/// Owns the socket: writes outgoing messages, answers pings, sends our own
+/// heartbeats and forwards everything else. Ends with a `Disconnected` event
+/// unless the `Client` was dropped or disconnected.
+async fn run(
+    mut transport: Transport,
+    encoding: Encoding,
+    mut outgoing: mpsc::UnboundedReceiver<GameMessage>,
+    events: mpsc::UnboundedSender<Event>,
+    heartbeat_settings: Option<HeartbeatSettings>,
+    latency: Arc<Mutex<Option<Duration>>>,
+) {
+    let mut heartbeat = heartbeat_settings.map(|s| Heartbeat::new(s.max_missed));
+    // Without heartbeats the timer is never polled, so any period will do.
+    let interval = heartbeat_settings.map_or(Duration::from_secs(1), |s| s.interval);
+    let mut heartbeat_timer =
+        tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+
+    let reason = loop {
+        tokio::select! {
+            msg = outgoing.recv() => {
+                let Some(msg) = msg else {
+                    transport.close().await.ok();
+                    return;
+                };
+                if let Err(e) = write(&mut transport, encoding, &msg).await {
+                    break DisconnectReason::Error(e.to_string());
+                }
+            }
+            _ = heartbeat_timer.tick(), if heartbeat.is_some() => {
+                let Some(hb) = heartbeat.as_mut() else { continue };
+                let Some(ping) = hb.tick() else {
+                    break DisconnectReason::TimedOut;
+                };
+                if let Err(e) = write(&mut transport, encoding, &ping).await {
+                    break DisconnectReason::Error(e.to_string());
+                }
+            }
+            frame = transport.next() => {
+                let bytes = match frame {
+                    Some(Ok(bytes)) => bytes,
+                    Some(Err(e)) => break DisconnectReason::Error(e.to_string()),
+                    None => break DisconnectReason::ServerClosed,
+                };
+                // Newer servers may send messages this build does not know; skip them.
+                let Ok(msg) = encoding.decode(&bytes) else {
+                    continue;
+                };
+                let event = match msg {
+                    GameMessage::Ping { nonce } => {
+                        if let Err(e) = write(&mut transport, encoding, &GameMessage::Pong { nonce }).await {
+                            break DisconnectReason::Error(e.to_string());
+                        }
+                        continue;
+                    }
+                    GameMessage::Pong { nonce } => {
+                        let Some(hb) = heartbeat.as_mut() else { continue };
+                        hb.pong(nonce);
+                        let Some(rtt) = hb.latency() else { continue };
+                        *latency.lock().unwrap() = Some(rtt);
+                        Event::Latency(rtt)
+                    }
+                    msg => Event::Message(msg),
+                };
+                if events.send(event).is_err() {
+                    // Nobody is listening any more.
+                    return;
+                }
+            }
+        }
+    };
+    events.send(Event::Disconnected(reason)).ok();
+}
@@ -11,6 +11,12 @@ pub const COST_ARCHBISHOP: i32 = 7;
 pub const COST_CANNON: i32 = 8;
 pub const COST_MONARCH: i32 = 12;
 
+pub const LEADERBOARD_SIZE: usize = 8;
+pub const LOBBY_REFRESH_SECS: f64 = 3.0;
+pub const STANDINGS_REFRESH_SECS: f64 = 5.0;
+pub const CORRESPONDENCE_REFRESH_SECS: f64 = 10.0;
+pub const REJOIN_RETRY_SECS: f64 = 2.0;
+
 pub const VAL_PAWN: i32 = 1;
 pub const VAL_KNIGHT: i32 = 3;
 pub const VAL_BISHOP: i32 = 3;
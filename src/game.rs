@@ -1,5 +1,6 @@
 use crate::board::*;
 use crate::types::*;
+use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum TurnPhase {
@@ -9,7 +10,7 @@ pub enum TurnPhase {
     GameOver,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum DrawReason {
     Stalemate,
     ThreeFoldRepetition,
@@ -17,7 +18,7 @@ pub enum DrawReason {
     FiftyMoveRule,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum GameResult {
     Win(PlayerColor),
     Draw(DrawReason),
@@ -37,11 +38,16 @@ pub struct Game {
     pub half_move_clock: u32,
 }
 
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Game {
     pub fn new() -> Self {
         let board = Board::new();
-        let mut history = Vec::new();
-        history.push(board.clone());
+        let history = vec![board.clone()];
 
         Self {
             board,
@@ -80,19 +86,19 @@ impl Game {
             return;
         }
 
-        if self.legal_moves.contains(&pos) {
-            if let Some(from) = self.selected_pos {
-                self.make_move(from, pos);
-                return;
-            }
+        if self.legal_moves.contains(&pos)
+            && let Some(from) = self.selected_pos
+        {
+            self.make_move(from, pos);
+            return;
         }
 
         if let Some(piece) = self.board.get_piece(pos) {
             if piece.color == self.turn {
-                if let TurnPhase::PostUpgrade(upgraded_pos) = self.phase {
-                    if pos == upgraded_pos {
-                        return;
-                    }
+                if let TurnPhase::PostUpgrade(upgraded_pos) = self.phase
+                    && pos == upgraded_pos
+                {
+                    return;
                 }
 
                 self.selected_pos = Some(pos);
@@ -107,6 +113,36 @@ impl Game {
         }
     }
 
+    pub fn is_legal_move(&self, from: Pos, to: Pos) -> bool {
+        match self.phase {
+            TurnPhase::Normal => {}
+            TurnPhase::PostUpgrade(upgraded_pos) if upgraded_pos != from => {}
+            _ => return false,
+        }
+
+        match self.board.get_piece(from) {
+            Some(piece) if piece.color == self.turn => {
+                self.board.get_legal_moves(from).contains(&to)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn can_promote_to(&self, piece_type: PieceType) -> bool {
+        match self.phase {
+            TurnPhase::Promoting(_, is_hawk) => match piece_type {
+                PieceType::Queen | PieceType::Rook | PieceType::Bishop | PieceType::Knight => true,
+                PieceType::Hawk
+                | PieceType::Elephant
+                | PieceType::Archbishop
+                | PieceType::Cannon
+                | PieceType::Monarch => is_hawk,
+                PieceType::King | PieceType::Pawn => false,
+            },
+            _ => false,
+        }
+    }
+
     pub fn make_move(&mut self, from: Pos, to: Pos) {
         let piece = self.board.get_piece(from).unwrap();
         let target = self.board.get_piece(to);
@@ -252,13 +288,12 @@ impl Game {
         for x in 0..8 {
             for y in 0..8 {
                 let pos = Pos::new(x, y);
-                if let Some(p) = self.board.get_piece(pos) {
-                    if p.color == self.turn {
-                        if !self.board.get_legal_moves(pos).is_empty() {
-                            can_move = true;
-                            break;
-                        }
-                    }
+                if let Some(p) = self.board.get_piece(pos)
+                    && p.color == self.turn
+                    && !self.board.get_legal_moves(pos).is_empty()
+                {
+                    can_move = true;
+                    break;
                 }
             }
             if can_move {
@@ -285,31 +320,30 @@ impl Game {
             return;
         }
 
-        if let Some(mut piece) = self.board.get_piece(pos) {
-            if piece.color == self.turn {
-                if let Some(cost) = piece.upgrade_cost() {
-                    let points = if self.turn == PlayerColor::White {
-                        self.white_points
-                    } else {
-                        self.black_points
-                    };
-                    if points >= cost {
-                        if let Some(new_type) = piece.upgraded_type() {
-                            if self.turn == PlayerColor::White {
-                                self.white_points -= cost;
-                            } else {
-                                self.black_points -= cost;
-                            }
-
-                            piece.piece_type = new_type;
-                            self.board.set_piece(pos, Some(piece));
-
-                            self.phase = TurnPhase::PostUpgrade(pos);
-                            self.selected_pos = None;
-                            self.legal_moves.clear();
-                        }
-                    }
+        if let Some(mut piece) = self.board.get_piece(pos)
+            && piece.color == self.turn
+            && let Some(cost) = piece.upgrade_cost()
+        {
+            let points = if self.turn == PlayerColor::White {
+                self.white_points
+            } else {
+                self.black_points
+            };
+            if points >= cost
+                && let Some(new_type) = piece.upgraded_type()
+            {
+                if self.turn == PlayerColor::White {
+                    self.white_points -= cost;
+                } else {
+                    self.black_points -= cost;
                 }
+
+                piece.piece_type = new_type;
+                self.board.set_piece(pos, Some(piece));
+
+                self.phase = TurnPhase::PostUpgrade(pos);
+                self.selected_pos = None;
+                self.legal_moves.clear();
             }
         }
     }
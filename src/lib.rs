@@ -1,8 +1,17 @@
 pub mod assets;
 pub mod board;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod client;
 pub mod constants;
 pub mod game;
 pub mod network;
+pub mod online;
 pub mod piece;
 pub mod renderer;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod server;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod strategy;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod tls;
 pub mod types;
@@ -1,29 +1,113 @@
 use ascension_chess::assets::*;
 use ascension_chess::constants::*;
 use ascension_chess::game::*;
-use ascension_chess::network::GameMessage;
-use ascension_chess::renderer;
+use ascension_chess::network::{
+    ColorPreference, CorrespondenceInfo, GameAction, GameMessage, MatchScore, RatingEntry,
+    RoomInfo, TournamentFormat, TournamentStandings,
+};
+use ascension_chess::online::{self, Connection, HeartbeatSettings, LanSearch, TlsMode};
+use ascension_chess::renderer::{self, LanRow, RematchState, TakebackState};
 use ascension_chess::types::*;
-use clap::Parser;
-use futures::{SinkExt, StreamExt};
+use clap::{ArgGroup, Parser};
 use macroquad::prelude::*;
-use std::sync::mpsc;
-use std::thread;
-use tokio::net::TcpStream;
-use tokio::runtime::Runtime;
-use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(group(ArgGroup::new("online_room").args(["room", "create", "lan", "host"]).multiple(true)))]
 struct Args {
+    /// Room code or name to join; the room is created if it does not exist
     #[arg(short, long)]
+    room: Option<String>,
+
+    /// Password protecting the room; needs --room, --create, --lan or --host
+    /// (it used to be the room name, which is now --room)
+    #[arg(short, long, requires = "online_room")]
     password: Option<String>,
 
+    /// Create a new room with a generated code
     #[arg(short, long)]
     create: bool,
 
+    /// Color to play when creating a room: white, black or random
+    #[arg(long, default_value = "random")]
+    color: ColorPreference,
+
+    /// List a room created with --create or --room in the lobby
+    #[arg(long)]
+    public: bool,
+
+    /// Browse public rooms and pick one to join
+    #[arg(long, conflicts_with_all = ["room", "create"])]
+    lobby: bool,
+
+    /// Run a server inside this client on the given port (8080 if omitted)
+    /// and play in a room on it; the opponent joins with --lan or --server
+    #[arg(long, num_args = 0..=1, default_missing_value = "8080", conflicts_with_all = ["create", "lobby", "lan", "server", "tournament", "correspondence"])]
+    host: Option<u16>,
+
+    /// Find servers on the local network, then create a room on one or join
+    /// one of their open rooms
+    #[arg(long, conflicts_with_all = ["room", "create", "lobby", "server"])]
+    lan: bool,
+
+    /// Register for a tournament and play each of your pairings as they come up
+    #[arg(long, requires_all = ["name", "token"], conflicts_with_all = ["room", "create", "lobby", "lan"])]
+    tournament: Option<String>,
+
+    /// Create the tournament given by --tournament: round-robin or swiss:<rounds>
+    #[arg(long, requires = "tournament")]
+    tournament_format: Option<TournamentFormat>,
+
+    /// Close registration and pair the first round of a tournament you organize
+    #[arg(long, requires = "tournament")]
+    start_tournament: bool,
+
+    /// List your correspondence games and pick one to play
+    #[arg(long, requires_all = ["name", "token"], conflicts_with_all = ["room", "create", "lobby", "lan", "tournament"])]
+    correspondence: bool,
+
+    /// Challenge this account to a correspondence game, playing --color
+    #[arg(long, requires = "correspondence")]
+    challenge: Option<String>,
+
+    /// Days each player gets per move in a game started with --challenge
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..=30))]
+    days_per_move: u32,
+
     #[arg(long, default_value = "127.0.0.1:8080")]
     server: String,
+
+    /// Connect to the server over TLS, trusting the public certificate authorities
+    #[arg(long, conflicts_with = "host")]
+    tls: bool,
+
+    /// Connect over TLS, trusting only the certificate in this PEM file (for
+    /// a server with a self-signed certificate)
+    #[arg(long, conflicts_with = "host")]
+    trust_cert: Option<PathBuf>,
+
+    /// Account name for rated games
+    #[arg(long)]
+    name: Option<String>,
+
+    /// Secret token of the account given by --name
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Register --name as a new account and print its token
+    #[arg(long, requires = "name")]
+    register: bool,
+
+    /// Seconds between heartbeats sent to the server
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    heartbeat_interval: u64,
+
+    /// Unanswered heartbeats in a row before the connection is considered lost
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    max_missed_heartbeats: u32,
 }
 
 #[macroquad::main("Ascension Chess")]
@@ -34,101 +118,466 @@ async fn main() {
 
     request_new_screen_size(1280.0, 720.0);
 
-    let mut is_online = false;
+    let mut is_online = args.lan;
     let mut my_color = PlayerColor::White;
     let mut flipped = false;
     let mut connected = false;
+    let mut my_rating: Option<RatingEntry> = None;
+    let mut leaderboard: Vec<RatingEntry> = Vec::new();
+    let mut connection_error: Option<String> = None;
+    let mut opponent_left = false;
+    let mut server_restarting = false;
+    let mut match_score = MatchScore::default();
+    let mut rematch = RematchState::Available;
+    let mut takeback = TakebackState::Available;
+    let mut in_lobby = args.lobby;
+    let mut lobby_rooms: Vec<RoomInfo> = Vec::new();
+    let mut last_lobby_refresh = f64::NEG_INFINITY;
+    let mut in_tournament = args.tournament.is_some();
+    let mut standings: Option<TournamentStandings> = None;
+    let mut tournament_room: Option<String> = None;
+    let mut last_standings_refresh = f64::NEG_INFINITY;
+    let mut in_correspondence = args.correspondence;
+    let mut correspondence_games: Vec<CorrespondenceInfo> = Vec::new();
+    let mut playing_correspondence = false;
+    let mut last_correspondence_refresh = f64::NEG_INFINITY;
+    let mut lan_search = args.lan.then(LanSearch::start);
+    // Join message that takes our seat back once a restarting server is up.
+    let mut rejoin: Option<GameMessage> = None;
+    let mut last_rejoin_attempt = f64::NEG_INFINITY;
 
-    let (game_tx, game_rx) = mpsc::channel::<GameMessage>();
-    let (net_tx, mut net_rx_tokio) = tokio::sync::mpsc::unbounded_channel::<GameMessage>();
+    let mut account_msg = match (args.name.clone(), args.token.clone()) {
+        (Some(name), _) if args.register => Some(GameMessage::Register { name }),
+        (Some(name), Some(token)) => Some(GameMessage::Login { name, token }),
+        _ => None,
+    };
+    let heartbeat_settings = HeartbeatSettings {
+        interval: Duration::from_secs(args.heartbeat_interval),
+        max_missed: args.max_missed_heartbeats,
+    };
+    let tls = match &args.trust_cert {
+        Some(cert) => TlsMode::Pinned(cert.clone()),
+        None if args.tls => TlsMode::Public,
+        None => TlsMode::Off,
+    };
 
-    if args.password.is_some() || args.create {
-        is_online = true;
-        let server_addr = args.server.clone();
-        let password = args.password.clone();
-
-        thread::spawn(move || {
-            let rt = Runtime::new().unwrap();
-            rt.block_on(async {
-                match TcpStream::connect(server_addr).await {
-                    Ok(socket) => {
-                        let mut framed = Framed::new(socket, LengthDelimitedCodec::new());
-                        let join_msg = GameMessage::Join { room: password };
-                        let bytes = serde_json::to_vec(&join_msg).unwrap();
-                        if let Err(_) = framed.send(bytes.into()).await {
-                             game_tx.send(GameMessage::Error { message: "Failed to send join".into() }).ok();
-                             return;
-                        }
+    let hosted = args.host.map(|port| {
+        let display_name = match &args.name {
+            Some(name) => format!("{}'s game", name),
+            None => "Hosted game".to_string(),
+        };
+        match online::host(SocketAddr::from(([0, 0, 0, 0], port)), &display_name) {
+            Ok(hosted) => {
+                println!(
+                    "Hosting on port {}. Your opponent can find the game with --lan, or join with --server <this computer's address>:{} --room <room code>.",
+                    port, port
+                );
+                hosted
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    });
+    let server_addr = match args.host {
+        Some(port) => format!("127.0.0.1:{}", port),
+        None => args.server.clone(),
+    };
+    let mut current_server = (server_addr.clone(), tls.clone());
 
-                        loop {
-                            tokio::select! {
-                                Some(msg) = net_rx_tokio.recv() => {
-                                    if let Ok(bytes) = serde_json::to_vec(&msg) {
-                                        framed.send(bytes.into()).await.ok();
-                                    }
-                                }
-                                result = framed.next() => {
-                                    match result {
-                                        Some(Ok(bytes)) => {
-                                            if let Ok(msg) = serde_json::from_slice::<GameMessage>(&bytes) {
-                                                if let GameMessage::RoomCode { code } = &msg {
-                                                    println!("Room Code: {}", code);
-                                                }
-                                                game_tx.send(msg).ok();
-                                            }
-                                        }
-                                        _ => {
-                                            game_tx.send(GameMessage::OpponentDisconnected).ok();
-                                            break;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        game_tx.send(GameMessage::Error { message: e.to_string() }).ok();
-                    }
-                }
-            });
+    let mut connection = if args.room.is_some()
+        || args.create
+        || args.lobby
+        || hosted.is_some()
+        || in_tournament
+        || in_correspondence
+    {
+        is_online = true;
+        let picks_room_later = args.lobby || in_tournament || in_correspondence;
+        let join_msg = (!picks_room_later).then(|| GameMessage::Join {
+            room: args.room.clone(),
+            password: args.password.clone(),
+            color: Some(args.color),
+            // A hosted room is listed so the opponent can pick it with --lan.
+            public: args.public || hosted.is_some(),
+            rejoin_token: None,
         });
+        Some(online::connect(
+            server_addr.clone(),
+            tls.clone(),
+            account_msg.clone(),
+            join_msg,
+            heartbeat_settings,
+        ))
     } else {
-        connected = true;
+        connected = !args.lan;
+        None
+    };
+    if let (Some(opponent), Some(conn)) = (&args.challenge, &connection) {
+        conn.send(GameMessage::CreateCorrespondence {
+            opponent: opponent.clone(),
+            days_per_move: args.days_per_move,
+            color: Some(args.color),
+        });
+    }
+    if let (Some(name), Some(conn)) = (&args.tournament, &connection) {
+        if let Some(format) = args.tournament_format {
+            conn.send(GameMessage::CreateTournament {
+                name: name.clone(),
+                format,
+            });
+        }
+        if args.start_tournament {
+            conn.send(GameMessage::StartTournament { name: name.clone() });
+        } else {
+            conn.send(GameMessage::JoinTournament { name: name.clone() });
+        }
     }
 
     loop {
         let board_offset_x = (screen_width() - SQUARE_SIZE * 8.0) / 2.0;
         let board_offset_y = (screen_height() - SQUARE_SIZE * 8.0) / 2.0;
 
-        while let Ok(msg) = game_rx.try_recv() {
+        while let Some(msg) = connection.as_ref().and_then(Connection::try_recv) {
             match msg {
-                GameMessage::Welcome { color } => {
+                GameMessage::RoomCode { code } => {
+                    println!("Room Code: {}", code);
+                }
+                GameMessage::Registered { name, token } => {
+                    println!("Registered account {}. Token: {}", name, token);
+                    println!("Keep this token secret; pass it with --name and --token to log in.");
+                    // Log in rather than register again when rejoining.
+                    account_msg = Some(GameMessage::Login { name, token });
+                }
+                GameMessage::Welcome { color, .. } => {
                     my_color = color;
                     flipped = color == PlayerColor::Black;
                     connected = true;
+                    rejoin = None;
+                    server_restarting = false;
+                    opponent_left = false;
+                    if let (Some(name), Some(conn)) = (&args.name, &connection) {
+                        conn.send(GameMessage::QueryRating { name: name.clone() });
+                    }
                 }
                 GameMessage::Move { from, to } => {
                     game.make_move(from, to);
+                    takeback = TakebackState::Available;
                 }
                 GameMessage::Upgrade { pos } => {
                     game.attempt_upgrade(pos);
+                    takeback = TakebackState::Available;
                 }
                 GameMessage::Promote { piece_type } => {
                     game.resolve_promotion(piece_type);
+                    takeback = TakebackState::Available;
+                }
+                GameMessage::RequestTakeback => {
+                    takeback = TakebackState::Received;
+                }
+                GameMessage::DeclineTakeback => {
+                    println!("Your opponent declined the take-back");
+                    takeback = TakebackState::Available;
+                }
+                GameMessage::TakenBack { actions } => {
+                    game = GameAction::replay(&actions);
+                    takeback = TakebackState::Available;
+                }
+                GameMessage::UpgradeRequired { message, .. } => {
+                    println!("Error: {}", message);
+                    connection_error = Some(message);
                 }
                 GameMessage::Error { message } => {
                     println!("Error: {}", message);
+                    // A refused take-back request or answer leaves none pending.
+                    takeback = TakebackState::Available;
+                    if !connected {
+                        connection_error = Some(message);
+                    }
+                }
+                GameMessage::OfferRematch => {
+                    rematch = RematchState::Received;
+                }
+                GameMessage::RematchStarted { color, score } => {
+                    game = Game::new();
+                    my_color = color;
+                    flipped = color == PlayerColor::Black;
+                    match_score = score;
+                    rematch = RematchState::Available;
+                    takeback = TakebackState::Available;
+                }
+                GameMessage::ServerRestarting {
+                    message,
+                    room,
+                    rejoin_token,
+                } => {
+                    println!("{}", message);
+                    server_restarting = true;
+                    rejoin = room.map(|room| GameMessage::Join {
+                        room: Some(room),
+                        password: args.password.clone(),
+                        color: None,
+                        public: false,
+                        rejoin_token,
+                    });
                 }
                 GameMessage::OpponentDisconnected => {
                     println!("Opponent disconnected");
+                    opponent_left = true;
+                    takeback = TakebackState::Available;
+                }
+                GameMessage::Rating { entry } if args.name.as_ref() == Some(&entry.name) => {
+                    my_rating = Some(entry);
+                }
+                GameMessage::Leaderboard { entries } => {
+                    leaderboard = entries;
+                }
+                GameMessage::RoomList { rooms } => {
+                    lobby_rooms = rooms;
+                }
+                GameMessage::Standings { standings: latest } => {
+                    let progressed = standings
+                        .as_ref()
+                        .is_none_or(|s| (s.round, s.finished) != (latest.round, latest.finished));
+                    if progressed {
+                        print_standings(&latest);
+                    }
+                    standings = Some(latest);
+                }
+                GameMessage::CorrespondenceGames { games } => {
+                    if let Some(name) = &args.name {
+                        for info in games.iter().filter(|info| info.awaits_answer_from(name)) {
+                            let seen = correspondence_games.iter().any(|old| old.room == info.room);
+                            if !seen {
+                                println!(
+                                    "{} challenged you to a correspondence game in room {}",
+                                    info.challenger.as_deref().unwrap_or_default(),
+                                    info.room
+                                );
+                            }
+                        }
+                        for info in games.iter().filter(|info| info.is_turn_of(name)) {
+                            let seen = correspondence_games
+                                .iter()
+                                .any(|old| old.room == info.room && old.is_turn_of(name));
+                            if !seen {
+                                let opponent = if &info.white == name {
+                                    &info.black
+                                } else {
+                                    &info.white
+                                };
+                                println!("Your move against {} in room {}", opponent, info.room);
+                            }
+                        }
+                    }
+                    correspondence_games = games;
+                }
+                GameMessage::TimeForfeit { color } => {
+                    println!("{:?} ran out of time", color);
+                    game.result = Some(GameResult::Win(color.opposite()));
                 }
                 _ => {}
             }
         }
 
+        // Once the restarting server has closed the connection, keep trying
+        // to take our seat back.
+        if let Some(join) = &rejoin
+            && connection.as_ref().is_some_and(Connection::is_closed)
+            && get_time() - last_rejoin_attempt >= REJOIN_RETRY_SECS
+        {
+            connection = Some(online::connect(
+                current_server.0.clone(),
+                current_server.1.clone(),
+                account_msg.clone(),
+                Some(join.clone()),
+                heartbeat_settings,
+            ));
+            last_rejoin_attempt = get_time();
+            // The server replays the game from the start.
+            game = Game::new();
+            takeback = TakebackState::Available;
+        }
+
+        if in_lobby && let Some(conn) = &connection {
+            if get_time() - last_lobby_refresh >= LOBBY_REFRESH_SECS {
+                conn.send(GameMessage::ListRooms);
+                last_lobby_refresh = get_time();
+            }
+            if is_mouse_button_pressed(MouseButton::Left) {
+                let mouse = mouse_position().into();
+                let picked =
+                    (0..lobby_rooms.len()).find(|&i| renderer::lobby_row_rect(i).contains(mouse));
+                if let Some(i) = picked {
+                    conn.send(GameMessage::Join {
+                        room: Some(lobby_rooms[i].code.clone()),
+                        password: None,
+                        color: None,
+                        public: false,
+                        rejoin_token: None,
+                    });
+                    in_lobby = false;
+                }
+            }
+            renderer::draw_lobby(&lobby_rooms, connection_error.as_deref());
+            next_frame().await;
+            continue;
+        }
+
+        if let Some(search) = &mut lan_search {
+            let rows = renderer::lan_rows(search.servers());
+            let mouse = mouse_position().into();
+            let picked = (0..rows.len()).find(|&i| renderer::lobby_row_rect(i).contains(mouse));
+            let join = match picked.map(|i| &rows[i]) {
+                Some(LanRow::Server(server)) if is_mouse_button_pressed(MouseButton::Left) => {
+                    Some((server, None))
+                }
+                Some(LanRow::Room(server, room)) if is_mouse_button_pressed(MouseButton::Left) => {
+                    Some((server, Some(room.code.clone())))
+                }
+                _ => None,
+            };
+            if let Some((server, room)) = join {
+                let addr = server.addr;
+                // Follow what the server announces; --trust-cert still picks
+                // which certificate to accept.
+                let lan_tls = match &tls {
+                    _ if !server.announcement.tls => TlsMode::Off,
+                    TlsMode::Off => TlsMode::Public,
+                    mode => mode.clone(),
+                };
+                let creating = room.is_none();
+                connection = Some(online::connect(
+                    addr.to_string(),
+                    lan_tls.clone(),
+                    account_msg.clone(),
+                    Some(GameMessage::Join {
+                        room,
+                        password: args.password.clone().filter(|_| creating),
+                        color: Some(args.color),
+                        public: args.public && creating,
+                        rejoin_token: None,
+                    }),
+                    heartbeat_settings,
+                ));
+                current_server = (addr.to_string(), lan_tls);
+                lan_search = None;
+            } else {
+                renderer::draw_lan_servers(&rows, connection_error.as_deref());
+                next_frame().await;
+            }
+            continue;
+        }
+
+        if in_correspondence && let Some(conn) = &connection {
+            if get_time() - last_correspondence_refresh >= CORRESPONDENCE_REFRESH_SECS {
+                conn.send(GameMessage::QueryCorrespondence {
+                    my_turn_only: false,
+                });
+                last_correspondence_refresh = get_time();
+            }
+            let name = args.name.as_deref().unwrap_or_default();
+            let clicked = |button| {
+                let mouse = mouse_position().into();
+                (0..correspondence_games.len())
+                    .find(|&i| renderer::lobby_row_rect(i).contains(mouse))
+                    .filter(|_| is_mouse_button_pressed(button))
+                    .map(|i| &correspondence_games[i])
+            };
+            if let Some(picked) = clicked(MouseButton::Left) {
+                let room = picked.room.clone();
+                if picked.awaits_answer_from(name) {
+                    conn.send(GameMessage::AcceptCorrespondence { room });
+                    // The reply lists only the accepted game; fetch the rest.
+                    last_correspondence_refresh = f64::NEG_INFINITY;
+                } else if picked.challenger.is_none() {
+                    conn.send(GameMessage::Join {
+                        room: Some(room),
+                        password: None,
+                        color: None,
+                        public: false,
+                        rejoin_token: None,
+                    });
+                    in_correspondence = false;
+                    playing_correspondence = true;
+                }
+            } else if let Some(picked) = clicked(MouseButton::Right)
+                && picked.challenger.is_some()
+            {
+                conn.send(GameMessage::DeclineCorrespondence {
+                    room: picked.room.clone(),
+                });
+            }
+            renderer::draw_correspondence(
+                &correspondence_games,
+                args.name.as_deref().unwrap_or_default(),
+                macroquad::miniquad::date::now() as u64,
+                connection_error.as_deref(),
+            );
+            next_frame().await;
+            continue;
+        }
+
+        if let Some(name) = &args.tournament
+            && (in_tournament || game.result.is_some())
+            && get_time() - last_standings_refresh >= STANDINGS_REFRESH_SECS
+            && let Some(conn) = &connection
+        {
+            conn.send(GameMessage::QueryStandings { name: name.clone() });
+            last_standings_refresh = get_time();
+        }
+
+        // Once a tournament game is over, move on to the next pairing on a
+        // fresh connection.
+        let next_pairing = standings
+            .as_ref()
+            .zip(args.name.as_deref())
+            .and_then(|(s, name)| s.pending_pairing(name))
+            .filter(|p| tournament_room.as_ref() != Some(&p.room));
+        if let Some(pairing) = next_pairing
+            && (in_tournament || game.result.is_some())
+        {
+            let join_msg = GameMessage::Join {
+                room: Some(pairing.room.clone()),
+                password: None,
+                color: None,
+                public: false,
+                rejoin_token: None,
+            };
+            if in_tournament && let Some(conn) = &connection {
+                conn.send(join_msg);
+            } else {
+                connection = Some(online::connect(
+                    args.server.clone(),
+                    tls.clone(),
+                    account_msg.clone(),
+                    Some(join_msg),
+                    heartbeat_settings,
+                ));
+                game = Game::new();
+                connected = false;
+                opponent_left = false;
+                server_restarting = false;
+                match_score = MatchScore::default();
+                takeback = TakebackState::Available;
+            }
+            tournament_room = Some(pairing.room.clone());
+            in_tournament = false;
+        }
+
+        if in_tournament {
+            renderer::draw_standings(standings.as_ref(), connection_error.as_deref());
+            next_frame().await;
+            continue;
+        }
+
         if !connected && is_online {
             clear_background(LIGHTGRAY);
             draw_text("Connecting...", 100.0, 100.0, 40.0, BLACK);
+            if let Some(message) = &connection_error {
+                draw_text(message, 100.0, 150.0, 25.0, RED);
+            }
             next_frame().await;
             continue;
         }
@@ -147,7 +596,7 @@ async fn main() {
             let pos = Pos::new(bx, by);
             if pos.is_valid() {
                 let prev_selected = game.selected_pos;
-                let is_move = if let Some(_) = prev_selected {
+                let is_move = if prev_selected.is_some() {
                     game.legal_moves.contains(&pos)
                 } else {
                     false
@@ -155,26 +604,28 @@ async fn main() {
 
                 game.select_square(pos);
 
-                if is_move {
-                    if let Some(from) = prev_selected {
-                        if is_online {
-                            net_tx.send(GameMessage::Move { from, to: pos }).ok();
-                        }
-                    }
+                if is_move
+                    && let Some(from) = prev_selected
+                    && let Some(conn) = &connection
+                {
+                    conn.send(GameMessage::Move { from, to: pos });
+                    // Playing on instead of answering turns a take-back request down.
+                    takeback = TakebackState::Available;
                 }
             }
         }
 
-        if is_key_pressed(KeyCode::U) && can_play {
-            if let Some(pos) = game.selected_pos {
-                game.attempt_upgrade(pos);
-                if let TurnPhase::PostUpgrade(p) = game.phase {
-                    if p == pos {
-                        if is_online {
-                            net_tx.send(GameMessage::Upgrade { pos }).ok();
-                        }
-                    }
-                }
+        if is_key_pressed(KeyCode::U)
+            && can_play
+            && let Some(pos) = game.selected_pos
+        {
+            game.attempt_upgrade(pos);
+            if let TurnPhase::PostUpgrade(p) = game.phase
+                && p == pos
+                && let Some(conn) = &connection
+            {
+                conn.send(GameMessage::Upgrade { pos });
+                takeback = TakebackState::Available;
             }
         }
 
@@ -209,14 +660,133 @@ async fn main() {
 
             if let Some(pt) = promoted_type {
                 game.resolve_promotion(pt);
-                if is_online {
-                    net_tx.send(GameMessage::Promote { piece_type: pt }).ok();
+                if let Some(conn) = &connection {
+                    conn.send(GameMessage::Promote { piece_type: pt });
+                    takeback = TakebackState::Available;
                 }
             }
         }
 
+        let rematch_allowed = tournament_room.is_none() && !playing_correspondence;
+        if game.result.is_some()
+            && rematch_allowed
+            && is_mouse_button_pressed(MouseButton::Left)
+            && let Some(conn) = &connection
+            && renderer::rematch_button_rect(board_offset_x, board_offset_y)
+                .contains(mouse_position().into())
+        {
+            match rematch {
+                RematchState::Available => {
+                    conn.send(GameMessage::OfferRematch);
+                    rematch = RematchState::Offered;
+                }
+                RematchState::Received => conn.send(GameMessage::AcceptRematch),
+                RematchState::Offered => {}
+            }
+        }
+
+        // Your last turn can be taken back until your opponent finishes theirs.
+        let show_takeback = is_online
+            && connected
+            && rematch_allowed
+            && game.result.is_none()
+            && (takeback != TakebackState::Available
+                || (game.turn != my_color && game.history.len() > 1));
+        if show_takeback
+            && is_mouse_button_pressed(MouseButton::Left)
+            && let Some(conn) = &connection
+        {
+            let mouse = mouse_position().into();
+            if renderer::takeback_button_rect(board_offset_x, board_offset_y).contains(mouse) {
+                match takeback {
+                    TakebackState::Available => {
+                        conn.send(GameMessage::RequestTakeback);
+                        takeback = TakebackState::Requested;
+                    }
+                    TakebackState::Received => conn.send(GameMessage::AcceptTakeback),
+                    TakebackState::Requested => {}
+                }
+            } else if takeback == TakebackState::Received
+                && renderer::decline_takeback_button_rect(board_offset_x, board_offset_y)
+                    .contains(mouse)
+            {
+                conn.send(GameMessage::DeclineTakeback);
+                takeback = TakebackState::Available;
+            }
+        }
+
+        if is_key_pressed(KeyCode::L)
+            && let Some(conn) = &connection
+        {
+            if leaderboard.is_empty() {
+                conn.send(GameMessage::QueryLeaderboard {
+                    limit: LEADERBOARD_SIZE,
+                });
+            } else {
+                leaderboard.clear();
+            }
+        }
+
         renderer::draw_game(&game, &assets, flipped, board_offset_x, board_offset_y);
+        if let Some(conn) = &connection {
+            let notice = if server_restarting && rejoin.is_some() {
+                Some("Server restarting, rejoining the room")
+            } else if server_restarting {
+                Some("Server restarting, rejoin the room")
+            } else if conn.is_closed() {
+                Some("Connection lost")
+            } else if opponent_left {
+                Some("Opponent disconnected")
+            } else {
+                None
+            };
+            renderer::draw_connection_status(
+                conn.latency(),
+                notice,
+                board_offset_x,
+                board_offset_y,
+            );
+        }
+        if is_online {
+            // The score from the server covers finished games; count this one once it ends.
+            let mut shown_score = match_score;
+            if let Some(result) = game.result {
+                shown_score.record(result, my_color);
+            }
+            renderer::draw_match_panel(
+                &shown_score,
+                game.result.filter(|_| rematch_allowed).map(|_| rematch),
+                board_offset_x,
+                board_offset_y,
+            );
+            if show_takeback {
+                renderer::draw_takeback_buttons(takeback, board_offset_x, board_offset_y);
+            }
+            renderer::draw_account_panel(
+                my_rating.as_ref(),
+                &leaderboard,
+                board_offset_x,
+                board_offset_y,
+            );
+        }
 
         next_frame().await
     }
 }
+
+fn print_standings(standings: &TournamentStandings) {
+    println!(
+        "Tournament {} ({:?}), round {} of {}{}",
+        standings.name,
+        standings.format,
+        standings.round,
+        standings.total_rounds,
+        if standings.finished { ", finished" } else { "" }
+    );
+    for row in &standings.rows {
+        println!(
+            "{:>3}. {:<20} {:>5.1}  Buchholz {:.1}  S-B {:.2}",
+            row.rank, row.name, row.points, row.buchholz, row.sonneborn_berger
+        );
+    }
+}
@@ -1,14 +1,620 @@
+use crate::game::{Game, GameResult};
 use crate::types::{PieceType, PlayerColor, Pos};
 use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum GameMessage {
-    Join { room: Option<String> },
-    RoomCode { code: String },
-    Welcome { color: PlayerColor },
+/// Version of the message protocol spoken by this build. Bump it whenever a
+/// change would break peers built from an older version.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest client protocol version the server still accepts. Clients that
+/// send `Join` without a `Hello` first are treated as version 1.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Optional features this build supports, exchanged in `Hello`/`ServerHello`.
+pub const CAPABILITIES: &[&str] = &["accounts", "heartbeat", "msgpack"];
+
+/// Seat a player asks for when creating a room.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorPreference {
+    White,
+    Black,
+    Random,
+}
+
+impl FromStr for ColorPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "white" => Ok(ColorPreference::White),
+            "black" => Ok(ColorPreference::Black),
+            "random" => Ok(ColorPreference::Random),
+            _ => Err(format!("expected white, black or random, got '{}'", s)),
+        }
+    }
+}
+
+/// An open public room as listed in the lobby.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RoomInfo {
+    pub code: String,
+    pub host: String,
+    pub host_rating: Option<f64>,
+    /// Color the next player to join will get.
+    pub open_color: PlayerColor,
+}
+
+/// UDP port servers listen on for LAN discovery queries.
+pub const DISCOVERY_PORT: u16 = 8083;
+
+/// Datagram a client broadcasts to find servers on the local network.
+pub const DISCOVERY_QUERY: &[u8] = b"ascension-chess discover";
+
+/// A server's answer to a discovery query, sent back as JSON in one datagram.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerAnnouncement {
+    pub server_name: String,
+    pub protocol_version: u32,
+    /// Port of the TCP game protocol on the answering host.
+    pub port: u16,
+    /// The first few open public rooms; `open_room_count` counts them all.
+    pub open_rooms: Vec<RoomInfo>,
+    pub open_room_count: u32,
+    /// The game port requires TLS.
+    #[serde(default)]
+    pub tls: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TournamentFormat {
+    RoundRobin,
+    Swiss { rounds: u32 },
+}
+
+impl FromStr for TournamentFormat {
+    type Err = String;
+
+    /// Parses `round-robin` or `swiss:<rounds>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "round-robin" | "roundrobin" => Ok(TournamentFormat::RoundRobin),
+            other => other
+                .strip_prefix("swiss:")
+                .and_then(|rounds| rounds.parse().ok())
+                .map(|rounds| TournamentFormat::Swiss { rounds })
+                .ok_or_else(|| format!("expected round-robin or swiss:<rounds>, got '{}'", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StandingRow {
+    pub rank: u32,
+    pub name: String,
+    pub points: f32,
+    pub buchholz: f32,
+    pub sonneborn_berger: f32,
+    pub games: u32,
+}
+
+/// A game of the current round. `black` is `None` for a bye.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PairingInfo {
+    pub room: String,
+    pub white: String,
+    pub black: Option<String>,
+    pub result: Option<GameResult>,
+    /// Decided because a player did not turn up or abandoned the game. A
+    /// forfeit without a `result` was lost by both players.
+    #[serde(default)]
+    pub forfeit: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TournamentStandings {
+    pub name: String,
+    pub format: TournamentFormat,
+    pub organizer: String,
+    /// Current round, starting at 1; 0 while registration is open.
+    pub round: u32,
+    pub total_rounds: u32,
+    pub finished: bool,
+    pub rows: Vec<StandingRow>,
+    pub pairings: Vec<PairingInfo>,
+}
+
+impl TournamentStandings {
+    /// The unfinished game `player` has been paired into this round, if any.
+    pub fn pending_pairing(&self, player: &str) -> Option<&PairingInfo> {
+        self.pairings.iter().find(|p| {
+            p.result.is_none()
+                && !p.forfeit
+                && p.black.is_some()
+                && (p.white == player || p.black.as_deref() == Some(player))
+        })
+    }
+}
+
+/// A correspondence game as listed to one of its players.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CorrespondenceInfo {
+    /// Room to join to see the game and move.
+    pub room: String,
+    pub white: String,
+    pub black: String,
+    pub days_per_move: u32,
+    pub turn: PlayerColor,
+    /// Unix time in seconds by which `turn` must move or lose on time.
+    pub deadline: u64,
+    pub moves: u32,
+    pub result: Option<GameResult>,
+    /// Player who sent the challenge, while it waits to be accepted; until
+    /// then `deadline` is when the challenge lapses.
+    #[serde(default)]
+    pub challenger: Option<String>,
+}
+
+impl CorrespondenceInfo {
+    pub fn is_turn_of(&self, player: &str) -> bool {
+        let to_move = match self.turn {
+            PlayerColor::White => &self.white,
+            PlayerColor::Black => &self.black,
+        };
+        self.result.is_none() && self.challenger.is_none() && to_move == player
+    }
+
+    /// Whether `player` was challenged to this game and has yet to answer.
+    pub fn awaits_answer_from(&self, player: &str) -> bool {
+        self.challenger
+            .as_deref()
+            .is_some_and(|challenger| challenger != player)
+    }
+}
+
+/// A player's action within a turn, as sent in `Move`, `Upgrade` and
+/// `Promote` messages.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum GameAction {
     Move { from: Pos, to: Pos },
     Upgrade { pos: Pos },
     Promote { piece_type: PieceType },
-    Error { message: String },
+}
+
+impl GameAction {
+    pub fn from_message(msg: &GameMessage) -> Option<Self> {
+        match *msg {
+            GameMessage::Move { from, to } => Some(GameAction::Move { from, to }),
+            GameMessage::Upgrade { pos } => Some(GameAction::Upgrade { pos }),
+            GameMessage::Promote { piece_type } => Some(GameAction::Promote { piece_type }),
+            _ => None,
+        }
+    }
+
+    pub fn to_message(self) -> GameMessage {
+        match self {
+            GameAction::Move { from, to } => GameMessage::Move { from, to },
+            GameAction::Upgrade { pos } => GameMessage::Upgrade { pos },
+            GameAction::Promote { piece_type } => GameMessage::Promote { piece_type },
+        }
+    }
+
+    /// Plays the action on `game` without checking that it is legal, as
+    /// clients do with actions relayed by the server.
+    pub fn apply(self, game: &mut Game) {
+        match self {
+            GameAction::Move { from, to } => game.make_move(from, to),
+            GameAction::Upgrade { pos } => game.attempt_upgrade(pos),
+            GameAction::Promote { piece_type } => game.resolve_promotion(piece_type),
+        }
+    }
+
+    /// A new game with `actions` played on it, as after a take-back.
+    pub fn replay(actions: &[GameAction]) -> Game {
+        let mut game = Game::new();
+        for action in actions {
+            action.apply(&mut game);
+        }
+        game
+    }
+}
+
+/// Number of games in a match between the same two players.
+pub const MATCH_LENGTH: u32 = 5;
+
+/// Running score of a match, from the point of view of the player it is sent to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct MatchScore {
+    pub you: f32,
+    pub opponent: f32,
+    pub games: u32,
+}
+
+impl MatchScore {
+    /// Adds a finished game in which "you" played `color`.
+    pub fn record(&mut self, result: GameResult, color: PlayerColor) {
+        match result {
+            GameResult::Win(winner) if winner == color => self.you += 1.0,
+            GameResult::Win(_) => self.opponent += 1.0,
+            GameResult::Draw(_) => {
+                self.you += 0.5;
+                self.opponent += 0.5;
+            }
+        }
+        self.games += 1;
+    }
+
+    /// The same score seen from the other player's side.
+    pub fn flipped(&self) -> Self {
+        Self {
+            you: self.opponent,
+            opponent: self.you,
+            games: self.games,
+        }
+    }
+
+    /// True once either player has more than half the points or every game
+    /// of the match has been played.
+    pub fn is_decided(&self) -> bool {
+        let half = MATCH_LENGTH as f32 / 2.0;
+        self.you > half || self.opponent > half || self.games >= MATCH_LENGTH
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RatingEntry {
+    pub name: String,
+    pub rating: f64,
+    pub deviation: f64,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum GameMessage {
+    Hello {
+        protocol_version: u32,
+        client_name: String,
+        capabilities: Vec<String>,
+    },
+    ServerHello {
+        protocol_version: u32,
+        server_name: String,
+        capabilities: Vec<String>,
+    },
+    UpgradeRequired {
+        min_version: u32,
+        message: String,
+    },
+    Join {
+        room: Option<String>,
+        /// Password required to join the room, or set on the room when it is created.
+        #[serde(default)]
+        password: Option<String>,
+        /// Seat wanted by the player creating the room; ignored when joining
+        /// an existing room. Older clients leave it out and get White.
+        #[serde(default)]
+        color: Option<ColorPreference>,
+        /// List the room in the lobby while it waits for an opponent.
+        #[serde(default)]
+        public: bool,
+        /// Token from `Welcome` or `ServerRestarting` that gives back the
+        /// seat held before, which a restored room keeps for its holder.
+        #[serde(default)]
+        rejoin_token: Option<String>,
+    },
+    RoomCode {
+        code: String,
+    },
+    Welcome {
+        color: PlayerColor,
+        /// Pass it with `Join` to take this seat back after a restart.
+        #[serde(default)]
+        rejoin_token: Option<String>,
+    },
+    Move {
+        from: Pos,
+        to: Pos,
+    },
+    Upgrade {
+        pos: Pos,
+    },
+    Promote {
+        piece_type: PieceType,
+    },
+    Error {
+        message: String,
+    },
     OpponentDisconnected,
+    Register {
+        name: String,
+    },
+    Registered {
+        name: String,
+        token: String,
+    },
+    Login {
+        name: String,
+        token: String,
+    },
+    QueryRating {
+        name: String,
+    },
+    Rating {
+        entry: RatingEntry,
+    },
+    QueryLeaderboard {
+        limit: usize,
+    },
+    Leaderboard {
+        entries: Vec<RatingEntry>,
+    },
+    Ping {
+        nonce: u64,
+    },
+    Pong {
+        nonce: u64,
+    },
+    /// Sent to every player before the server shuts down. Open rooms are
+    /// restored after the restart and each seat is kept for the player
+    /// rejoining `room` with `rejoin_token`.
+    ServerRestarting {
+        message: String,
+        #[serde(default)]
+        room: Option<String>,
+        #[serde(default)]
+        rejoin_token: Option<String>,
+    },
+    CreateTournament {
+        name: String,
+        format: TournamentFormat,
+    },
+    JoinTournament {
+        name: String,
+    },
+    StartTournament {
+        name: String,
+    },
+    QueryStandings {
+        name: String,
+    },
+    Standings {
+        standings: TournamentStandings,
+    },
+    ListRooms,
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    OfferRematch,
+    AcceptRematch,
+    /// A new game in the same room; colors are swapped from the last one.
+    RematchStarted {
+        color: PlayerColor,
+        score: MatchScore,
+    },
+    CreateCorrespondence {
+        opponent: String,
+        days_per_move: u32,
+        #[serde(default)]
+        color: Option<ColorPreference>,
+    },
+    QueryCorrespondence {
+        #[serde(default)]
+        my_turn_only: bool,
+    },
+    CorrespondenceGames {
+        games: Vec<CorrespondenceInfo>,
+    },
+    /// `color` ran out of time in a correspondence game and lost.
+    TimeForfeit {
+        color: PlayerColor,
+    },
+    /// Asks the opponent to undo the sender's last turn; relayed to them.
+    RequestTakeback,
+    AcceptTakeback,
+    /// Turns a take-back request down; relayed to the player who asked.
+    DeclineTakeback,
+    /// A take-back was accepted. The game is now `actions` played from the
+    /// start, with the requester to move again.
+    TakenBack {
+        actions: Vec<GameAction>,
+    },
+    /// Accepts the correspondence challenge in `room`; the game starts and
+    /// is listed in the `CorrespondenceGames` reply.
+    AcceptCorrespondence {
+        room: String,
+    },
+    /// Declines the correspondence challenge in `room`, or withdraws it when
+    /// sent by the challenger. Answered with the sender's remaining games.
+    DeclineCorrespondence {
+        room: String,
+    },
+}
+
+impl GameMessage {
+    /// Name of the variant, used to label metrics and logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GameMessage::Hello { .. } => "Hello",
+            GameMessage::ServerHello { .. } => "ServerHello",
+            GameMessage::UpgradeRequired { .. } => "UpgradeRequired",
+            GameMessage::Join { .. } => "Join",
+            GameMessage::RoomCode { .. } => "RoomCode",
+            GameMessage::Welcome { .. } => "Welcome",
+            GameMessage::Move { .. } => "Move",
+            GameMessage::Upgrade { .. } => "Upgrade",
+            GameMessage::Promote { .. } => "Promote",
+            GameMessage::Error { .. } => "Error",
+            GameMessage::OpponentDisconnected => "OpponentDisconnected",
+            GameMessage::Register { .. } => "Register",
+            GameMessage::Registered { .. } => "Registered",
+            GameMessage::Login { .. } => "Login",
+            GameMessage::QueryRating { .. } => "QueryRating",
+            GameMessage::Rating { .. } => "Rating",
+            GameMessage::QueryLeaderboard { .. } => "QueryLeaderboard",
+            GameMessage::Leaderboard { .. } => "Leaderboard",
+            GameMessage::Ping { .. } => "Ping",
+            GameMessage::Pong { .. } => "Pong",
+            GameMessage::ServerRestarting { .. } => "ServerRestarting",
+            GameMessage::CreateTournament { .. } => "CreateTournament",
+            GameMessage::JoinTournament { .. } => "JoinTournament",
+            GameMessage::StartTournament { .. } => "StartTournament",
+            GameMessage::QueryStandings { .. } => "QueryStandings",
+            GameMessage::Standings { .. } => "Standings",
+            GameMessage::ListRooms => "ListRooms",
+            GameMessage::RoomList { .. } => "RoomList",
+            GameMessage::OfferRematch => "OfferRematch",
+            GameMessage::AcceptRematch => "AcceptRematch",
+            GameMessage::RematchStarted { .. } => "RematchStarted",
+            GameMessage::CreateCorrespondence { .. } => "CreateCorrespondence",
+            GameMessage::QueryCorrespondence { .. } => "QueryCorrespondence",
+            GameMessage::CorrespondenceGames { .. } => "CorrespondenceGames",
+            GameMessage::TimeForfeit { .. } => "TimeForfeit",
+            GameMessage::RequestTakeback => "RequestTakeback",
+            GameMessage::AcceptTakeback => "AcceptTakeback",
+            GameMessage::DeclineTakeback => "DeclineTakeback",
+            GameMessage::TakenBack { .. } => "TakenBack",
+            GameMessage::AcceptCorrespondence { .. } => "AcceptCorrespondence",
+            GameMessage::DeclineCorrespondence { .. } => "DeclineCorrespondence",
+        }
+    }
+
+    pub fn hello(client_name: &str) -> Self {
+        GameMessage::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            client_name: client_name.to_string(),
+            capabilities: CAPABILITIES.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+}
+
+/// How `GameMessage`s are written to the wire. Every connection starts with
+/// JSON; once both sides list the `msgpack` capability in the handshake,
+/// every frame after `ServerHello` is MessagePack instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl Encoding {
+    /// The encoding to switch to after a handshake that agreed on
+    /// `capabilities`.
+    pub fn negotiated(capabilities: &[String]) -> Self {
+        if capabilities.iter().any(|c| c == "msgpack") {
+            Encoding::MessagePack
+        } else {
+            Encoding::Json
+        }
+    }
+
+    pub fn encode(self, msg: &GameMessage) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        Ok(match self {
+            Encoding::Json => serde_json::to_vec(msg)?,
+            // Named fields keep `#[serde(default)]` fields optional, as in JSON.
+            Encoding::MessagePack => rmp_serde::to_vec_named(msg)?,
+        })
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<GameMessage, Box<dyn Error + Send + Sync>> {
+        Ok(match self {
+            Encoding::Json => serde_json::from_slice(bytes)?,
+            Encoding::MessagePack => rmp_serde::from_slice(bytes)?,
+        })
+    }
+}
+
+/// Tracks pings sent to a peer that negotiated the `heartbeat` capability.
+/// Call `tick` once per heartbeat interval and `pong` for every `Pong` received.
+pub struct Heartbeat {
+    next_nonce: u64,
+    pending: Option<(u64, Instant)>,
+    missed: u32,
+    max_missed: u32,
+    latency: Option<Duration>,
+}
+
+impl Heartbeat {
+    pub fn new(max_missed: u32) -> Self {
+        Self {
+            next_nonce: 0,
+            pending: None,
+            missed: 0,
+            max_missed,
+            latency: None,
+        }
+    }
+
+    /// Returns the next `Ping` to send, or `None` once the peer has missed
+    /// `max_missed` heartbeats in a row and should be treated as gone.
+    pub fn tick(&mut self) -> Option<GameMessage> {
+        if self.pending.is_some() {
+            self.missed += 1;
+            if self.missed >= self.max_missed {
+                return None;
+            }
+        }
+        let nonce = self.next_nonce;
+        self.next_nonce += 1;
+        self.pending = Some((nonce, Instant::now()));
+        Some(GameMessage::Ping { nonce })
+    }
+
+    /// Records the answer to a ping. Stale or made-up nonces are ignored, so
+    /// they neither count as a sign of life nor update the latency.
+    pub fn pong(&mut self, nonce: u64) {
+        if let Some((pending, sent_at)) = self.pending
+            && pending == nonce
+        {
+            self.missed = 0;
+            self.latency = Some(sent_at.elapsed());
+            self.pending = None;
+        }
+    }
+
+    /// Round trip time of the last answered ping.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ping_nonce(msg: Option<GameMessage>) -> u64 {
+        match msg {
+            Some(GameMessage::Ping { nonce }) => nonce,
+            other => panic!("expected a ping, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pongs_with_the_wrong_nonce_do_not_reset_missed_heartbeats() {
+        let mut heartbeat = Heartbeat::new(3);
+        let first = ping_nonce(heartbeat.tick());
+        let second = ping_nonce(heartbeat.tick());
+        heartbeat.pong(first);
+        heartbeat.pong(second + 100);
+        assert_eq!(heartbeat.latency(), None);
+
+        ping_nonce(heartbeat.tick());
+        assert!(heartbeat.tick().is_none());
+    }
+
+    #[test]
+    fn answered_pings_reset_missed_heartbeats() {
+        let mut heartbeat = Heartbeat::new(2);
+        ping_nonce(heartbeat.tick());
+        let nonce = ping_nonce(heartbeat.tick());
+        heartbeat.pong(nonce);
+        assert!(heartbeat.latency().is_some());
+
+        ping_nonce(heartbeat.tick());
+        ping_nonce(heartbeat.tick());
+    }
 }
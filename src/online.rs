@@ -0,0 +1,349 @@
+use crate::network::{GameMessage, ServerAnnouncement};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Duration;
+
+/// How often the client pings the server and how many unanswered pings in a
+/// row it tolerates before giving up on the connection.
+#[derive(Clone, Copy, Debug)]
+pub struct HeartbeatSettings {
+    pub interval: Duration,
+    pub max_missed: u32,
+}
+
+/// Whether and how the connection to the server is encrypted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum TlsMode {
+    #[default]
+    Off,
+    /// Trust certificates from the public certificate authorities.
+    Public,
+    /// Trust only the certificate in this PEM file, such as a server's
+    /// self-signed one.
+    Pinned(PathBuf),
+}
+
+#[derive(Default)]
+struct LinkStatus {
+    latency: Option<Duration>,
+    closed: bool,
+}
+
+/// Handle to the game server connection. On native targets the connection
+/// runs on a background thread with its own tokio runtime; browser builds
+/// have no sockets, so connecting only reports an error.
+pub struct Connection {
+    incoming: mpsc::Receiver<GameMessage>,
+    status: Arc<Mutex<LinkStatus>>,
+    #[cfg(not(target_arch = "wasm32"))]
+    outgoing: tokio::sync::mpsc::UnboundedSender<GameMessage>,
+}
+
+impl Connection {
+    pub fn send(&self, msg: GameMessage) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.outgoing.send(msg).ok();
+        #[cfg(target_arch = "wasm32")]
+        drop(msg);
+    }
+
+    pub fn try_recv(&self) -> Option<GameMessage> {
+        self.incoming.try_recv().ok()
+    }
+
+    /// Round trip time to the server, once a heartbeat has been answered.
+    pub fn latency(&self) -> Option<Duration> {
+        self.status.lock().unwrap().latency
+    }
+
+    /// True once the connection to the server has dropped or timed out.
+    pub fn is_closed(&self) -> bool {
+        self.status.lock().unwrap().closed
+    }
+}
+
+/// Connects to `server_addr` and sends `account_msg` and `join_msg` (each
+/// if given). Without a `Join` the connection stays in the lobby until one
+/// is sent with `Connection::send`. Heartbeats are only sent if the server
+/// advertises support for them.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn connect(
+    server_addr: String,
+    tls: TlsMode,
+    account_msg: Option<GameMessage>,
+    join_msg: Option<GameMessage>,
+    heartbeat_settings: HeartbeatSettings,
+) -> Connection {
+    use crate::client::{Client, Event};
+    use crate::tls::ClientTls;
+    use tokio::runtime::Runtime;
+
+    let (game_tx, game_rx) = mpsc::channel::<GameMessage>();
+    let (net_tx, mut net_rx_tokio) = tokio::sync::mpsc::unbounded_channel::<GameMessage>();
+    let status = Arc::new(Mutex::new(LinkStatus::default()));
+    let thread_status = status.clone();
+
+    std::thread::spawn(move || {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let client_name = concat!("ascension-chess ", env!("CARGO_PKG_VERSION"));
+            let heartbeat = Some(heartbeat_settings);
+            let connected = match tls {
+                TlsMode::Off => Client::connect(server_addr, client_name, heartbeat).await,
+                TlsMode::Public => {
+                    let tls = ClientTls::public();
+                    Client::connect_tls(&server_addr, &tls, client_name, heartbeat).await
+                }
+                TlsMode::Pinned(cert) => match ClientTls::pinned(&cert) {
+                    Ok(tls) => {
+                        Client::connect_tls(&server_addr, &tls, client_name, heartbeat).await
+                    }
+                    Err(e) => Err(e.into()),
+                },
+            };
+            let mut client = match connected {
+                Ok(client) => client,
+                Err(e) => {
+                    game_tx
+                        .send(GameMessage::Error {
+                            message: e.to_string(),
+                        })
+                        .ok();
+                    return;
+                }
+            };
+            for msg in [account_msg, join_msg].into_iter().flatten() {
+                client.send(msg).ok();
+            }
+
+            loop {
+                tokio::select! {
+                    msg = net_rx_tokio.recv() => {
+                        // The Connection was dropped; hang up.
+                        let Some(msg) = msg else {
+                            client.disconnect().await;
+                            break;
+                        };
+                        client.send(msg).ok();
+                    }
+                    event = client.next_event() => match event {
+                        Some(Event::Message(msg)) => {
+                            game_tx.send(msg).ok();
+                        }
+                        Some(Event::Latency(rtt)) => {
+                            thread_status.lock().unwrap().latency = Some(rtt);
+                        }
+                        // Only the server says the opponent left; losing our
+                        // own connection is reported as an error.
+                        Some(Event::Disconnected(reason)) => {
+                            game_tx
+                                .send(GameMessage::Error {
+                                    message: reason.to_string(),
+                                })
+                                .ok();
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+            }
+        });
+        thread_status.lock().unwrap().closed = true;
+    });
+
+    Connection {
+        incoming: game_rx,
+        status,
+        outgoing: net_tx,
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn connect(
+    _server_addr: String,
+    _tls: TlsMode,
+    _account_msg: Option<GameMessage>,
+    _join_msg: Option<GameMessage>,
+    _heartbeat_settings: HeartbeatSettings,
+) -> Connection {
+    let (game_tx, game_rx) = mpsc::channel::<GameMessage>();
+    game_tx
+        .send(GameMessage::Error {
+            message: "Online play is not available in the browser build".into(),
+        })
+        .ok();
+    Connection {
+        incoming: game_rx,
+        status: Arc::new(Mutex::new(LinkStatus {
+            latency: None,
+            closed: true,
+        })),
+    }
+}
+
+/// A game server run by the client itself for `--host`. It stops when this
+/// handle is dropped.
+pub struct HostedServer {
+    #[cfg(not(target_arch = "wasm32"))]
+    _shutdown: tokio::sync::oneshot::Sender<()>,
+}
+
+/// Starts a game server listening on `bind` on a background thread,
+/// announcing itself on the local network as `display_name`. Returns once
+/// the server is listening, or why it could not start.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn host(bind: SocketAddr, display_name: &str) -> Result<HostedServer, String> {
+    use crate::server::{Config, Server};
+    use tokio::runtime::Runtime;
+
+    let data_dir = std::env::temp_dir().join(format!("ascension-chess-host-{}", bind.port()));
+    let config = Config::hosted(bind, display_name, &data_dir)?;
+    let (ready_tx, ready_rx) = mpsc::channel();
+    let (shutdown, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    std::thread::spawn(move || {
+        let rt = Runtime::new().unwrap();
+        rt.block_on(async {
+            let server = match Server::bind(config).await {
+                Ok(server) => server,
+                Err(e) => {
+                    ready_tx
+                        .send(Err(format!("Cannot host on {}: {}", bind, e)))
+                        .ok();
+                    return;
+                }
+            };
+            ready_tx.send(Ok(())).ok();
+            server
+                .run(async {
+                    shutdown_rx.await.ok();
+                })
+                .await
+                .ok();
+        });
+    });
+    ready_rx
+        .recv()
+        .map_err(|_| "The hosted server stopped unexpectedly".to_string())??;
+    Ok(HostedServer {
+        _shutdown: shutdown,
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn host(_bind: SocketAddr, _display_name: &str) -> Result<HostedServer, String> {
+    Err("Hosting is not available in the browser build".into())
+}
+
+/// How often a LAN search repeats its query, which also refreshes the room
+/// lists of servers already found.
+#[cfg(not(target_arch = "wasm32"))]
+const LAN_QUERY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A server that answered a LAN discovery query.
+#[derive(Debug, Clone)]
+pub struct LanServer {
+    /// Address of the server's game port, ready for `connect`.
+    pub addr: SocketAddr,
+    pub announcement: ServerAnnouncement,
+}
+
+/// Looks for servers on the local network until dropped, by broadcasting
+/// discovery queries from a background thread. Browser builds find nothing.
+pub struct LanSearch {
+    found: mpsc::Receiver<LanServer>,
+    servers: Vec<LanServer>,
+    stop: Arc<AtomicBool>,
+}
+
+impl LanSearch {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn start() -> Self {
+        use crate::network::{DISCOVERY_PORT, DISCOVERY_QUERY};
+        use std::net::{Ipv4Addr, UdpSocket};
+        use std::time::Instant;
+
+        let (found_tx, found) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        std::thread::spawn(move || {
+            let Ok(socket) = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) else {
+                return;
+            };
+            socket.set_broadcast(true).ok();
+            socket
+                .set_read_timeout(Some(Duration::from_millis(200)))
+                .ok();
+            // Servers on this machine answer on loopback even where
+            // broadcasts go nowhere.
+            let targets = [Ipv4Addr::BROADCAST, Ipv4Addr::LOCALHOST];
+            let mut last_query: Option<Instant> = None;
+            let mut buf = [0; 8192];
+            while !thread_stop.load(Ordering::Relaxed) {
+                if last_query.is_none_or(|at| at.elapsed() >= LAN_QUERY_INTERVAL) {
+                    for ip in targets {
+                        socket.send_to(DISCOVERY_QUERY, (ip, DISCOVERY_PORT)).ok();
+                    }
+                    last_query = Some(Instant::now());
+                }
+                let Ok((len, from)) = socket.recv_from(&mut buf) else {
+                    continue;
+                };
+                let Ok(announcement) = serde_json::from_slice::<ServerAnnouncement>(&buf[..len])
+                else {
+                    continue;
+                };
+                let server = LanServer {
+                    addr: SocketAddr::new(from.ip(), announcement.port),
+                    announcement,
+                };
+                if found_tx.send(server).is_err() {
+                    break;
+                }
+            }
+        });
+        LanSearch {
+            found,
+            servers: Vec::new(),
+            stop,
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn start() -> Self {
+        LanSearch {
+            found: mpsc::channel().1,
+            servers: Vec::new(),
+            stop: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Servers found so far, with the room lists of their latest answers.
+    pub fn servers(&mut self) -> &[LanServer] {
+        while let Ok(found) = self.found.try_recv() {
+            // A server on this machine answers both on loopback and on its
+            // network address; list it once, under the latter.
+            let same = |known: &LanServer| {
+                known.addr == found.addr
+                    || (known.addr.port() == found.addr.port()
+                        && known.announcement.server_name == found.announcement.server_name
+                        && (known.addr.ip().is_loopback() || found.addr.ip().is_loopback()))
+            };
+            match self.servers.iter_mut().find(|known| same(known)) {
+                Some(known) if found.addr.ip().is_loopback() && !known.addr.ip().is_loopback() => {
+                    known.announcement = found.announcement;
+                }
+                Some(known) => *known = found,
+                None => self.servers.push(found),
+            }
+        }
+        &self.servers
+    }
+}
+
+impl Drop for LanSearch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
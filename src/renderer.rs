@@ -1,8 +1,13 @@
 use crate::assets::*;
 use crate::constants::*;
 use crate::game::*;
+use crate::network::{
+    CorrespondenceInfo, MATCH_LENGTH, MatchScore, RatingEntry, RoomInfo, TournamentStandings,
+};
+use crate::online::LanServer;
 use crate::types::*;
 use macroquad::prelude::*;
+use std::time::Duration;
 
 pub fn draw_game(game: &Game, assets: &Assets, flipped: bool, offset_x: f32, offset_y: f32) {
     clear_background(LIGHTGRAY);
@@ -26,11 +31,11 @@ pub fn draw_game(game: &Game, assets: &Assets, flipped: bool, offset_x: f32, off
         draw_rectangle(tx, ty, SQUARE_SIZE, SQUARE_SIZE, LAST_MOVE_COLOR);
     }
 
-    if game.board.is_in_check(game.turn) {
-        if let Some(king_pos) = game.board.find_king(game.turn) {
-            let (kx, ky) = get_screen_coords(king_pos, flipped, offset_x, offset_y);
-            draw_rectangle(kx, ky, SQUARE_SIZE, SQUARE_SIZE, CHECK_COLOR);
-        }
+    if game.board.is_in_check(game.turn)
+        && let Some(king_pos) = game.board.find_king(game.turn)
+    {
+        let (kx, ky) = get_screen_coords(king_pos, flipped, offset_x, offset_y);
+        draw_rectangle(kx, ky, SQUARE_SIZE, SQUARE_SIZE, CHECK_COLOR);
     }
 
     if let Some(pos) = game.selected_pos {
@@ -77,20 +82,20 @@ pub fn draw_game(game: &Game, assets: &Assets, flipped: bool, offset_x: f32, off
     for x in 0..8 {
         for y in 0..8 {
             let pos = Pos::new(x, y);
-            if let Some(piece) = game.board.get_piece(pos) {
-                if let Some(tex) = assets.textures.get(&(piece.piece_type, piece.color)) {
-                    let (sx, sy) = get_screen_coords(pos, flipped, offset_x, offset_y);
-                    draw_texture_ex(
-                        tex,
-                        sx,
-                        sy,
-                        WHITE,
-                        DrawTextureParams {
-                            dest_size: Some(vec2(SQUARE_SIZE, SQUARE_SIZE)),
-                            ..Default::default()
-                        },
-                    );
-                }
+            if let Some(piece) = game.board.get_piece(pos)
+                && let Some(tex) = assets.textures.get(&(piece.piece_type, piece.color))
+            {
+                let (sx, sy) = get_screen_coords(pos, flipped, offset_x, offset_y);
+                draw_texture_ex(
+                    tex,
+                    sx,
+                    sy,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(vec2(SQUARE_SIZE, SQUARE_SIZE)),
+                        ..Default::default()
+                    },
+                );
             }
         }
     }
@@ -268,3 +273,470 @@ fn draw_ui(game: &Game, offset_x: f32, offset_y: f32) {
         }
     }
 }
+
+pub fn lobby_row_rect(index: usize) -> Rect {
+    Rect::new(100.0, 140.0 + index as f32 * 45.0, 700.0, 40.0)
+}
+
+/// Draws the list of open public rooms; click a row to join it.
+pub fn draw_lobby(rooms: &[RoomInfo], error: Option<&str>) {
+    clear_background(LIGHTGRAY);
+    draw_text("Open rooms", 100.0, 80.0, 40.0, BLACK);
+    draw_text("Click a room to join it", 100.0, 115.0, 20.0, DARKGRAY);
+    if let Some(error) = error {
+        draw_text(error, 500.0, 80.0, 25.0, RED);
+    }
+
+    if rooms.is_empty() {
+        draw_text(
+            "No open rooms right now. Waiting for someone to create one...",
+            100.0,
+            170.0,
+            25.0,
+            DARKGRAY,
+        );
+        return;
+    }
+
+    let mouse = mouse_position().into();
+    for (i, room) in rooms.iter().enumerate() {
+        let row = lobby_row_rect(i);
+        if row.y + row.h > screen_height() {
+            break;
+        }
+        let background = if row.contains(mouse) { WHITE } else { GRAY };
+        draw_rectangle(row.x, row.y, row.w, row.h, background);
+        let rating = match room.host_rating {
+            Some(rating) => format!("{:.0}", rating),
+            None => "unrated".to_string(),
+        };
+        draw_text(&room.code, row.x + 10.0, row.y + 27.0, 25.0, BLACK);
+        draw_text(
+            &format!("{} ({})", room.host, rating),
+            row.x + 300.0,
+            row.y + 27.0,
+            25.0,
+            BLACK,
+        );
+        draw_text(
+            &format!("You play {:?}", room.open_color),
+            row.x + 540.0,
+            row.y + 27.0,
+            20.0,
+            DARKGRAY,
+        );
+    }
+}
+
+/// A row of the LAN screen: a server, where clicking creates a room, or one
+/// of its open rooms.
+pub enum LanRow<'a> {
+    Server(&'a LanServer),
+    Room(&'a LanServer, &'a RoomInfo),
+}
+
+/// Each server followed by its open rooms, in the order they are drawn.
+pub fn lan_rows(servers: &[LanServer]) -> Vec<LanRow<'_>> {
+    servers
+        .iter()
+        .flat_map(|server| {
+            std::iter::once(LanRow::Server(server)).chain(
+                server
+                    .announcement
+                    .open_rooms
+                    .iter()
+                    .map(move |room| LanRow::Room(server, room)),
+            )
+        })
+        .collect()
+}
+
+/// Draws the servers found on the local network and their open rooms in
+/// lobby rows.
+pub fn draw_lan_servers(rows: &[LanRow], error: Option<&str>) {
+    clear_background(LIGHTGRAY);
+    draw_text("Servers on this network", 100.0, 80.0, 40.0, BLACK);
+    draw_text(
+        "Click a server to create a room there, or a room to join it",
+        100.0,
+        115.0,
+        20.0,
+        DARKGRAY,
+    );
+    if let Some(error) = error {
+        draw_text(error, 600.0, 80.0, 25.0, RED);
+    }
+
+    if rows.is_empty() {
+        draw_text("Looking for servers...", 100.0, 170.0, 25.0, DARKGRAY);
+        return;
+    }
+
+    let mouse = mouse_position().into();
+    for (i, lan_row) in rows.iter().enumerate() {
+        let row = lobby_row_rect(i);
+        if row.y + row.h > screen_height() {
+            break;
+        }
+        match lan_row {
+            LanRow::Server(server) => {
+                let background = if row.contains(mouse) {
+                    WHITE
+                } else {
+                    Color::new(0.7, 0.75, 0.85, 1.0)
+                };
+                draw_rectangle(row.x, row.y, row.w, row.h, background);
+                draw_text(
+                    &server.announcement.server_name,
+                    row.x + 10.0,
+                    row.y + 27.0,
+                    25.0,
+                    BLACK,
+                );
+                draw_text(
+                    &server.addr.to_string(),
+                    row.x + 300.0,
+                    row.y + 27.0,
+                    20.0,
+                    DARKGRAY,
+                );
+                draw_text(
+                    &format!("{} open rooms", server.announcement.open_room_count),
+                    row.x + 540.0,
+                    row.y + 27.0,
+                    20.0,
+                    DARKGRAY,
+                );
+            }
+            LanRow::Room(_, room) => {
+                let background = if row.contains(mouse) { WHITE } else { GRAY };
+                draw_rectangle(row.x + 30.0, row.y, row.w - 30.0, row.h, background);
+                let rating = match room.host_rating {
+                    Some(rating) => format!("{:.0}", rating),
+                    None => "unrated".to_string(),
+                };
+                draw_text(&room.code, row.x + 40.0, row.y + 27.0, 25.0, BLACK);
+                draw_text(
+                    &format!("{} ({})", room.host, rating),
+                    row.x + 300.0,
+                    row.y + 27.0,
+                    25.0,
+                    BLACK,
+                );
+                draw_text(
+                    &format!("You play {:?}", room.open_color),
+                    row.x + 540.0,
+                    row.y + 27.0,
+                    20.0,
+                    DARKGRAY,
+                );
+            }
+        }
+    }
+}
+
+/// Lists `player`'s correspondence games in lobby rows; `now` is Unix time in
+/// seconds.
+pub fn draw_correspondence(
+    games: &[CorrespondenceInfo],
+    player: &str,
+    now: u64,
+    error: Option<&str>,
+) {
+    clear_background(LIGHTGRAY);
+    draw_text("Correspondence games", 100.0, 80.0, 40.0, BLACK);
+    draw_text(
+        "Click a game to open it or a challenge to accept it; right-click a challenge to decline it",
+        100.0,
+        115.0,
+        20.0,
+        DARKGRAY,
+    );
+    if let Some(error) = error {
+        draw_text(error, 550.0, 80.0, 25.0, RED);
+    }
+
+    if games.is_empty() {
+        draw_text(
+            "No correspondence games yet. Challenge someone with --challenge.",
+            100.0,
+            170.0,
+            25.0,
+            DARKGRAY,
+        );
+        return;
+    }
+
+    let mouse = mouse_position().into();
+    for (i, game) in games.iter().enumerate() {
+        let row = lobby_row_rect(i);
+        if row.y + row.h > screen_height() {
+            break;
+        }
+        let your_turn = game.is_turn_of(player) || game.awaits_answer_from(player);
+        let background = if row.contains(mouse) {
+            WHITE
+        } else if your_turn {
+            Color::new(0.7, 0.85, 0.7, 1.0)
+        } else {
+            GRAY
+        };
+        draw_rectangle(row.x, row.y, row.w, row.h, background);
+        let (my_color, opponent) = if game.white == player {
+            (PlayerColor::White, &game.black)
+        } else {
+            (PlayerColor::Black, &game.white)
+        };
+        let left = game.deadline.saturating_sub(now);
+        let time_left = format!("{}d {}h left", left / 86400, left % 86400 / 3600);
+        let status = match game.result {
+            None if game.awaits_answer_from(player) => format!("Challenged you, {}", time_left),
+            None if game.challenger.is_some() => format!("Not accepted yet, {}", time_left),
+            Some(GameResult::Win(winner)) if winner == my_color => "You won".to_string(),
+            Some(GameResult::Win(_)) => "You lost".to_string(),
+            Some(GameResult::Draw(_)) => "Draw".to_string(),
+            None => {
+                let whose = if your_turn { "Your move" } else { "Their move" };
+                format!("{}, {}", whose, time_left)
+            }
+        };
+        draw_text(
+            &format!("vs {}", opponent),
+            row.x + 10.0,
+            row.y + 27.0,
+            25.0,
+            BLACK,
+        );
+        draw_text(&status, row.x + 300.0, row.y + 27.0, 25.0, BLACK);
+        draw_text(
+            &format!("{} moves", game.moves),
+            row.x + 580.0,
+            row.y + 27.0,
+            20.0,
+            DARKGRAY,
+        );
+    }
+}
+
+pub fn draw_standings(standings: Option<&TournamentStandings>, error: Option<&str>) {
+    clear_background(LIGHTGRAY);
+    if let Some(error) = error {
+        draw_text(error, 100.0, 150.0, 25.0, RED);
+    }
+    let Some(standings) = standings else {
+        draw_text("Loading tournament...", 100.0, 80.0, 40.0, BLACK);
+        return;
+    };
+
+    draw_text(
+        &format!("Tournament {}", standings.name),
+        100.0,
+        80.0,
+        40.0,
+        BLACK,
+    );
+    let status = if standings.finished {
+        "Finished".to_string()
+    } else if standings.round == 0 {
+        format!(
+            "Registration open, waiting for {} to start it",
+            standings.organizer
+        )
+    } else {
+        format!(
+            "Round {} of {}, waiting for your next game",
+            standings.round, standings.total_rounds
+        )
+    };
+    draw_text(&status, 100.0, 115.0, 20.0, DARKGRAY);
+
+    let columns = [100.0, 150.0, 450.0, 570.0, 690.0];
+    for (x, heading) in columns
+        .iter()
+        .zip(["#", "Player", "Points", "Buchholz", "S-B"])
+    {
+        draw_text(heading, *x, 180.0, 22.0, DARKGRAY);
+    }
+    for (i, row) in standings.rows.iter().enumerate() {
+        let y = 215.0 + 30.0 * i as f32;
+        if y > screen_height() {
+            break;
+        }
+        let cells = [
+            row.rank.to_string(),
+            row.name.clone(),
+            format!("{:.1}", row.points),
+            format!("{:.1}", row.buchholz),
+            format!("{:.2}", row.sonneborn_berger),
+        ];
+        for (x, cell) in columns.iter().zip(&cells) {
+            draw_text(cell, *x, y, 22.0, BLACK);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RematchState {
+    Available,
+    Offered,
+    Received,
+}
+
+/// Where the rematch button sits, to the left of the board.
+pub fn rematch_button_rect(offset_x: f32, offset_y: f32) -> Rect {
+    Rect::new(offset_x - 260.0, offset_y + 70.0, 220.0, 40.0)
+}
+
+/// Draws the match score and, once the game is over, the rematch button.
+pub fn draw_match_panel(
+    score: &MatchScore,
+    rematch: Option<RematchState>,
+    offset_x: f32,
+    offset_y: f32,
+) {
+    let ui_x = offset_x - 260.0;
+    draw_text(
+        &format!("Match (best of {})", MATCH_LENGTH),
+        ui_x,
+        offset_y + 20.0,
+        25.0,
+        BLACK,
+    );
+    draw_text(
+        &format!("You {} - {} Opponent", score.you, score.opponent),
+        ui_x,
+        offset_y + 50.0,
+        25.0,
+        BLACK,
+    );
+
+    let Some(rematch) = rematch else {
+        return;
+    };
+    let button = rematch_button_rect(offset_x, offset_y);
+    let (label, color) = match rematch {
+        RematchState::Available => ("Rematch", DARKGREEN),
+        RematchState::Offered => ("Rematch offered...", GRAY),
+        RematchState::Received => ("Accept rematch", ORANGE),
+    };
+    draw_rectangle(button.x, button.y, button.w, button.h, color);
+    draw_text(label, button.x + 12.0, button.y + 27.0, 25.0, WHITE);
+
+    if score.is_decided() {
+        let verdict = if score.you > score.opponent {
+            "You won the match!"
+        } else if score.you < score.opponent {
+            "You lost the match"
+        } else {
+            "The match is drawn"
+        };
+        draw_text(verdict, ui_x, button.y + button.h + 30.0, 25.0, RED);
+        draw_text(
+            "A rematch starts a new match",
+            ui_x,
+            button.y + button.h + 55.0,
+            20.0,
+            DARKGRAY,
+        );
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TakebackState {
+    Available,
+    Requested,
+    Received,
+}
+
+/// Where the take-back button sits while a game is in progress, in the
+/// rematch button's place.
+pub fn takeback_button_rect(offset_x: f32, offset_y: f32) -> Rect {
+    rematch_button_rect(offset_x, offset_y)
+}
+
+/// Where the button to turn down the opponent's take-back request sits.
+pub fn decline_takeback_button_rect(offset_x: f32, offset_y: f32) -> Rect {
+    let accept = takeback_button_rect(offset_x, offset_y);
+    Rect::new(accept.x, accept.y + accept.h + 10.0, accept.w, accept.h)
+}
+
+pub fn draw_takeback_buttons(takeback: TakebackState, offset_x: f32, offset_y: f32) {
+    let button = takeback_button_rect(offset_x, offset_y);
+    let (label, color) = match takeback {
+        TakebackState::Available => ("Take back", DARKGREEN),
+        TakebackState::Requested => ("Take-back asked...", GRAY),
+        TakebackState::Received => ("Accept take-back", ORANGE),
+    };
+    draw_rectangle(button.x, button.y, button.w, button.h, color);
+    draw_text(label, button.x + 12.0, button.y + 27.0, 25.0, WHITE);
+
+    if takeback == TakebackState::Received {
+        let decline = decline_takeback_button_rect(offset_x, offset_y);
+        draw_rectangle(decline.x, decline.y, decline.w, decline.h, GRAY);
+        draw_text("Decline", decline.x + 12.0, decline.y + 27.0, 25.0, WHITE);
+    }
+}
+
+pub fn draw_connection_status(
+    latency: Option<Duration>,
+    notice: Option<&str>,
+    offset_x: f32,
+    offset_y: f32,
+) {
+    let ui_x = offset_x + SQUARE_SIZE * 8.0 + 20.0;
+    let ui_y = offset_y + 395.0;
+
+    let latency_text = match latency {
+        Some(rtt) => format!("Ping: {} ms", rtt.as_millis()),
+        None => "Ping: --".to_string(),
+    };
+    draw_text(&latency_text, ui_x, ui_y, 20.0, DARKGRAY);
+    if let Some(notice) = notice {
+        draw_text(notice, ui_x + 110.0, ui_y, 20.0, RED);
+    }
+}
+
+pub fn draw_account_panel(
+    my_rating: Option<&RatingEntry>,
+    leaderboard: &[RatingEntry],
+    offset_x: f32,
+    offset_y: f32,
+) {
+    let ui_x = offset_x + SQUARE_SIZE * 8.0 + 20.0;
+    let mut ui_y = offset_y + 420.0;
+
+    let rating_text = match my_rating {
+        Some(entry) => format!(
+            "Rating: {:.0} (+/- {:.0})",
+            entry.rating,
+            entry.deviation * 2.0
+        ),
+        None => "Rating: unrated".to_string(),
+    };
+    draw_text(&rating_text, ui_x, ui_y, 25.0, BLACK);
+    ui_y += 25.0;
+
+    if leaderboard.is_empty() {
+        draw_text("Press L for leaderboard", ui_x, ui_y, 20.0, DARKGRAY);
+        return;
+    }
+
+    for (i, entry) in leaderboard.iter().enumerate() {
+        draw_text(
+            &format!(
+                "{}. {} {:.0} ({}/{}/{})",
+                i + 1,
+                entry.name,
+                entry.rating,
+                entry.wins,
+                entry.losses,
+                entry.draws
+            ),
+            ui_x,
+            ui_y,
+            20.0,
+            DARKGRAY,
+        );
+        ui_y += 20.0;
+    }
+}
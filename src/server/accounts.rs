@@ -0,0 +1,160 @@
+use super::rating::Rating;
+use crate::game::GameResult;
+use crate::network::RatingEntry;
+use crate::types::PlayerColor;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const MAX_NAME_LEN: usize = 20;
+const MAX_LEADERBOARD_SIZE: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Account {
+    pub name: String,
+    token_hash: String,
+    pub rating: Rating,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl Account {
+    fn entry(&self) -> RatingEntry {
+        RatingEntry {
+            name: self.name.clone(),
+            rating: self.rating.rating,
+            deviation: self.rating.deviation,
+            wins: self.wins,
+            losses: self.losses,
+            draws: self.draws,
+        }
+    }
+}
+
+/// Accounts persisted as a single JSON file, rewritten on every change.
+pub struct AccountStore {
+    path: PathBuf,
+    accounts: HashMap<String, Account>,
+}
+
+impl AccountStore {
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let accounts = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, accounts })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&self.accounts)?)?;
+        fs::rename(tmp_path, &self.path)
+    }
+
+    /// Creates an account and returns its secret token. Only a hash of the
+    /// token is kept on the server.
+    pub fn register(&mut self, name: &str) -> Result<String, String> {
+        if name.is_empty()
+            || name.len() > MAX_NAME_LEN
+            || !name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        {
+            return Err("Invalid account name".into());
+        }
+        if self.accounts.contains_key(name) {
+            return Err("Account name already taken".into());
+        }
+
+        let mut bytes = [0u8; 16];
+        rand::rng().fill_bytes(&mut bytes);
+        let token = to_hex(&bytes);
+
+        self.accounts.insert(
+            name.to_string(),
+            Account {
+                name: name.to_string(),
+                token_hash: hash_token(&token),
+                rating: Rating::default(),
+                wins: 0,
+                losses: 0,
+                draws: 0,
+            },
+        );
+        self.save().map_err(|e| e.to_string())?;
+        Ok(token)
+    }
+
+    pub fn verify(&self, name: &str, token: &str) -> bool {
+        self.accounts
+            .get(name)
+            .is_some_and(|account| account.token_hash == hash_token(token))
+    }
+
+    pub fn entry(&self, name: &str) -> Option<RatingEntry> {
+        self.accounts.get(name).map(Account::entry)
+    }
+
+    pub fn leaderboard(&self, limit: usize) -> Vec<RatingEntry> {
+        let mut entries: Vec<RatingEntry> = self.accounts.values().map(Account::entry).collect();
+        entries.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+        entries.truncate(limit.min(MAX_LEADERBOARD_SIZE));
+        entries
+    }
+
+    pub fn record_result(
+        &mut self,
+        white: &str,
+        black: &str,
+        result: GameResult,
+    ) -> io::Result<()> {
+        let (Some(white_account), Some(black_account)) =
+            (self.accounts.get(white), self.accounts.get(black))
+        else {
+            return Ok(());
+        };
+
+        let white_score = match result {
+            GameResult::Win(PlayerColor::White) => 1.0,
+            GameResult::Win(PlayerColor::Black) => 0.0,
+            GameResult::Draw(_) => 0.5,
+        };
+        let white_rating = white_account
+            .rating
+            .update(&black_account.rating, white_score);
+        let black_rating = black_account
+            .rating
+            .update(&white_account.rating, 1.0 - white_score);
+
+        for (name, rating, score) in [
+            (white, white_rating, white_score),
+            (black, black_rating, 1.0 - white_score),
+        ] {
+            if let Some(account) = self.accounts.get_mut(name) {
+                account.rating = rating;
+                if score == 1.0 {
+                    account.wins += 1;
+                } else if score == 0.0 {
+                    account.losses += 1;
+                } else {
+                    account.draws += 1;
+                }
+            }
+        }
+        self.save()
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,125 @@
+use crate::game::GameResult;
+use crate::network::GameAction;
+use crate::types::PlayerColor;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedAction {
+    pub color: PlayerColor,
+    pub action: GameAction,
+    /// Milliseconds since the Unix epoch.
+    pub at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameSummary {
+    pub id: u64,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    pub result: GameResult,
+    /// Seconds since the Unix epoch.
+    pub started_at: u64,
+    pub finished_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GameRecord {
+    #[serde(flatten)]
+    pub summary: GameSummary,
+    pub room: String,
+    pub white_points: i32,
+    pub black_points: i32,
+    pub actions: Vec<RecordedAction>,
+}
+
+/// Finished games stored as one JSON file each, plus an append-only
+/// `index.jsonl` of summaries that is loaded into memory at startup.
+pub struct GameArchive {
+    dir: PathBuf,
+    index: Vec<GameSummary>,
+}
+
+impl GameArchive {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut index = Vec::new();
+        match fs::File::open(dir.join("index.jsonl")) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if line.is_empty() {
+                        continue;
+                    }
+                    index.push(serde_json::from_str(&line)?);
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        Ok(Self { dir, index })
+    }
+
+    pub fn next_id(&self) -> u64 {
+        self.index.last().map_or(1, |summary| summary.id + 1)
+    }
+
+    pub fn save(&mut self, record: &GameRecord) -> io::Result<()> {
+        let path = self.dir.join(format!("{}.json", record.summary.id));
+        fs::write(path, serde_json::to_vec_pretty(record)?)?;
+
+        let mut index_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join("index.jsonl"))?;
+        writeln!(index_file, "{}", serde_json::to_string(&record.summary)?)?;
+
+        self.index.push(record.summary.clone());
+        Ok(())
+    }
+
+    /// Lists games, newest first, optionally filtered by player name and a
+    /// `[since, until]` range on the finish time.
+    pub fn list(
+        &self,
+        player: Option<&str>,
+        since: Option<u64>,
+        until: Option<u64>,
+        limit: usize,
+    ) -> Vec<GameSummary> {
+        self.index
+            .iter()
+            .rev()
+            .filter(|summary| {
+                player.is_none_or(|p| {
+                    summary.white.as_deref() == Some(p) || summary.black.as_deref() == Some(p)
+                })
+            })
+            .filter(|summary| since.is_none_or(|t| summary.finished_at >= t))
+            .filter(|summary| until.is_none_or(|t| summary.finished_at <= t))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn load(&self, id: u64) -> io::Result<Option<GameRecord>> {
+        match fs::read(self.dir.join(format!("{}.json", id))) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+pub fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
@@ -0,0 +1,580 @@
+use crate::network::DISCOVERY_PORT;
+use clap::Parser;
+use serde::Deserialize;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
+
+const DEFAULT_BIND: &str = "0.0.0.0:8080";
+const DEFAULT_WS_BIND: &str = "0.0.0.0:8082";
+const DEFAULT_HTTP_BIND: &str = "0.0.0.0:8081";
+const DEFAULT_MAX_ROOMS: usize = 1000;
+const DEFAULT_ROOM_TIMEOUT_SECS: u64 = 600; // 10 minutes
+const DEFAULT_RATE_LIMIT_WINDOW_SECS: u64 = 60;
+const DEFAULT_MAX_REQUESTS_PER_WINDOW: u32 = 30;
+const DEFAULT_MESSAGES_PER_SEC: u32 = 10;
+const DEFAULT_MESSAGE_BURST: u32 = 20;
+const DEFAULT_MAX_RATE_VIOLATIONS: u32 = 10;
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 5;
+const DEFAULT_MAX_MISSED_HEARTBEATS: u32 = 3;
+const DEFAULT_MAX_MSG_SIZE: usize = 8 * 1024; // 8KB
+const DEFAULT_LOG_LEVEL: &str = "info";
+const DEFAULT_LOG_FORMAT: &str = "text";
+const DEFAULT_ROOM_NAME_MIN_LEN: usize = 1;
+const DEFAULT_ROOM_NAME_MAX_LEN: usize = 20;
+const DEFAULT_ACCOUNTS_FILE: &str = "accounts.json";
+const DEFAULT_GAMES_DIR: &str = "games";
+const DEFAULT_ROOMS_FILE: &str = "rooms.json";
+const DEFAULT_TOURNAMENTS_FILE: &str = "tournaments.json";
+const DEFAULT_CORRESPONDENCE_FILE: &str = "correspondence.json";
+const DEFAULT_DISPLAY_NAME: &str = "Ascension Chess";
+
+// Frames must at least fit the largest reply the server sends (a full leaderboard).
+const MIN_MSG_SIZE: usize = 8 * 1024;
+const MAX_MSG_SIZE: usize = 1024 * 1024;
+const MAX_ROOM_NAME_LEN: usize = 64;
+const MAX_DISPLAY_NAME_LEN: usize = 40;
+
+/// Command line flags. Every setting can also come from an `ASCENSION_*`
+/// environment variable or the TOML file given by `--config`; flags win over
+/// the environment, which wins over the file, which wins over the defaults.
+#[derive(Parser, Debug, Default)]
+#[command(version, about, long_about = None)]
+pub struct Args {
+    /// TOML file with server settings
+    #[arg(long, env = "ASCENSION_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Address for the length-delimited TCP protocol [default: 0.0.0.0:8080]
+    #[arg(long, env = "ASCENSION_BIND")]
+    bind: Option<String>,
+
+    /// Address for the WebSocket protocol [default: 0.0.0.0:8082]
+    #[arg(long, env = "ASCENSION_WS_BIND")]
+    ws_bind: Option<String>,
+
+    /// Address for the HTTP game archive [default: 0.0.0.0:8081]
+    #[arg(long, env = "ASCENSION_HTTP_BIND")]
+    http_bind: Option<String>,
+
+    /// Maximum number of rooms open at once [default: 1000]
+    #[arg(long, env = "ASCENSION_MAX_ROOMS")]
+    max_rooms: Option<usize>,
+
+    /// Seconds of inactivity before a room is removed [default: 600]
+    #[arg(long, env = "ASCENSION_ROOM_TIMEOUT_SECS")]
+    room_timeout_secs: Option<u64>,
+
+    /// Length of the per-IP connection rate limit window in seconds [default: 60]
+    #[arg(long, env = "ASCENSION_RATE_LIMIT_WINDOW_SECS")]
+    rate_limit_window_secs: Option<u64>,
+
+    /// New connections allowed per IP in each window [default: 30]
+    #[arg(long, env = "ASCENSION_MAX_REQUESTS_PER_WINDOW")]
+    max_requests_per_window: Option<u32>,
+
+    /// Messages each connection may send per second once its burst is spent [default: 10]
+    #[arg(long, env = "ASCENSION_MESSAGES_PER_SEC")]
+    messages_per_sec: Option<u32>,
+
+    /// Messages each connection may send back to back [default: 20]
+    #[arg(long, env = "ASCENSION_MESSAGE_BURST")]
+    message_burst: Option<u32>,
+
+    /// Rate-limited messages, less one per 10 s within the limit, after which a connection is dropped [default: 10]
+    #[arg(long, env = "ASCENSION_MAX_RATE_VIOLATIONS")]
+    max_rate_violations: Option<u32>,
+
+    /// Expect a PROXY protocol v1 header on game and WebSocket connections
+    /// and use the client address from it for bans and limits [default: false]
+    #[arg(long, env = "ASCENSION_PROXY_PROTOCOL")]
+    proxy_protocol: Option<bool>,
+
+    /// IP addresses refused outright, comma separated or repeated [default: none]
+    #[arg(long = "ban-ip", env = "ASCENSION_BANNED_IPS", value_delimiter = ',')]
+    banned_ips: Vec<IpAddr>,
+
+    /// Seconds between pings to clients that support heartbeats [default: 5]
+    #[arg(long, env = "ASCENSION_HEARTBEAT_INTERVAL_SECS")]
+    heartbeat_interval_secs: Option<u64>,
+
+    /// Unanswered pings in a row before a client is disconnected [default: 3]
+    #[arg(long, env = "ASCENSION_MAX_MISSED_HEARTBEATS")]
+    max_missed_heartbeats: Option<u32>,
+
+    /// Largest accepted message in bytes [default: 8192]
+    #[arg(long, env = "ASCENSION_MAX_MSG_SIZE")]
+    max_msg_size: Option<usize>,
+
+    /// One of off, error, warn, info, debug, trace; RUST_LOG overrides it [default: info]
+    #[arg(long, env = "ASCENSION_LOG_LEVEL")]
+    log_level: Option<String>,
+
+    /// Log output, text or json [default: text]
+    #[arg(long, env = "ASCENSION_LOG_FORMAT")]
+    log_format: Option<String>,
+
+    /// Bearer token required by the HTTP /status page, which is disabled without one
+    #[arg(long, env = "ASCENSION_ADMIN_TOKEN")]
+    admin_token: Option<String>,
+
+    /// Shortest allowed room name [default: 1]
+    #[arg(long, env = "ASCENSION_ROOM_NAME_MIN_LEN")]
+    room_name_min_len: Option<usize>,
+
+    /// Longest allowed room name [default: 20]
+    #[arg(long, env = "ASCENSION_ROOM_NAME_MAX_LEN")]
+    room_name_max_len: Option<usize>,
+
+    /// Characters allowed in room names besides letters and digits [default: none]
+    #[arg(long, env = "ASCENSION_ROOM_NAME_EXTRA_CHARS")]
+    room_name_extra_chars: Option<String>,
+
+    /// File storing player accounts [default: accounts.json]
+    #[arg(long, env = "ASCENSION_ACCOUNTS_FILE")]
+    accounts_file: Option<PathBuf>,
+
+    /// Directory storing finished games [default: games]
+    #[arg(long, env = "ASCENSION_GAMES_DIR")]
+    games_dir: Option<PathBuf>,
+
+    /// File where open rooms are saved on shutdown and restored from on startup [default: rooms.json]
+    #[arg(long, env = "ASCENSION_ROOMS_FILE")]
+    rooms_file: Option<PathBuf>,
+
+    /// File storing tournaments [default: tournaments.json]
+    #[arg(long, env = "ASCENSION_TOURNAMENTS_FILE")]
+    tournaments_file: Option<PathBuf>,
+
+    /// File storing correspondence games [default: correspondence.json]
+    #[arg(long, env = "ASCENSION_CORRESPONDENCE_FILE")]
+    correspondence_file: Option<PathBuf>,
+
+    /// Answer clients looking for servers on the local network [default: false]
+    #[arg(long, env = "ASCENSION_LAN_DISCOVERY")]
+    lan_discovery: Option<bool>,
+
+    /// UDP address for LAN discovery queries [default: 0.0.0.0:8083]
+    #[arg(long, env = "ASCENSION_DISCOVERY_BIND")]
+    discovery_bind: Option<String>,
+
+    /// Name players see when they find the server on the local network [default: Ascension Chess]
+    #[arg(long, env = "ASCENSION_DISPLAY_NAME")]
+    display_name: Option<String>,
+
+    /// PEM certificate chain; with --tls-key, game connections require TLS
+    #[arg(long, env = "ASCENSION_TLS_CERT")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert
+    #[arg(long, env = "ASCENSION_TLS_KEY")]
+    tls_key: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    bind: Option<String>,
+    ws_bind: Option<String>,
+    http_bind: Option<String>,
+    max_rooms: Option<usize>,
+    room_timeout_secs: Option<u64>,
+    rate_limit_window_secs: Option<u64>,
+    max_requests_per_window: Option<u32>,
+    messages_per_sec: Option<u32>,
+    message_burst: Option<u32>,
+    max_rate_violations: Option<u32>,
+    proxy_protocol: Option<bool>,
+    banned_ips: Option<Vec<IpAddr>>,
+    heartbeat_interval_secs: Option<u64>,
+    max_missed_heartbeats: Option<u32>,
+    max_msg_size: Option<usize>,
+    log_level: Option<String>,
+    log_format: Option<String>,
+    admin_token: Option<String>,
+    room_name_min_len: Option<usize>,
+    room_name_max_len: Option<usize>,
+    room_name_extra_chars: Option<String>,
+    accounts_file: Option<PathBuf>,
+    games_dir: Option<PathBuf>,
+    rooms_file: Option<PathBuf>,
+    tournaments_file: Option<PathBuf>,
+    correspondence_file: Option<PathBuf>,
+    lan_discovery: Option<bool>,
+    discovery_bind: Option<String>,
+    display_name: Option<String>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind: SocketAddr,
+    pub ws_bind: SocketAddr,
+    pub http_bind: SocketAddr,
+    pub max_rooms: usize,
+    pub room_timeout: Duration,
+    pub rate_limit_window: Duration,
+    pub max_requests_per_window: u32,
+    pub messages_per_sec: u32,
+    pub message_burst: u32,
+    pub max_rate_violations: u32,
+    /// Connections start with a PROXY header naming the real client.
+    pub proxy_protocol: bool,
+    pub banned_ips: Vec<IpAddr>,
+    pub heartbeat_interval: Duration,
+    pub max_missed_heartbeats: u32,
+    pub max_msg_size: usize,
+    pub log_level: LevelFilter,
+    pub log_format: LogFormat,
+    pub admin_token: Option<String>,
+    pub room_name_min_len: usize,
+    pub room_name_max_len: usize,
+    pub room_name_extra_chars: String,
+    pub accounts_file: PathBuf,
+    pub games_dir: PathBuf,
+    pub rooms_file: PathBuf,
+    pub tournaments_file: PathBuf,
+    pub correspondence_file: PathBuf,
+    /// Where to answer LAN discovery queries, if at all.
+    pub discovery_bind: Option<SocketAddr>,
+    pub display_name: String,
+    /// Certificate chain and private key for TLS on the game ports, if set.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+}
+
+impl Config {
+    /// Builds the configuration from flags, environment and config file,
+    /// returning a readable message for the first invalid setting.
+    pub fn load(args: Args) -> Result<Self, String> {
+        let file = match &args.config {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("Cannot read config file {}: {}", path.display(), e))?;
+                toml::from_str(&text)
+                    .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?
+            }
+            None => FileConfig::default(),
+        };
+
+        let config = Config {
+            bind: parse_addr("bind", args.bind.or(file.bind), DEFAULT_BIND)?,
+            ws_bind: parse_addr("ws_bind", args.ws_bind.or(file.ws_bind), DEFAULT_WS_BIND)?,
+            http_bind: parse_addr(
+                "http_bind",
+                args.http_bind.or(file.http_bind),
+                DEFAULT_HTTP_BIND,
+            )?,
+            max_rooms: args
+                .max_rooms
+                .or(file.max_rooms)
+                .unwrap_or(DEFAULT_MAX_ROOMS),
+            room_timeout: Duration::from_secs(
+                args.room_timeout_secs
+                    .or(file.room_timeout_secs)
+                    .unwrap_or(DEFAULT_ROOM_TIMEOUT_SECS),
+            ),
+            rate_limit_window: Duration::from_secs(
+                args.rate_limit_window_secs
+                    .or(file.rate_limit_window_secs)
+                    .unwrap_or(DEFAULT_RATE_LIMIT_WINDOW_SECS),
+            ),
+            max_requests_per_window: args
+                .max_requests_per_window
+                .or(file.max_requests_per_window)
+                .unwrap_or(DEFAULT_MAX_REQUESTS_PER_WINDOW),
+            messages_per_sec: args
+                .messages_per_sec
+                .or(file.messages_per_sec)
+                .unwrap_or(DEFAULT_MESSAGES_PER_SEC),
+            message_burst: args
+                .message_burst
+                .or(file.message_burst)
+                .unwrap_or(DEFAULT_MESSAGE_BURST),
+            max_rate_violations: args
+                .max_rate_violations
+                .or(file.max_rate_violations)
+                .unwrap_or(DEFAULT_MAX_RATE_VIOLATIONS),
+            proxy_protocol: args.proxy_protocol.or(file.proxy_protocol).unwrap_or(false),
+            banned_ips: if args.banned_ips.is_empty() {
+                file.banned_ips.unwrap_or_default()
+            } else {
+                args.banned_ips
+            },
+            heartbeat_interval: Duration::from_secs(
+                args.heartbeat_interval_secs
+                    .or(file.heartbeat_interval_secs)
+                    .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECS),
+            ),
+            max_missed_heartbeats: args
+                .max_missed_heartbeats
+                .or(file.max_missed_heartbeats)
+                .unwrap_or(DEFAULT_MAX_MISSED_HEARTBEATS),
+            max_msg_size: args
+                .max_msg_size
+                .or(file.max_msg_size)
+                .unwrap_or(DEFAULT_MAX_MSG_SIZE),
+            log_level: {
+                let level = args
+                    .log_level
+                    .or(file.log_level)
+                    .unwrap_or_else(|| DEFAULT_LOG_LEVEL.to_string());
+                level
+                    .parse()
+                    .map_err(|_| format!("log_level: unknown level '{}'", level))?
+            },
+            log_format: {
+                let format = args
+                    .log_format
+                    .or(file.log_format)
+                    .unwrap_or_else(|| DEFAULT_LOG_FORMAT.to_string());
+                match format.as_str() {
+                    "text" => LogFormat::Text,
+                    "json" => LogFormat::Json,
+                    _ => {
+                        return Err(format!(
+                            "log_format: expected text or json, got '{}'",
+                            format
+                        ));
+                    }
+                }
+            },
+            admin_token: args
+                .admin_token
+                .or(file.admin_token)
+                .filter(|t| !t.is_empty()),
+            room_name_min_len: args
+                .room_name_min_len
+                .or(file.room_name_min_len)
+                .unwrap_or(DEFAULT_ROOM_NAME_MIN_LEN),
+            room_name_max_len: args
+                .room_name_max_len
+                .or(file.room_name_max_len)
+                .unwrap_or(DEFAULT_ROOM_NAME_MAX_LEN),
+            room_name_extra_chars: args
+                .room_name_extra_chars
+                .or(file.room_name_extra_chars)
+                .unwrap_or_default(),
+            accounts_file: args
+                .accounts_file
+                .or(file.accounts_file)
+                .unwrap_or_else(|| DEFAULT_ACCOUNTS_FILE.into()),
+            games_dir: args
+                .games_dir
+                .or(file.games_dir)
+                .unwrap_or_else(|| DEFAULT_GAMES_DIR.into()),
+            rooms_file: args
+                .rooms_file
+                .or(file.rooms_file)
+                .unwrap_or_else(|| DEFAULT_ROOMS_FILE.into()),
+            tournaments_file: args
+                .tournaments_file
+                .or(file.tournaments_file)
+                .unwrap_or_else(|| DEFAULT_TOURNAMENTS_FILE.into()),
+            correspondence_file: args
+                .correspondence_file
+                .or(file.correspondence_file)
+                .unwrap_or_else(|| DEFAULT_CORRESPONDENCE_FILE.into()),
+            discovery_bind: if args.lan_discovery.or(file.lan_discovery).unwrap_or(false) {
+                Some(parse_addr(
+                    "discovery_bind",
+                    args.discovery_bind.or(file.discovery_bind),
+                    &format!("0.0.0.0:{}", DISCOVERY_PORT),
+                )?)
+            } else {
+                None
+            },
+            display_name: args
+                .display_name
+                .or(file.display_name)
+                .unwrap_or_else(|| DEFAULT_DISPLAY_NAME.to_string()),
+            tls_cert: args.tls_cert.or(file.tls_cert),
+            tls_key: args.tls_key.or(file.tls_key),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Settings for a server run by the desktop client in `--host` mode:
+    /// games on `bind`, answering LAN discovery as `display_name`, with the
+    /// other listeners on loopback ports nobody needs to know and its files
+    /// in `data_dir`.
+    pub fn hosted(bind: SocketAddr, display_name: &str, data_dir: &Path) -> Result<Self, String> {
+        Config::load(Args {
+            bind: Some(bind.to_string()),
+            ws_bind: Some("127.0.0.1:0".into()),
+            http_bind: Some("127.0.0.1:0".into()),
+            lan_discovery: Some(true),
+            display_name: Some(display_name.to_string()),
+            accounts_file: Some(data_dir.join(DEFAULT_ACCOUNTS_FILE)),
+            games_dir: Some(data_dir.join(DEFAULT_GAMES_DIR)),
+            rooms_file: Some(data_dir.join(DEFAULT_ROOMS_FILE)),
+            tournaments_file: Some(data_dir.join(DEFAULT_TOURNAMENTS_FILE)),
+            correspondence_file: Some(data_dir.join(DEFAULT_CORRESPONDENCE_FILE)),
+            ..Args::default()
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.max_rooms == 0 {
+            return Err("max_rooms must be at least 1".into());
+        }
+        if self.room_timeout.is_zero() {
+            return Err("room_timeout_secs must be at least 1".into());
+        }
+        if self.rate_limit_window.is_zero() {
+            return Err("rate_limit_window_secs must be at least 1".into());
+        }
+        if self.max_requests_per_window == 0 {
+            return Err("max_requests_per_window must be at least 1".into());
+        }
+        if self.messages_per_sec == 0 {
+            return Err("messages_per_sec must be at least 1".into());
+        }
+        if self.message_burst == 0 {
+            return Err("message_burst must be at least 1".into());
+        }
+        if self.max_rate_violations == 0 {
+            return Err("max_rate_violations must be at least 1".into());
+        }
+        if self.heartbeat_interval.is_zero() {
+            return Err("heartbeat_interval_secs must be at least 1".into());
+        }
+        if self.max_missed_heartbeats == 0 {
+            return Err("max_missed_heartbeats must be at least 1".into());
+        }
+        if !(MIN_MSG_SIZE..=MAX_MSG_SIZE).contains(&self.max_msg_size) {
+            return Err(format!(
+                "max_msg_size must be between {} and {} bytes",
+                MIN_MSG_SIZE, MAX_MSG_SIZE
+            ));
+        }
+        if self.room_name_min_len == 0 || self.room_name_min_len > self.room_name_max_len {
+            return Err(
+                "room_name_min_len must be at least 1 and at most room_name_max_len".into(),
+            );
+        }
+        if self.room_name_max_len > MAX_ROOM_NAME_LEN {
+            return Err(format!(
+                "room_name_max_len must be at most {}",
+                MAX_ROOM_NAME_LEN
+            ));
+        }
+        if self.display_name.trim().is_empty()
+            || self.display_name.chars().count() > MAX_DISPLAY_NAME_LEN
+        {
+            return Err(format!(
+                "display_name must be between 1 and {} characters",
+                MAX_DISPLAY_NAME_LEN
+            ));
+        }
+        if self.tls_cert.is_some() != self.tls_key.is_some() {
+            return Err("tls_cert and tls_key must be set together".into());
+        }
+        if let Some(c) = self
+            .room_name_extra_chars
+            .chars()
+            .find(|c| c.is_whitespace() || c.is_control())
+        {
+            return Err(format!(
+                "room_name_extra_chars may not contain whitespace or control characters ({:?})",
+                c
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.banned_ips.contains(&ip)
+    }
+
+    pub fn is_valid_room_name(&self, name: &str) -> bool {
+        let len = name.chars().count();
+        len >= self.room_name_min_len
+            && len <= self.room_name_max_len
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || self.room_name_extra_chars.contains(c))
+    }
+}
+
+fn parse_addr(name: &str, value: Option<String>, default: &str) -> Result<SocketAddr, String> {
+    let value = value.unwrap_or_else(|| default.to_string());
+    value
+        .parse()
+        .map_err(|_| format!("{}: '{}' is not a valid socket address", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(args: &[&str]) -> Result<Config, String> {
+        Config::load(Args::parse_from(["server"].iter().chain(args)))
+    }
+
+    /// Writes `contents` to a config file of its own for the test `name`.
+    fn config_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "ascension-config-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn flags_win_over_environment_over_file_over_defaults() {
+        let path = config_file("precedence", "room_timeout_secs = 30\n");
+        let file = format!("--config={}", path.display());
+        let timeout = |args: &[&str]| load(args).unwrap().room_timeout.as_secs();
+
+        assert_eq!(timeout(&[]), DEFAULT_ROOM_TIMEOUT_SECS);
+        assert_eq!(timeout(&[&file]), 30);
+        // SAFETY: no other test reads or writes this variable.
+        unsafe { std::env::set_var("ASCENSION_ROOM_TIMEOUT_SECS", "40") };
+        assert_eq!(timeout(&[&file]), 40);
+        assert_eq!(timeout(&[&file, "--room-timeout-secs=50"]), 50);
+        unsafe { std::env::remove_var("ASCENSION_ROOM_TIMEOUT_SECS") };
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn unknown_keys_in_the_file_are_refused() {
+        let path = config_file("unknown", "max_romos = 10\n");
+        let error = load(&[&format!("--config={}", path.display())]).unwrap_err();
+        assert!(error.contains("max_romos"), "{}", error);
+        fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn out_of_range_settings_are_refused() {
+        let error = load(&["--max-rooms=0"]).unwrap_err();
+        assert_eq!(error, "max_rooms must be at least 1");
+
+        let path = config_file("range", "max_rooms = 0\n");
+        let error = load(&[&format!("--config={}", path.display())]).unwrap_err();
+        assert_eq!(error, "max_rooms must be at least 1");
+        fs::remove_file(path).ok();
+
+        for size in [MIN_MSG_SIZE - 1, MAX_MSG_SIZE + 1] {
+            let error = load(&[&format!("--max-msg-size={}", size)]).unwrap_err();
+            assert!(
+                error.starts_with("max_msg_size must be between"),
+                "{}",
+                error
+            );
+        }
+        assert!(load(&[&format!("--max-msg-size={}", MAX_MSG_SIZE)]).is_ok());
+    }
+}
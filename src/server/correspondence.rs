@@ -0,0 +1,381 @@
+use super::archive::RecordedAction;
+use super::snapshot::RoomSnapshot;
+use crate::game::GameResult;
+use crate::network::CorrespondenceInfo;
+use crate::types::PlayerColor;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+pub const MAX_DAYS_PER_MOVE: u32 = 30;
+/// Unfinished games a player may have at once; also caps the size of a
+/// listing so it fits in one message.
+const MAX_ACTIVE_GAMES: usize = 30;
+/// Finished games stay listed this long before being dropped from the store.
+const FINISHED_RETENTION_SECS: u64 = 7 * 24 * 60 * 60;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Serialize, Deserialize)]
+struct CorrespondenceGame {
+    white: String,
+    black: String,
+    days_per_move: u32,
+    started_at: u64,
+    actions: Vec<RecordedAction>,
+    turn: PlayerColor,
+    /// Unix time in seconds when the player to move got the turn.
+    turn_started_at: u64,
+    result: Option<GameResult>,
+    finished_at: Option<u64>,
+    /// Account that sent the challenge, until the opponent accepts it. The
+    /// game cannot be played before then, and lapses at the deadline.
+    #[serde(default)]
+    challenger: Option<String>,
+}
+
+impl CorrespondenceGame {
+    fn deadline(&self) -> u64 {
+        self.turn_started_at + self.days_per_move as u64 * SECS_PER_DAY
+    }
+
+    fn has_player(&self, player: &str) -> bool {
+        self.white == player || self.black == player
+    }
+
+    fn snapshot(&self, room: &str) -> RoomSnapshot {
+        RoomSnapshot {
+            name: room.to_string(),
+            white_account: Some(self.white.clone()),
+            black_account: Some(self.black.clone()),
+            password: None,
+            started_at: self.started_at,
+            actions: self.actions.clone(),
+            score: Default::default(),
+            public: false,
+            tournament: None,
+            white_token: None,
+            black_token: None,
+        }
+    }
+
+    fn info(&self, room: &str) -> CorrespondenceInfo {
+        CorrespondenceInfo {
+            room: room.to_string(),
+            white: self.white.clone(),
+            black: self.black.clone(),
+            days_per_move: self.days_per_move,
+            turn: self.turn,
+            deadline: self.deadline(),
+            moves: self.actions.len() as u32,
+            result: self.result,
+            challenger: self.challenger.clone(),
+        }
+    }
+}
+
+/// Games that ran out of time, as returned by `CorrespondenceStore::expire`.
+#[derive(Default)]
+pub struct Expired {
+    pub forfeited: Vec<(RoomSnapshot, GameResult)>,
+    pub aborted: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct StoreFile {
+    next_id: u64,
+    games: HashMap<String, CorrespondenceGame>,
+}
+
+/// Correspondence games, kept on disk between moves so players can come and
+/// go. Each game is played in a room named after it, which is rebuilt from
+/// the stored actions whenever a player joins.
+pub struct CorrespondenceStore {
+    path: PathBuf,
+    data: StoreFile,
+}
+
+impl CorrespondenceStore {
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let data = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => StoreFile::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, data })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&self.data)?)?;
+        fs::rename(tmp_path, &self.path)
+    }
+
+    /// Stores a challenge from `challenger`, one of `white` and `black`,
+    /// which the other player has `days_per_move` days to accept.
+    pub fn create(
+        &mut self,
+        white: &str,
+        black: &str,
+        challenger: &str,
+        days_per_move: u32,
+        now: u64,
+    ) -> Result<CorrespondenceInfo, String> {
+        if !(1..=MAX_DAYS_PER_MOVE).contains(&days_per_move) {
+            return Err(format!(
+                "Days per move must be between 1 and {}",
+                MAX_DAYS_PER_MOVE
+            ));
+        }
+        let active = |player: &str| {
+            self.data
+                .games
+                .values()
+                .filter(|g| g.result.is_none() && g.has_player(player))
+                .count()
+        };
+        if active(white) >= MAX_ACTIVE_GAMES || active(black) >= MAX_ACTIVE_GAMES {
+            return Err("Too many correspondence games in progress".into());
+        }
+
+        self.data.next_id += 1;
+        let room = format!("c-{}", self.data.next_id);
+        let game = CorrespondenceGame {
+            white: white.to_string(),
+            black: black.to_string(),
+            days_per_move,
+            started_at: now,
+            actions: Vec::new(),
+            turn: PlayerColor::White,
+            turn_started_at: now,
+            result: None,
+            finished_at: None,
+            challenger: Some(challenger.to_string()),
+        };
+        let info = game.info(&room);
+        self.data.games.insert(room, game);
+        self.save().map_err(|e| e.to_string())?;
+        Ok(info)
+    }
+
+    /// Accepts the challenge to `player` in `room`, starting the clock of the
+    /// player with White.
+    pub fn accept(
+        &mut self,
+        room: &str,
+        player: &str,
+        now: u64,
+    ) -> Result<CorrespondenceInfo, String> {
+        let game = self.challenge_to(room, player)?;
+        if game.challenger.as_deref() == Some(player) {
+            return Err("Your opponent has not accepted the challenge yet".into());
+        }
+        game.challenger = None;
+        game.started_at = now;
+        game.turn_started_at = now;
+        let info = game.info(room);
+        self.save().map_err(|e| e.to_string())?;
+        Ok(info)
+    }
+
+    /// Drops the challenge in `room`, either declined by the challenged
+    /// player or withdrawn by the challenger.
+    pub fn decline(&mut self, room: &str, player: &str) -> Result<(), String> {
+        self.challenge_to(room, player)?;
+        self.data.games.remove(room);
+        self.save().map_err(|e| e.to_string())
+    }
+
+    fn challenge_to(
+        &mut self,
+        room: &str,
+        player: &str,
+    ) -> Result<&mut CorrespondenceGame, String> {
+        self.data
+            .games
+            .get_mut(room)
+            .filter(|game| game.challenger.is_some() && game.has_player(player))
+            .ok_or_else(|| "No correspondence challenge in that room".to_string())
+    }
+
+    /// Whether `room` holds a challenge that has not been accepted yet.
+    pub fn is_pending(&self, room: &str) -> bool {
+        self.data
+            .games
+            .get(room)
+            .is_some_and(|game| game.challenger.is_some())
+    }
+
+    /// The player's games, unfinished ones whose turn it is first.
+    pub fn games_of(&self, player: &str, my_turn_only: bool) -> Vec<CorrespondenceInfo> {
+        let mut games: Vec<CorrespondenceInfo> = self
+            .data
+            .games
+            .iter()
+            .filter(|(_, g)| g.has_player(player))
+            .map(|(room, g)| g.info(room))
+            .filter(|info| !my_turn_only || info.is_turn_of(player))
+            .collect();
+        games.sort_by_key(|info| {
+            (
+                !info.is_turn_of(player),
+                info.result.is_some(),
+                info.deadline,
+            )
+        });
+        games.truncate(MAX_ACTIVE_GAMES);
+        games
+    }
+
+    /// The unfinished game played in `room`, as a snapshot to rebuild the
+    /// room from.
+    pub fn snapshot(&self, room: &str) -> Option<RoomSnapshot> {
+        self.data
+            .games
+            .get(room)
+            .filter(|game| game.result.is_none() && game.challenger.is_none())
+            .map(|game| game.snapshot(room))
+    }
+
+    /// Stores an action played in `room` along with the resulting turn and
+    /// result. The move clock restarts whenever the turn passes.
+    pub fn record_action(
+        &mut self,
+        room: &str,
+        action: RecordedAction,
+        turn: PlayerColor,
+        result: Option<GameResult>,
+        now: u64,
+    ) -> io::Result<()> {
+        let Some(game) = self.data.games.get_mut(room) else {
+            return Ok(());
+        };
+        if game.result.is_some() {
+            return Ok(());
+        }
+        game.actions.push(action);
+        if game.turn != turn {
+            game.turn = turn;
+            game.turn_started_at = now;
+        }
+        if result.is_some() {
+            game.result = result;
+            game.finished_at = Some(now);
+        }
+        self.save()
+    }
+
+    /// Ends games whose player to move ran out of time and drops games that
+    /// finished long ago. Returns the forfeited games with their results,
+    /// and the rooms of accepted games dropped because nobody had moved;
+    /// those and unanswered challenges lapse without a result.
+    pub fn expire(&mut self, now: u64) -> io::Result<Expired> {
+        let mut expired = Expired::default();
+        let before = self.data.games.len();
+        self.data.games.retain(|room, game| {
+            let lapsed = game.result.is_none()
+                && now >= game.deadline()
+                && (game.challenger.is_some() || game.actions.is_empty());
+            if lapsed && game.challenger.is_none() {
+                expired.aborted.push(room.clone());
+            }
+            !lapsed
+        });
+        for (room, game) in &mut self.data.games {
+            if game.result.is_none() && now >= game.deadline() {
+                let result = GameResult::Win(game.turn.opposite());
+                game.result = Some(result);
+                game.finished_at = Some(now);
+                expired.forfeited.push((game.snapshot(room), result));
+            }
+        }
+        self.data.games.retain(|_, game| {
+            game.finished_at
+                .is_none_or(|at| now < at + FINISHED_RETENTION_SECS)
+        });
+
+        if !expired.forfeited.is_empty() || self.data.games.len() != before {
+            self.save()?;
+        }
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::GameAction;
+    use crate::types::Pos;
+
+    const DAY: u64 = SECS_PER_DAY;
+
+    fn store(name: &str) -> CorrespondenceStore {
+        let path = std::env::temp_dir().join(format!(
+            "ascension-correspondence-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        fs::remove_file(&path).ok();
+        CorrespondenceStore::load(path).unwrap()
+    }
+
+    fn first_move() -> RecordedAction {
+        RecordedAction {
+            color: PlayerColor::White,
+            action: GameAction::Move {
+                from: Pos::new(4, 1),
+                to: Pos::new(4, 3),
+            },
+            at: 0,
+        }
+    }
+
+    #[test]
+    fn only_games_with_moves_are_lost_on_time() {
+        let mut store = store("expire");
+        let unanswered = store.create("alice", "bob", "alice", 1, 0).unwrap().room;
+        let unmoved = store.create("alice", "bob", "alice", 1, 0).unwrap().room;
+        store.accept(&unmoved, "bob", 0).unwrap();
+        let moved = store.create("alice", "bob", "bob", 1, 0).unwrap().room;
+        store.accept(&moved, "alice", 0).unwrap();
+        store
+            .record_action(&moved, first_move(), PlayerColor::Black, None, 0)
+            .unwrap();
+
+        assert!(store.expire(DAY - 1).unwrap().forfeited.is_empty());
+        let expired = store.expire(DAY).unwrap();
+        assert_eq!(expired.aborted, [unmoved]);
+        let forfeited: Vec<_> = expired
+            .forfeited
+            .iter()
+            .map(|(snapshot, result)| (snapshot.name.as_str(), *result))
+            .collect();
+        assert_eq!(
+            forfeited,
+            [(moved.as_str(), GameResult::Win(PlayerColor::White))]
+        );
+        let left: Vec<String> = store
+            .games_of("bob", false)
+            .into_iter()
+            .map(|g| g.room)
+            .collect();
+        assert_eq!(left, [moved]);
+        assert!(!store.is_pending(&unanswered));
+        fs::remove_file(&store.path).ok();
+    }
+
+    #[test]
+    fn only_the_challenged_player_can_accept() {
+        let mut store = store("accept");
+        let room = store.create("alice", "bob", "alice", 3, 0).unwrap().room;
+        assert!(store.snapshot(&room).is_none());
+        assert!(store.accept(&room, "alice", 0).is_err());
+        assert!(store.accept(&room, "carol", 0).is_err());
+        assert!(store.accept(&room, "bob", 0).is_ok());
+        assert!(store.snapshot(&room).is_some());
+        assert!(store.accept(&room, "bob", 0).is_err());
+        fs::remove_file(&store.path).ok();
+    }
+}
@@ -0,0 +1,60 @@
+use super::limits::RateLimiter;
+use super::{ServerState, list_open_rooms};
+use crate::network::{DISCOVERY_QUERY, PROTOCOL_VERSION, ServerAnnouncement};
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tracing::{debug, warn};
+
+/// Open rooms included in an announcement, few enough to fit one datagram.
+const ANNOUNCED_ROOMS: usize = 8;
+/// Queries answered per address and window: enough for a couple of clients
+/// searching from one machine, which query every two seconds.
+const QUERY_WINDOW: Duration = Duration::from_secs(60);
+const MAX_QUERIES_PER_WINDOW: u32 = 60;
+
+/// Answers discovery queries with the game port and the open public rooms.
+/// Answers are larger than queries, so each sender address gets only so
+/// many, counted apart from its game connections.
+pub async fn serve(socket: UdpSocket, game_port: u16, state: ServerState) -> io::Result<()> {
+    let mut limiter = RateLimiter::new(QUERY_WINDOW, MAX_QUERIES_PER_WINDOW);
+    let mut last_cleanup = Instant::now();
+    let mut buf = [0; 64];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Discovery receive error: {}", e);
+                continue;
+            }
+        };
+        if &buf[..len] != DISCOVERY_QUERY || state.config.is_banned(peer.ip()) {
+            continue;
+        }
+        if last_cleanup.elapsed() >= QUERY_WINDOW {
+            limiter.cleanup();
+            last_cleanup = Instant::now();
+        }
+        if !limiter.check(peer.ip()) {
+            continue;
+        }
+
+        let (open_rooms, open_room_count) =
+            list_open_rooms(&state.rooms, &state.accounts, ANNOUNCED_ROOMS).await;
+        let announcement = ServerAnnouncement {
+            server_name: state.config.display_name.clone(),
+            protocol_version: PROTOCOL_VERSION,
+            port: game_port,
+            open_rooms,
+            open_room_count: open_room_count as u32,
+            tls: state.tls.is_some(),
+        };
+        debug!(%peer, "Answering discovery query");
+        if let Err(e) = socket
+            .send_to(&serde_json::to_vec(&announcement)?, peer)
+            .await
+        {
+            warn!(%peer, "Discovery reply failed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use super::ServerState;
+use super::archive::{GameRecord, GameSummary};
+use crate::game::GameResult;
+use crate::types::PlayerColor;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tracing::error;
+
+const DEFAULT_LIST_LIMIT: usize = 50;
+const MAX_LIST_LIMIT: usize = 500;
+
+#[derive(Deserialize)]
+struct ListParams {
+    player: Option<String>,
+    since: Option<u64>,
+    until: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SeatStatus {
+    connected: bool,
+    account: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RoomStatus {
+    name: String,
+    white: SeatStatus,
+    black: SeatStatus,
+    has_password: bool,
+    turn: PlayerColor,
+    actions: usize,
+    result: Option<GameResult>,
+    idle_secs: u64,
+}
+
+#[derive(Serialize)]
+struct ServerStatus {
+    version: &'static str,
+    uptime_secs: u64,
+    active_connections: u64,
+    rooms: Vec<RoomStatus>,
+}
+
+/// Serves the read-only game archive (`GET /games?player=&since=&until=&limit=`
+/// and `GET /games/{id}`), Prometheus metrics (`GET /metrics`) and, when an
+/// admin token is configured, a JSON status page (`GET /status`).
+pub async fn serve(listener: TcpListener, state: ServerState) -> Result<(), std::io::Error> {
+    let app = Router::new()
+        .route("/games", get(list_games))
+        .route("/games/{id}", get(get_game))
+        .route("/metrics", get(metrics))
+        .route("/status", get(status))
+        .with_state(state);
+    axum::serve(listener, app).await
+}
+
+async fn metrics(State(state): State<ServerState>) -> impl IntoResponse {
+    let active_rooms = state.rooms.lock().await.len();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(active_rooms, &state.rejections),
+    )
+}
+
+async fn status(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+) -> Result<Json<ServerStatus>, StatusCode> {
+    let Some(token) = &state.config.admin_token else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| given == token);
+    if !authorized {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let now = Instant::now();
+    let rooms_guard = state.rooms.lock().await;
+    let mut rooms: Vec<RoomStatus> = rooms_guard
+        .iter()
+        .map(|(name, room)| RoomStatus {
+            name: name.clone(),
+            white: SeatStatus {
+                connected: room.white.is_some(),
+                account: room.white_account.clone(),
+            },
+            black: SeatStatus {
+                connected: room.black.is_some(),
+                account: room.black_account.clone(),
+            },
+            has_password: room.password.is_some(),
+            turn: room.game.turn,
+            actions: room.actions.len(),
+            result: room.game.result,
+            idle_secs: now.duration_since(room.last_active).as_secs(),
+        })
+        .collect();
+    drop(rooms_guard);
+    rooms.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Json(ServerStatus {
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: state.metrics.started.elapsed().as_secs(),
+        active_connections: state.metrics.active_connections(),
+        rooms,
+    }))
+}
+
+async fn list_games(
+    State(state): State<ServerState>,
+    Query(params): Query<ListParams>,
+) -> Json<Vec<GameSummary>> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .min(MAX_LIST_LIMIT);
+    Json(state.archive.lock().await.list(
+        params.player.as_deref(),
+        params.since,
+        params.until,
+        limit,
+    ))
+}
+
+async fn get_game(
+    State(state): State<ServerState>,
+    Path(id): Path<u64>,
+) -> Result<Json<GameRecord>, StatusCode> {
+    match state.archive.lock().await.load(id) {
+        Ok(Some(record)) => Ok(Json(record)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Failed to load game {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
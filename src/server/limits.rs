@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Limits how many new connections each IP may open per window.
+pub struct RateLimiter {
+    requests: HashMap<IpAddr, (u32, Instant)>,
+    window: Duration,
+    max_requests: u32,
+}
+
+impl RateLimiter {
+    pub fn new(window: Duration, max_requests: u32) -> Self {
+        Self {
+            requests: HashMap::new(),
+            window,
+            max_requests,
+        }
+    }
+
+    pub fn check(&mut self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let (count, start) = self.requests.entry(ip).or_insert((0, now));
+
+        if now.duration_since(*start) > self.window {
+            *count = 1;
+            *start = now;
+            true
+        } else {
+            *count += 1;
+            *count <= self.max_requests
+        }
+    }
+
+    pub fn cleanup(&mut self) {
+        let now = Instant::now();
+        let window = self.window;
+        self.requests
+            .retain(|_, (_, start)| now.duration_since(*start) <= window);
+    }
+}
+
+pub enum Verdict {
+    Allow,
+    Reject,
+    Disconnect,
+}
+
+/// One violation is forgiven for every this long a client stays within its
+/// limit, so only bursts close together add up to a disconnect.
+const VIOLATION_DECAY: Duration = Duration::from_secs(10);
+
+/// Token bucket limiting the messages a single connection may send. Each
+/// rejected message counts as a violation; too many and the client is dropped.
+pub struct MessageLimiter {
+    tokens: f64,
+    burst: f64,
+    per_sec: f64,
+    last_refill: Instant,
+    violations: u32,
+    max_violations: u32,
+    /// When the violations last went up or one was last forgiven.
+    decay_from: Instant,
+}
+
+impl MessageLimiter {
+    pub fn new(per_sec: u32, burst: u32, max_violations: u32) -> Self {
+        Self {
+            tokens: burst as f64,
+            burst: burst as f64,
+            per_sec: per_sec as f64,
+            last_refill: Instant::now(),
+            violations: 0,
+            max_violations,
+            decay_from: Instant::now(),
+        }
+    }
+
+    pub fn check(&mut self) -> Verdict {
+        self.check_at(Instant::now())
+    }
+
+    fn check_at(&mut self, now: Instant) -> Verdict {
+        let forgiven = (now.duration_since(self.decay_from).as_secs_f64()
+            / VIOLATION_DECAY.as_secs_f64()) as u32;
+        if forgiven > 0 {
+            self.violations = self.violations.saturating_sub(forgiven);
+            self.decay_from += VIOLATION_DECAY * forgiven;
+        }
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_sec).min(self.burst);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Verdict::Allow
+        } else {
+            self.violations += 1;
+            self.decay_from = now;
+            if self.violations >= self.max_violations {
+                Verdict::Disconnect
+            } else {
+                Verdict::Reject
+            }
+        }
+    }
+}
+
+/// Running totals of traffic the server turned away.
+#[derive(Default)]
+pub struct RejectionCounters {
+    pub banned_connections: AtomicU64,
+    pub throttled_connections: AtomicU64,
+    pub throttled_messages: AtomicU64,
+    pub flood_disconnects: AtomicU64,
+}
+
+impl RejectionCounters {
+    pub fn total(&self) -> u64 {
+        self.banned_connections.load(Ordering::Relaxed)
+            + self.throttled_connections.load(Ordering::Relaxed)
+            + self.throttled_messages.load(Ordering::Relaxed)
+            + self.flood_disconnects.load(Ordering::Relaxed)
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "banned connections: {}, throttled connections: {}, throttled messages: {}, flood disconnects: {}",
+            self.banned_connections.load(Ordering::Relaxed),
+            self.throttled_connections.load(Ordering::Relaxed),
+            self.throttled_messages.load(Ordering::Relaxed),
+            self.flood_disconnects.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verdicts(limiter: &mut MessageLimiter, now: Instant, count: usize) -> Vec<&'static str> {
+        (0..count)
+            .map(|_| match limiter.check_at(now) {
+                Verdict::Allow => "allow",
+                Verdict::Reject => "reject",
+                Verdict::Disconnect => "disconnect",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn violations_close_together_disconnect() {
+        let mut limiter = MessageLimiter::new(1, 1, 3);
+        let start = Instant::now();
+        assert_eq!(
+            verdicts(&mut limiter, start, 4),
+            ["allow", "reject", "reject", "disconnect"]
+        );
+    }
+
+    #[test]
+    fn violations_are_forgiven_after_compliant_traffic() {
+        let mut limiter = MessageLimiter::new(1, 1, 3);
+        let start = Instant::now();
+        assert_eq!(
+            verdicts(&mut limiter, start, 3),
+            ["allow", "reject", "reject"]
+        );
+        // One message a second is within the limit; after two decay periods
+        // both violations are forgotten.
+        for second in 1..=20 {
+            let now = start + Duration::from_secs(second);
+            assert_eq!(verdicts(&mut limiter, now, 1), ["allow"]);
+        }
+        let later = start + Duration::from_secs(21);
+        assert_eq!(
+            verdicts(&mut limiter, later, 3),
+            ["allow", "reject", "reject"]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use super::limits::RejectionCounters;
+use super::transport::TransportKind;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Upper bounds, in seconds, of the message handling latency histogram.
+const LATENCY_BUCKETS: [f64; 8] = [0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+/// Server-wide counters, rendered in the Prometheus text format by `render`.
+pub struct Metrics {
+    pub started: Instant,
+    tcp_connections: AtomicU64,
+    websocket_connections: AtomicU64,
+    active_connections: AtomicU64,
+    pub games_started: AtomicU64,
+    pub games_finished: AtomicU64,
+    messages: Mutex<BTreeMap<&'static str, u64>>,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_count: AtomicU64,
+    latency_sum_micros: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            tcp_connections: AtomicU64::new(0),
+            websocket_connections: AtomicU64::new(0),
+            active_connections: AtomicU64::new(0),
+            games_started: AtomicU64::new(0),
+            games_finished: AtomicU64::new(0),
+            messages: Mutex::new(BTreeMap::new()),
+            latency_buckets: Default::default(),
+            latency_count: AtomicU64::new(0),
+            latency_sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+/// Keeps a connection counted as active until it is dropped.
+pub struct ActiveConnection(Arc<Metrics>);
+
+impl Drop for ActiveConnection {
+    fn drop(&mut self) {
+        self.0.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub struct MessageTimer<'a> {
+    metrics: &'a Metrics,
+    started: Instant,
+}
+
+impl Drop for MessageTimer<'_> {
+    fn drop(&mut self) {
+        self.metrics.message_handled(self.started.elapsed());
+    }
+}
+
+impl Metrics {
+    pub fn connection_opened(self: &Arc<Self>, kind: TransportKind) -> ActiveConnection {
+        let counter = match kind {
+            TransportKind::Tcp => &self.tcp_connections,
+            TransportKind::WebSocket => &self.websocket_connections,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ActiveConnection(self.clone())
+    }
+
+    pub fn active_connections(&self) -> u64 {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn message_received(&self, kind: &'static str) {
+        *self.messages.lock().unwrap().entry(kind).or_insert(0) += 1;
+    }
+
+    /// Starts timing an in-game message; the time is recorded when the
+    /// returned timer is dropped.
+    pub fn time_message(&self) -> MessageTimer<'_> {
+        MessageTimer {
+            metrics: self,
+            started: Instant::now(),
+        }
+    }
+
+    fn message_handled(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            if secs <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn render(&self, active_rooms: usize, rejections: &RejectionCounters) -> String {
+        let mut out = String::new();
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        metric(
+            &mut out,
+            "ascension_uptime_seconds",
+            "gauge",
+            "Seconds since the server started.",
+        );
+        writeln!(
+            out,
+            "ascension_uptime_seconds {}",
+            self.started.elapsed().as_secs()
+        )
+        .unwrap();
+
+        metric(
+            &mut out,
+            "ascension_connections_total",
+            "counter",
+            "Connections accepted, by transport.",
+        );
+        writeln!(
+            out,
+            "ascension_connections_total{{transport=\"tcp\"}} {}",
+            load(&self.tcp_connections)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "ascension_connections_total{{transport=\"websocket\"}} {}",
+            load(&self.websocket_connections)
+        )
+        .unwrap();
+
+        metric(
+            &mut out,
+            "ascension_active_connections",
+            "gauge",
+            "Connections currently open.",
+        );
+        writeln!(
+            out,
+            "ascension_active_connections {}",
+            self.active_connections()
+        )
+        .unwrap();
+
+        metric(
+            &mut out,
+            "ascension_active_rooms",
+            "gauge",
+            "Rooms currently open.",
+        );
+        writeln!(out, "ascension_active_rooms {}", active_rooms).unwrap();
+
+        metric(
+            &mut out,
+            "ascension_games_started_total",
+            "counter",
+            "Games in which a first action was played.",
+        );
+        writeln!(
+            out,
+            "ascension_games_started_total {}",
+            load(&self.games_started)
+        )
+        .unwrap();
+
+        metric(
+            &mut out,
+            "ascension_games_finished_total",
+            "counter",
+            "Games that reached a result.",
+        );
+        writeln!(
+            out,
+            "ascension_games_finished_total {}",
+            load(&self.games_finished)
+        )
+        .unwrap();
+
+        metric(
+            &mut out,
+            "ascension_messages_received_total",
+            "counter",
+            "Messages received from clients, by type.",
+        );
+        for (kind, count) in self.messages.lock().unwrap().iter() {
+            writeln!(
+                out,
+                "ascension_messages_received_total{{type=\"{}\"}} {}",
+                kind, count
+            )
+            .unwrap();
+        }
+
+        metric(
+            &mut out,
+            "ascension_rejected_total",
+            "counter",
+            "Connections and messages turned away, by reason.",
+        );
+        for (reason, counter) in [
+            ("banned_connection", &rejections.banned_connections),
+            ("throttled_connection", &rejections.throttled_connections),
+            ("throttled_message", &rejections.throttled_messages),
+            ("flood_disconnect", &rejections.flood_disconnects),
+        ] {
+            writeln!(
+                out,
+                "ascension_rejected_total{{reason=\"{}\"}} {}",
+                reason,
+                load(counter)
+            )
+            .unwrap();
+        }
+
+        metric(
+            &mut out,
+            "ascension_message_handling_seconds",
+            "histogram",
+            "Time spent handling an in-game message.",
+        );
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            writeln!(
+                out,
+                "ascension_message_handling_seconds_bucket{{le=\"{}\"}} {}",
+                bound,
+                load(bucket)
+            )
+            .unwrap();
+        }
+        let count = load(&self.latency_count);
+        writeln!(
+            out,
+            "ascension_message_handling_seconds_bucket{{le=\"+Inf\"}} {}",
+            count
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "ascension_message_handling_seconds_sum {}",
+            load(&self.latency_sum_micros) as f64 / 1_000_000.0
+        )
+        .unwrap();
+        writeln!(out, "ascension_message_handling_seconds_count {}", count).unwrap();
+
+        out
+    }
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
@@ -0,0 +1,1665 @@
+//! The game server: accepts TCP and WebSocket players, runs their rooms and
+//! serves the HTTP archive. The `server` binary wraps it with flags and
+//! logging; tests and embedding applications bind it on any address they like.
+
+mod accounts;
+mod archive;
+mod config;
+mod correspondence;
+mod discovery;
+mod http;
+mod limits;
+mod metrics;
+mod proxy;
+mod rating;
+mod room_codes;
+mod snapshot;
+mod tournament;
+mod transport;
+
+use crate::game::{Game, GameResult, TurnPhase};
+use crate::network::{
+    CAPABILITIES, ColorPreference, Encoding, GameAction, GameMessage, Heartbeat,
+    MIN_PROTOCOL_VERSION, MatchScore, PROTOCOL_VERSION, RoomInfo,
+};
+use crate::types::PlayerColor;
+use accounts::AccountStore;
+use archive::{GameArchive, GameRecord, GameSummary, RecordedAction};
+pub use config::{Args, Config, LogFormat};
+use correspondence::CorrespondenceStore;
+use limits::{MessageLimiter, RateLimiter, RejectionCounters, Verdict};
+use metrics::Metrics;
+use snapshot::RoomSnapshot;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{Mutex, mpsc};
+use tokio::time;
+use tokio_rustls::TlsAcceptor;
+use tournament::TournamentStore;
+use tracing::{Instrument, error, info, info_span, warn};
+use transport::{Transport, TransportKind};
+
+type Tx = mpsc::UnboundedSender<GameMessage>;
+
+const MAX_LISTED_ROOMS: usize = 40;
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a client may take over the TLS handshake and WebSocket upgrade
+/// before it is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+struct ServerState {
+    rooms: Arc<Mutex<HashMap<String, Room>>>,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+    rejections: Arc<RejectionCounters>,
+    metrics: Arc<Metrics>,
+    accounts: Arc<Mutex<AccountStore>>,
+    archive: Arc<Mutex<GameArchive>>,
+    tournaments: Arc<Mutex<TournamentStore>>,
+    correspondence: Arc<Mutex<CorrespondenceStore>>,
+    tls: Option<TlsAcceptor>,
+    config: Arc<Config>,
+}
+
+struct Room {
+    white: Option<Tx>,
+    black: Option<Tx>,
+    white_account: Option<String>,
+    black_account: Option<String>,
+    /// Rejoin tokens of the seats' current or last holders.
+    white_token: Option<String>,
+    black_token: Option<String>,
+    /// Seats of a restored room kept for the players who held them until
+    /// they come back or `held_until` passes.
+    held: Vec<PlayerColor>,
+    held_until: Instant,
+    password: Option<String>,
+    game: Game,
+    actions: Vec<RecordedAction>,
+    started_at: u64,
+    last_active: Instant,
+    /// Match score from the point of view of whoever holds the white seat.
+    score: MatchScore,
+    rematch_offer: Option<PlayerColor>,
+    /// Player waiting for the opponent to answer a take-back request.
+    takeback_offer: Option<PlayerColor>,
+    /// Listed in the lobby while waiting for an opponent.
+    public: bool,
+    /// Set for rooms reserved for a tournament pairing.
+    tournament: Option<String>,
+    /// Rooms of correspondence games save every action to the store.
+    correspondence: bool,
+}
+
+/// Seats of a tournament or correspondence game room, which only its two
+/// players may take.
+struct Reservation {
+    white: String,
+    black: String,
+    tournament: Option<String>,
+    /// Saved correspondence game to rebuild the room from.
+    correspondence: Option<RoomSnapshot>,
+}
+
+impl Room {
+    /// Rebuilds a room saved at shutdown by replaying its actions, or returns
+    /// `None` if any of them is no longer legal. Seats taken at shutdown are
+    /// held for their players for `hold_for`.
+    fn restore(snapshot: RoomSnapshot, hold_for: Duration) -> Option<Self> {
+        let mut game = Game::new();
+        for recorded in &snapshot.actions {
+            if !play(&mut game, recorded.color, recorded.action) {
+                return None;
+            }
+        }
+        let held = [
+            (PlayerColor::White, &snapshot.white_token),
+            (PlayerColor::Black, &snapshot.black_token),
+        ]
+        .into_iter()
+        .filter(|(_, token)| token.is_some())
+        .map(|(color, _)| color)
+        .collect();
+        Some(Room {
+            white: None,
+            black: None,
+            white_account: snapshot.white_account,
+            black_account: snapshot.black_account,
+            white_token: snapshot.white_token,
+            black_token: snapshot.black_token,
+            held,
+            held_until: Instant::now() + hold_for,
+            password: snapshot.password,
+            game,
+            actions: snapshot.actions,
+            started_at: snapshot.started_at,
+            last_active: Instant::now(),
+            score: snapshot.score,
+            rematch_offer: None,
+            takeback_offer: None,
+            public: snapshot.public,
+            tournament: snapshot.tournament,
+            correspondence: false,
+        })
+    }
+
+    fn snapshot(&self, name: &str) -> RoomSnapshot {
+        RoomSnapshot {
+            name: name.to_string(),
+            white_account: self.white_account.clone(),
+            black_account: self.black_account.clone(),
+            password: self.password.clone(),
+            started_at: self.started_at,
+            actions: self.actions.clone(),
+            score: self.score,
+            public: self.public,
+            tournament: self.tournament.clone(),
+            white_token: self.white.as_ref().and(self.white_token.clone()),
+            black_token: self.black.as_ref().and(self.black_token.clone()),
+        }
+    }
+
+    fn is_free(&self, color: PlayerColor) -> bool {
+        match color {
+            PlayerColor::White => self.white.is_none(),
+            PlayerColor::Black => self.black.is_none(),
+        }
+    }
+
+    /// Whether a seat is still kept for the player who held it before a
+    /// restart.
+    fn is_held(&self, color: PlayerColor) -> bool {
+        self.held.contains(&color) && Instant::now() < self.held_until
+    }
+
+    /// Whether `color` is the seat of the player with `account` or
+    /// `rejoin_token`.
+    fn belongs_to(
+        &self,
+        color: PlayerColor,
+        account: Option<&String>,
+        rejoin_token: Option<&String>,
+    ) -> bool {
+        let (seat_account, seat_token) = match color {
+            PlayerColor::White => (&self.white_account, &self.white_token),
+            PlayerColor::Black => (&self.black_account, &self.black_token),
+        };
+        (account.is_some() && seat_account.as_ref() == account)
+            || (rejoin_token.is_some() && seat_token.as_ref() == rejoin_token)
+    }
+
+    /// Seats a player, returning the rejoin token for the seat: the old one
+    /// when `returning`, otherwise a new one.
+    fn take_seat(
+        &mut self,
+        color: PlayerColor,
+        tx: Tx,
+        account: Option<String>,
+        returning: bool,
+    ) -> String {
+        let (seat, seat_account, seat_token) = match color {
+            PlayerColor::White => (
+                &mut self.white,
+                &mut self.white_account,
+                &mut self.white_token,
+            ),
+            PlayerColor::Black => (
+                &mut self.black,
+                &mut self.black_account,
+                &mut self.black_token,
+            ),
+        };
+        *seat = Some(tx);
+        *seat_account = account;
+        let token = match seat_token.take() {
+            Some(token) if returning => token,
+            _ => room_codes::rejoin_token(),
+        };
+        *seat_token = Some(token.clone());
+        self.held.retain(|&held| held != color);
+        self.last_active = Instant::now();
+        token
+    }
+
+    /// Starts a new game with the players' colors swapped, beginning a new
+    /// match first if the current one has been decided.
+    fn start_rematch(&mut self) {
+        if self.score.is_decided() {
+            self.score = MatchScore::default();
+        }
+        std::mem::swap(&mut self.white, &mut self.black);
+        std::mem::swap(&mut self.white_account, &mut self.black_account);
+        std::mem::swap(&mut self.white_token, &mut self.black_token);
+        self.score = self.score.flipped();
+        self.game = Game::new();
+        self.actions.clear();
+        self.started_at = archive::unix_millis() / 1000;
+        self.rematch_offer = None;
+        self.takeback_offer = None;
+
+        if let Some(white) = &self.white {
+            white
+                .send(GameMessage::RematchStarted {
+                    color: PlayerColor::White,
+                    score: self.score,
+                })
+                .ok();
+        }
+        if let Some(black) = &self.black {
+            black
+                .send(GameMessage::RematchStarted {
+                    color: PlayerColor::Black,
+                    score: self.score.flipped(),
+                })
+                .ok();
+        }
+    }
+
+    /// Applies an action from `color` to the server-side game, returning
+    /// false if it is not a legal action for that player right now.
+    fn apply(&mut self, color: PlayerColor, msg: &GameMessage) -> bool {
+        let Some(action) = GameAction::from_message(msg) else {
+            return false;
+        };
+        let applied = play(&mut self.game, color, action);
+        if applied {
+            self.actions.push(RecordedAction {
+                color,
+                action,
+                at: archive::unix_millis(),
+            });
+            // A pending request would now undo more than the turn it asked
+            // for, so moving on answers it as declined.
+            if let Some(requester) = self.takeback_offer.take() {
+                let tx = match requester {
+                    PlayerColor::White => &self.white,
+                    PlayerColor::Black => &self.black,
+                };
+                if let Some(tx) = tx {
+                    tx.send(GameMessage::DeclineTakeback).ok();
+                }
+            }
+        }
+        applied
+    }
+
+    /// Where `color`'s last turn starts in the recorded actions, if they
+    /// have played one.
+    fn last_turn_start(&self, color: PlayerColor) -> Option<usize> {
+        let last = self.actions.iter().rposition(|r| r.color == color)?;
+        Some(
+            self.actions[..last]
+                .iter()
+                .rposition(|r| r.color != color)
+                .map_or(0, |i| i + 1),
+        )
+    }
+
+    /// Undoes `color`'s last turn and anything played after it by replaying
+    /// the actions before it, then sends both players what remains.
+    fn take_back(&mut self, color: PlayerColor) {
+        let Some(start) = self.last_turn_start(color) else {
+            return;
+        };
+        self.actions.truncate(start);
+        self.game = Game::new();
+        for recorded in &self.actions {
+            play(&mut self.game, recorded.color, recorded.action);
+        }
+        self.takeback_offer = None;
+
+        let actions: Vec<GameAction> = self.actions.iter().map(|r| r.action).collect();
+        for player in [&self.white, &self.black].into_iter().flatten() {
+            player
+                .send(GameMessage::TakenBack {
+                    actions: actions.clone(),
+                })
+                .ok();
+        }
+    }
+}
+
+/// Plays `action` for `color` if it is legal right now, returning whether it
+/// was applied.
+fn play(game: &mut Game, color: PlayerColor, action: GameAction) -> bool {
+    if game.turn != color || game.result.is_some() {
+        return false;
+    }
+
+    match action {
+        GameAction::Move { from, to } => {
+            if !game.is_legal_move(from, to) {
+                return false;
+            }
+            game.make_move(from, to);
+            true
+        }
+        GameAction::Upgrade { pos } => {
+            if game.phase != TurnPhase::Normal {
+                return false;
+            }
+            game.attempt_upgrade(pos);
+            game.phase == TurnPhase::PostUpgrade(pos)
+        }
+        GameAction::Promote { piece_type } => {
+            if !game.can_promote_to(piece_type) {
+                return false;
+            }
+            game.resolve_promotion(piece_type);
+            true
+        }
+    }
+}
+
+/// A server with its listeners bound, ready to `run`. Bind addresses with
+/// port 0 get a free port, which `tcp_addr` and friends report.
+pub struct Server {
+    listener: TcpListener,
+    ws_listener: TcpListener,
+    http_listener: TcpListener,
+    discovery_socket: Option<UdpSocket>,
+    state: ServerState,
+}
+
+impl Server {
+    /// Binds every listener, loads the stores and restores the rooms saved at
+    /// the last shutdown.
+    pub async fn bind(config: Config) -> io::Result<Self> {
+        let tls = match (&config.tls_cert, &config.tls_key) {
+            (Some(cert), Some(key)) => Some(crate::tls::acceptor(cert, key)?),
+            _ => None,
+        };
+        let listener = TcpListener::bind(config.bind).await?;
+        let ws_listener = TcpListener::bind(config.ws_bind).await?;
+        let http_listener = TcpListener::bind(config.http_bind).await?;
+        let discovery_socket = match config.discovery_bind {
+            Some(addr) => Some(UdpSocket::bind(addr).await.map_err(|e| {
+                io::Error::new(e.kind(), format!("discovery port {}: {}", addr, e))
+            })?),
+            None => None,
+        };
+
+        let mut rooms = HashMap::new();
+        for snapshot in snapshot::take(&config.rooms_file)? {
+            let name = snapshot.name.clone();
+            match Room::restore(snapshot, config.room_timeout) {
+                Some(room) => {
+                    rooms.insert(name, room);
+                }
+                None => warn!(room = %name, "Could not replay saved room, dropping it"),
+            }
+        }
+        if !rooms.is_empty() {
+            info!(
+                "Restored {} rooms from {}",
+                rooms.len(),
+                config.rooms_file.display()
+            );
+        }
+
+        let state = ServerState {
+            rooms: Arc::new(Mutex::new(rooms)),
+            rate_limiter: Arc::new(Mutex::new(RateLimiter::new(
+                config.rate_limit_window,
+                config.max_requests_per_window,
+            ))),
+            rejections: Arc::new(RejectionCounters::default()),
+            metrics: Arc::new(Metrics::default()),
+            accounts: Arc::new(Mutex::new(AccountStore::load(&config.accounts_file)?)),
+            archive: Arc::new(Mutex::new(GameArchive::open(&config.games_dir)?)),
+            tournaments: Arc::new(Mutex::new(TournamentStore::load(&config.tournaments_file)?)),
+            correspondence: Arc::new(Mutex::new(CorrespondenceStore::load(
+                &config.correspondence_file,
+            )?)),
+            tls,
+            config: Arc::new(config),
+        };
+        Ok(Server {
+            listener,
+            ws_listener,
+            http_listener,
+            discovery_socket,
+            state,
+        })
+    }
+
+    pub fn tcp_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn ws_addr(&self) -> io::Result<SocketAddr> {
+        self.ws_listener.local_addr()
+    }
+
+    pub fn http_addr(&self) -> io::Result<SocketAddr> {
+        self.http_listener.local_addr()
+    }
+
+    /// Where LAN discovery queries are answered, unless discovery is off.
+    pub fn discovery_addr(&self) -> Option<io::Result<SocketAddr>> {
+        self.discovery_socket.as_ref().map(UdpSocket::local_addr)
+    }
+
+    /// Serves players until `shutdown` completes, then saves the open rooms
+    /// and tells their players the server is restarting.
+    pub async fn run(self, shutdown: impl Future<Output = ()>) -> io::Result<()> {
+        let Server {
+            listener,
+            ws_listener,
+            http_listener,
+            discovery_socket,
+            state,
+        } = self;
+        info!("Server listening on {}", listener.local_addr()?);
+        if state.tls.is_some() {
+            info!("Game and WebSocket connections require TLS");
+        }
+        info!(
+            "WebSocket server listening on {}",
+            ws_listener.local_addr()?
+        );
+        info!(
+            "Game archive and metrics listening on http://{}",
+            http_listener.local_addr()?
+        );
+
+        let http_state = state.clone();
+        let http = tokio::spawn(async move {
+            if let Err(e) = http::serve(http_listener, http_state).await {
+                error!("HTTP server error: {}", e);
+            }
+        });
+        let mut discovery = None;
+        if let Some(socket) = discovery_socket {
+            info!(
+                "Answering LAN discovery queries on {}",
+                socket.local_addr()?
+            );
+            let game_port = listener.local_addr()?.port();
+            let discovery_state = state.clone();
+            discovery = Some(tokio::spawn(async move {
+                if let Err(e) = discovery::serve(socket, game_port, discovery_state).await {
+                    error!("Discovery error: {}", e);
+                }
+            }));
+        }
+        let cleanup = tokio::spawn(cleanup_loop(state.clone()));
+        let ws_state = state.clone();
+        let ws_accept = tokio::spawn(async move {
+            if let Err(e) = accept_loop(ws_listener, TransportKind::WebSocket, ws_state).await {
+                error!("WebSocket listener error: {}", e);
+            }
+        });
+
+        let result = tokio::select! {
+            result = accept_loop(listener, TransportKind::Tcp, state.clone()) => result,
+            _ = shutdown => Ok(()),
+        };
+        ws_accept.abort();
+        cleanup.abort();
+        http.abort();
+        if let Some(discovery) = discovery {
+            discovery.abort();
+        }
+        shut_down(&state).await;
+        result
+    }
+}
+
+/// Removes abandoned rooms, settles tournament games nobody finished, ends
+/// correspondence games that ran out of time and forgets old connection
+/// counts once a minute, or as often as rooms time out if that is sooner.
+async fn cleanup_loop(state: ServerState) {
+    let room_timeout = state.config.room_timeout;
+    let mut interval = time::interval(Duration::from_secs(60).min(room_timeout));
+    let mut last_rejected = 0;
+    loop {
+        interval.tick().await;
+
+        let mut rooms_guard = state.rooms.lock().await;
+        let now = Instant::now();
+        let mut abandoned = Vec::new();
+        rooms_guard.retain(|name, room| {
+            let active = now.duration_since(room.last_active) < room_timeout;
+            if !active {
+                info!(room = %name, "Cleaning up abandoned room");
+                if room.tournament.is_some() && room.game.result.is_none() {
+                    abandoned.push((name.clone(), forfeit_winner(room)));
+                }
+            }
+            active
+        });
+        forfeit_tournament_games(&rooms_guard, abandoned, &state).await;
+        expire_correspondence_games(&mut rooms_guard, &state).await;
+        drop(rooms_guard);
+
+        state.rate_limiter.lock().await.cleanup();
+
+        let rejected = state.rejections.total();
+        if rejected != last_rejected {
+            info!("Rejected traffic so far: {}", state.rejections.summary());
+            last_rejected = rejected;
+        }
+    }
+}
+
+/// Saves every open room, tells its players the server is restarting and
+/// closes the rooms, which ends their connections once the notice is sent.
+async fn shut_down(state: &ServerState) {
+    info!("Shutting down");
+    let mut rooms_guard = state.rooms.lock().await;
+    // Correspondence games are already saved after every action.
+    let snapshots: Vec<RoomSnapshot> = rooms_guard
+        .iter()
+        .filter(|(_, room)| !room.correspondence)
+        .map(|(name, room)| room.snapshot(name))
+        .collect();
+    match snapshot::save(&state.config.rooms_file, &snapshots) {
+        Ok(()) => info!(
+            "Saved {} rooms to {}",
+            snapshots.len(),
+            state.config.rooms_file.display()
+        ),
+        Err(e) => error!("Failed to save rooms: {}", e),
+    }
+
+    for (name, room) in rooms_guard.iter() {
+        let seats = [
+            (&room.white, &room.white_token),
+            (&room.black, &room.black_token),
+        ];
+        for (tx, token) in seats {
+            if let Some(tx) = tx {
+                tx.send(GameMessage::ServerRestarting {
+                    message: "The server is restarting. Rejoin the same room code in a moment."
+                        .into(),
+                    room: Some(name.clone()),
+                    rejoin_token: token.clone(),
+                })
+                .ok();
+            }
+        }
+    }
+    rooms_guard.clear();
+    drop(rooms_guard);
+    // Give connection tasks a moment to flush the notice.
+    time::sleep(Duration::from_millis(500)).await;
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    kind: TransportKind,
+    state: ServerState,
+) -> std::io::Result<()> {
+    loop {
+        let (mut socket, peer) = listener.accept().await?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            let addr = if state.config.proxy_protocol {
+                match time::timeout(PROXY_HEADER_TIMEOUT, proxy::read_source(&mut socket)).await {
+                    Ok(Ok(source)) => source.unwrap_or(peer),
+                    // Health checks connect and hang up without a header.
+                    Ok(Err(e)) if e.kind() == io::ErrorKind::UnexpectedEof => return,
+                    Ok(Err(e)) => {
+                        warn!(%peer, "Dropped connection: {}", e);
+                        return;
+                    }
+                    Err(_) => {
+                        warn!(%peer, "Dropped connection without a PROXY header");
+                        return;
+                    }
+                }
+            } else {
+                peer
+            };
+            let span =
+                info_span!("connection", %addr, transport = ?kind, room = tracing::field::Empty);
+            async move {
+                if let Err(e) = handle_connection(socket, addr, kind, state).await {
+                    warn!("Connection error: {}", e);
+                }
+            }
+            .instrument(span)
+            .await
+        });
+    }
+}
+
+async fn handle_connection(
+    socket: TcpStream,
+    addr: SocketAddr,
+    kind: TransportKind,
+    state: ServerState,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if state.config.is_banned(addr.ip()) {
+        state
+            .rejections
+            .banned_connections
+            .fetch_add(1, Ordering::Relaxed);
+        return Err("Banned address".into());
+    }
+    {
+        let mut rl = state.rate_limiter.lock().await;
+        if !rl.check(addr.ip()) {
+            state
+                .rejections
+                .throttled_connections
+                .fetch_add(1, Ordering::Relaxed);
+            return Err("Rate limit exceeded".into());
+        }
+    }
+
+    let accept = Transport::accept(socket, kind, state.config.max_msg_size, state.tls.as_ref());
+    let mut transport = time::timeout(HANDSHAKE_TIMEOUT, accept)
+        .await
+        .map_err(|_| "Handshake timed out")??;
+    let _active = state.metrics.connection_opened(kind);
+    let ServerState {
+        rooms,
+        accounts,
+        archive,
+        tournaments,
+        correspondence,
+        rejections,
+        metrics,
+        config,
+        ..
+    } = state;
+    let mut limiter = MessageLimiter::new(
+        config.messages_per_sec,
+        config.message_burst,
+        config.max_rate_violations,
+    );
+
+    let mut account: Option<String> = None;
+    let mut heartbeat: Option<Heartbeat> = None;
+    let (room, password, color_preference, public, rejoin_token) = loop {
+        let message_bytes = match transport.recv().await {
+            Some(Ok(bytes)) => bytes,
+            None => return Ok(()),
+            Some(Err(e)) => return Err(e),
+        };
+        match throttle(&mut limiter, &mut transport, &rejections).await? {
+            Verdict::Allow => {}
+            Verdict::Reject => continue,
+            Verdict::Disconnect => return Err("Disconnected for flooding".into()),
+        }
+
+        let message: GameMessage = match transport.decode(&message_bytes) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Ignored undecodable message: {}", e);
+                let error = GameMessage::Error {
+                    message: "Unsupported message".into(),
+                };
+                transport.send(&error).await?;
+                continue;
+            }
+        };
+        metrics.message_received(message.kind());
+
+        let reply = match message {
+            GameMessage::Hello {
+                protocol_version,
+                client_name,
+                capabilities,
+            } => {
+                if protocol_version < MIN_PROTOCOL_VERSION {
+                    let reject = GameMessage::UpgradeRequired {
+                        min_version: MIN_PROTOCOL_VERSION,
+                        message: format!(
+                            "This server requires protocol version {} or newer (you have {}). Please update Ascension Chess.",
+                            MIN_PROTOCOL_VERSION, protocol_version
+                        ),
+                    };
+                    transport.send(&reject).await?;
+                    return Ok(());
+                }
+                let shared: Vec<String> = capabilities
+                    .into_iter()
+                    .filter(|c| CAPABILITIES.contains(&c.as_str()))
+                    .collect();
+                info!(
+                    client = %client_name,
+                    protocol_version,
+                    capabilities = %shared.join(", "),
+                    "Client connected"
+                );
+                if shared.iter().any(|c| c == "heartbeat") {
+                    heartbeat = Some(Heartbeat::new(config.max_missed_heartbeats));
+                }
+                let encoding = Encoding::negotiated(&shared);
+                let hello = GameMessage::ServerHello {
+                    protocol_version: PROTOCOL_VERSION,
+                    server_name: format!("ascension-chess-server {}", env!("CARGO_PKG_VERSION")),
+                    capabilities: shared,
+                };
+                transport.send(&hello).await?;
+                transport.set_encoding(encoding);
+                continue;
+            }
+            GameMessage::Join {
+                room,
+                password,
+                color,
+                public,
+                rejoin_token,
+            } => break (room, password, color, public, rejoin_token),
+            GameMessage::Register { name } => match accounts.lock().await.register(&name) {
+                Ok(token) => {
+                    account = Some(name.clone());
+                    GameMessage::Registered { name, token }
+                }
+                Err(message) => GameMessage::Error { message },
+            },
+            GameMessage::Login { name, token } => {
+                let accounts_guard = accounts.lock().await;
+                match accounts_guard.entry(&name) {
+                    Some(entry) if accounts_guard.verify(&name, &token) => {
+                        account = Some(name);
+                        GameMessage::Rating { entry }
+                    }
+                    _ => GameMessage::Error {
+                        message: "Invalid account name or token".into(),
+                    },
+                }
+            }
+            GameMessage::QueryRating { .. } | GameMessage::QueryLeaderboard { .. } => {
+                account_query_reply(&accounts, message).await
+            }
+            GameMessage::Ping { nonce } => GameMessage::Pong { nonce },
+            GameMessage::ListRooms => GameMessage::RoomList {
+                rooms: list_open_rooms(&rooms, &accounts, MAX_LISTED_ROOMS).await.0,
+            },
+            GameMessage::CreateTournament { .. }
+            | GameMessage::JoinTournament { .. }
+            | GameMessage::StartTournament { .. }
+            | GameMessage::QueryStandings { .. } => {
+                tournament_reply(&tournaments, &accounts, account.as_deref(), message).await
+            }
+            GameMessage::CreateCorrespondence { .. }
+            | GameMessage::QueryCorrespondence { .. }
+            | GameMessage::AcceptCorrespondence { .. }
+            | GameMessage::DeclineCorrespondence { .. } => {
+                correspondence_reply(&correspondence, &accounts, account.as_deref(), message).await
+            }
+            _ => return Err("Expected Join message".into()),
+        };
+        transport.send(&reply).await?;
+    };
+
+    if let Some(r) = &room
+        && correspondence.lock().await.is_pending(r)
+    {
+        let error = GameMessage::Error {
+            message: "This correspondence challenge has not been accepted yet".into(),
+        };
+        transport.send(&error).await?;
+        return Ok(());
+    }
+
+    // Reserved rooms are named by the server and may not pass the usual room
+    // name rules.
+    let mut reservation = match &room {
+        Some(r) => reservation(&tournaments, &correspondence, r).await,
+        None => None,
+    };
+    if let Some(reserved) = &reservation
+        && account.as_ref() != Some(&reserved.white)
+        && account.as_ref() != Some(&reserved.black)
+    {
+        let error = GameMessage::Error {
+            message: "This room is reserved for another game".into(),
+        };
+        transport.send(&error).await?;
+        return Ok(());
+    }
+
+    let requested_room = match room {
+        Some(r) if reservation.is_some() => Some(r),
+        Some(r) if room_codes::is_generated_code(&r) => Some(r.to_lowercase()),
+        Some(r) if config.is_valid_room_name(&r) => Some(r),
+        Some(_) => return Err("Invalid room name".into()),
+        None => None,
+    };
+    let is_random_creation = requested_room.is_none();
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut color: PlayerColor;
+    let token: String;
+    let room_name: String;
+    let history: Vec<GameMessage>;
+
+    {
+        let mut rooms_guard = rooms.lock().await;
+
+        room_name = match requested_room {
+            Some(name) => name,
+            None => match room_codes::generate(|code| rooms_guard.contains_key(code)) {
+                Some(code) => code,
+                None => {
+                    let error = GameMessage::Error {
+                        message: "Could not allocate a room code. Try again.".into(),
+                    };
+                    transport.send(&error).await?;
+                    return Ok(());
+                }
+            },
+        };
+
+        tracing::Span::current().record("room", room_name.as_str());
+        info!(
+            "User {} room",
+            if is_random_creation {
+                "creating"
+            } else {
+                "joining/creating"
+            }
+        );
+
+        let should_create = !rooms_guard.contains_key(&room_name);
+
+        if should_create {
+            if rooms_guard.len() >= config.max_rooms {
+                let error = GameMessage::Error {
+                    message: "Server is full".into(),
+                };
+                transport.send(&error).await?;
+                return Ok(());
+            }
+
+            color = match (
+                &reservation,
+                color_preference.unwrap_or(ColorPreference::White),
+            ) {
+                (Some(reserved), _) if account.as_ref() == Some(&reserved.white) => {
+                    PlayerColor::White
+                }
+                (Some(_), _) => PlayerColor::Black,
+                (None, ColorPreference::White) => PlayerColor::White,
+                (None, ColorPreference::Black) => PlayerColor::Black,
+                (None, ColorPreference::Random) if rand::random() => PlayerColor::White,
+                (None, ColorPreference::Random) => PlayerColor::Black,
+            };
+            let saved_game = reservation.as_mut().and_then(|r| r.correspondence.take());
+            let mut new_room = match saved_game {
+                Some(snapshot) => match Room::restore(snapshot, Duration::ZERO) {
+                    Some(room) => Room {
+                        correspondence: true,
+                        ..room
+                    },
+                    None => {
+                        warn!("Could not replay correspondence game");
+                        let error = GameMessage::Error {
+                            message: "This game could not be loaded".into(),
+                        };
+                        transport.send(&error).await?;
+                        return Ok(());
+                    }
+                },
+                None => Room {
+                    white: None,
+                    black: None,
+                    white_account: None,
+                    black_account: None,
+                    white_token: None,
+                    black_token: None,
+                    held: Vec::new(),
+                    held_until: Instant::now(),
+                    password: password.filter(|p| !p.is_empty()),
+                    game: Game::new(),
+                    actions: Vec::new(),
+                    started_at: archive::unix_millis() / 1000,
+                    last_active: Instant::now(),
+                    score: MatchScore::default(),
+                    rematch_offer: None,
+                    takeback_offer: None,
+                    public: public && reservation.is_none(),
+                    tournament: reservation.and_then(|r| r.tournament),
+                    correspondence: false,
+                },
+            };
+            let returning = new_room.belongs_to(color, account.as_ref(), None);
+            token = new_room.take_seat(color, tx, account.clone(), returning);
+            history = new_room
+                .actions
+                .iter()
+                .map(|recorded| recorded.action.to_message())
+                .collect();
+            rooms_guard.insert(room_name.clone(), new_room);
+
+            let code_msg = GameMessage::RoomCode {
+                code: room_name.clone(),
+            };
+            transport.send(&code_msg).await?;
+        } else if let Some(room) = rooms_guard.get_mut(&room_name) {
+            if room.password.is_some() && room.password != password {
+                let error = GameMessage::Error {
+                    message: "Incorrect room password".into(),
+                };
+                transport.send(&error).await?;
+                return Ok(());
+            }
+            // Returning players get their own seat back, e.g. after a restart,
+            // and players of a reserved room always sit where they were paired.
+            // Anyone else takes a free seat not held for someone returning.
+            let seats = match &reservation {
+                Some(reserved) if account.as_ref() == Some(&reserved.white) => {
+                    vec![PlayerColor::White]
+                }
+                Some(_) => vec![PlayerColor::Black],
+                None => vec![PlayerColor::White, PlayerColor::Black],
+            };
+            let returning = seats.iter().copied().find(|&c| {
+                room.is_free(c) && room.belongs_to(c, account.as_ref(), rejoin_token.as_ref())
+            });
+            let seat = returning.or_else(|| {
+                seats
+                    .iter()
+                    .copied()
+                    .find(|&c| room.is_free(c) && !room.is_held(c))
+            });
+            let Some(seat) = seat else {
+                let message = if seats.iter().any(|&c| room.is_held(c)) {
+                    "This seat is kept for the player who held it before the server restarted"
+                } else {
+                    "Room full"
+                };
+                let error = GameMessage::Error {
+                    message: message.into(),
+                };
+                transport.send(&error).await?;
+                return Ok(());
+            };
+            color = seat;
+            token = room.take_seat(seat, tx, account.clone(), returning.is_some());
+            history = room
+                .actions
+                .iter()
+                .map(|recorded| recorded.action.to_message())
+                .collect();
+        } else {
+            // Should not happen as we checked rooms_guard.contains_key
+            let error = GameMessage::Error {
+                message: "Room not found".into(),
+            };
+            transport.send(&error).await?;
+            return Ok(());
+        }
+    }
+
+    let welcome = GameMessage::Welcome {
+        color,
+        rejoin_token: Some(token),
+    };
+    transport.send(&welcome).await?;
+    // Bring a player joining a game in progress up to the current position.
+    for msg in &history {
+        transport.send(msg).await?;
+    }
+
+    let mut heartbeat_timer = time::interval(config.heartbeat_interval);
+    loop {
+        tokio::select! {
+            _ = heartbeat_timer.tick(), if heartbeat.is_some() => {
+                let Some(hb) = heartbeat.as_mut() else { continue };
+                match hb.tick() {
+                    Some(ping) => transport.send(&ping).await?,
+                    None => {
+                        warn!("Missed too many heartbeats");
+                        break;
+                    }
+                }
+            }
+            recv_res = rx.recv() => {
+                match recv_res {
+                    Some(msg) => {
+                        // Seats swap for a rematch; keep acting for our new color.
+                        if let GameMessage::RematchStarted { color: new_color, .. } = msg {
+                            color = new_color;
+                        }
+                        transport.send(&msg).await?
+                    }
+                    None => break,
+                }
+            }
+            result = transport.recv() => {
+                match result {
+                    Some(Ok(bytes)) => {
+                        match throttle(&mut limiter, &mut transport, &rejections).await? {
+                            Verdict::Allow => {}
+                            Verdict::Reject => continue,
+                            Verdict::Disconnect => {
+                                warn!("Disconnected for flooding");
+                                break;
+                            }
+                        }
+                        let _timer = metrics.time_message();
+                        let msg: GameMessage = match transport.decode(&bytes) {
+                            Ok(msg) => msg,
+                            Err(e) => {
+                                warn!("Ignored undecodable message: {}", e);
+                                let error = GameMessage::Error {
+                                    message: "Unsupported message".into(),
+                                };
+                                transport.send(&error).await?;
+                                continue;
+                            }
+                        };
+                        metrics.message_received(msg.kind());
+                        match msg {
+                            GameMessage::Move { .. } |
+                            GameMessage::Upgrade { .. } |
+                            GameMessage::Promote { .. } => {
+                                let mut rooms_guard = rooms.lock().await;
+                                let Some(room) = rooms_guard.get_mut(&room_name) else {
+                                    continue;
+                                };
+                                room.last_active = Instant::now();
+                                let was_over = room.game.result.is_some();
+                                let first_action = room.actions.is_empty();
+                                if !room.apply(color, &msg) {
+                                    drop(rooms_guard);
+                                    let error = GameMessage::Error {
+                                        message: "Illegal action".into(),
+                                    };
+                                    transport.send(&error).await?;
+                                    continue;
+                                }
+                                if first_action {
+                                    metrics.games_started.fetch_add(1, Ordering::Relaxed);
+                                }
+                                if room.correspondence {
+                                    save_correspondence_action(room, &room_name, &correspondence).await;
+                                }
+
+                                let target = if color == PlayerColor::White {
+                                    &room.black
+                                } else {
+                                    &room.white
+                                };
+                                if let Some(target_tx) = target {
+                                    target_tx.send(msg).ok();
+                                }
+
+                                if !was_over && let Some(result) = room.game.result {
+                                    metrics.games_finished.fetch_add(1, Ordering::Relaxed);
+                                    room.score.record(result, PlayerColor::White);
+                                    record_rated_result(room, result, &accounts).await;
+                                    archive_game(room, &room_name, result, &archive).await;
+                                    record_tournament_result(room, &room_name, result, &tournaments).await;
+                                }
+                            },
+                            GameMessage::QueryRating { .. } |
+                            GameMessage::QueryLeaderboard { .. } => {
+                                let reply = account_query_reply(&accounts, msg).await;
+                                transport.send(&reply).await?;
+                            },
+                            GameMessage::Ping { nonce } => {
+                                transport.send(&GameMessage::Pong { nonce }).await?;
+                            },
+                            GameMessage::CreateTournament { .. } |
+                            GameMessage::JoinTournament { .. } |
+                            GameMessage::StartTournament { .. } |
+                            GameMessage::QueryStandings { .. } => {
+                                let reply = tournament_reply(&tournaments, &accounts, account.as_deref(), msg).await;
+                                transport.send(&reply).await?;
+                            },
+                            GameMessage::CreateCorrespondence { .. } |
+                            GameMessage::QueryCorrespondence { .. } |
+                            GameMessage::AcceptCorrespondence { .. } |
+                            GameMessage::DeclineCorrespondence { .. } => {
+                                let reply = correspondence_reply(&correspondence, &accounts, account.as_deref(), msg).await;
+                                transport.send(&reply).await?;
+                            },
+                            GameMessage::OfferRematch | GameMessage::AcceptRematch => {
+                                let mut rooms_guard = rooms.lock().await;
+                                let Some(room) = rooms_guard.get_mut(&room_name) else {
+                                    continue;
+                                };
+                                let error = rematch(room, color, &msg);
+                                drop(rooms_guard);
+                                if let Some(message) = error {
+                                    transport.send(&GameMessage::Error { message: message.into() }).await?;
+                                }
+                            },
+                            GameMessage::RequestTakeback |
+                            GameMessage::AcceptTakeback |
+                            GameMessage::DeclineTakeback => {
+                                let mut rooms_guard = rooms.lock().await;
+                                let Some(room) = rooms_guard.get_mut(&room_name) else {
+                                    continue;
+                                };
+                                let error = takeback(room, color, &msg);
+                                drop(rooms_guard);
+                                if let Some(message) = error {
+                                    transport.send(&GameMessage::Error { message: message.into() }).await?;
+                                }
+                            },
+                            GameMessage::Pong { nonce } => {
+                                if let Some(hb) = heartbeat.as_mut() {
+                                    hb.pong(nonce);
+                                }
+                            },
+                            _ => {
+                                warn!("Ignored invalid message");
+                            }
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    let mut rooms_guard = rooms.lock().await;
+    if let Some(room) = rooms_guard.get_mut(&room_name) {
+        room.rematch_offer = None;
+        room.takeback_offer = None;
+        if color == PlayerColor::White {
+            room.white = None;
+            if let Some(black_tx) = &room.black {
+                black_tx.send(GameMessage::OpponentDisconnected).ok();
+            }
+        } else {
+            room.black = None;
+            if let Some(white_tx) = &room.white {
+                white_tx.send(GameMessage::OpponentDisconnected).ok();
+            }
+        }
+        // Restored rooms wait for the players still to come back.
+        let awaited = [PlayerColor::White, PlayerColor::Black]
+            .into_iter()
+            .any(|c| room.is_held(c));
+        if room.white.is_none() && room.black.is_none() && !awaited {
+            rooms_guard.remove(&room_name);
+        }
+    }
+
+    info!("User left room");
+    Ok(())
+}
+
+/// Charges one message against the connection's limiter, counting and
+/// answering rejected messages. The caller drops the message on `Reject` and
+/// the connection on `Disconnect`.
+async fn throttle(
+    limiter: &mut MessageLimiter,
+    transport: &mut Transport,
+    rejections: &RejectionCounters,
+) -> Result<Verdict, Box<dyn std::error::Error>> {
+    let verdict = limiter.check();
+    match verdict {
+        Verdict::Allow => {}
+        Verdict::Reject => {
+            rejections
+                .throttled_messages
+                .fetch_add(1, Ordering::Relaxed);
+            let error = GameMessage::Error {
+                message: "Too many messages, slow down".into(),
+            };
+            transport.send(&error).await?;
+        }
+        Verdict::Disconnect => {
+            rejections.flood_disconnects.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    Ok(verdict)
+}
+
+/// Public rooms without a password that are waiting for a second player
+/// before their first game, the first `limit` of them so the reply fits in
+/// one message, along with how many there are in all.
+async fn list_open_rooms(
+    rooms: &Mutex<HashMap<String, Room>>,
+    accounts: &Mutex<AccountStore>,
+    limit: usize,
+) -> (Vec<RoomInfo>, usize) {
+    let mut open: Vec<(String, Option<String>, PlayerColor)> = rooms
+        .lock()
+        .await
+        .iter()
+        .filter(|(_, room)| room.public && room.password.is_none() && room.actions.is_empty())
+        .filter_map(|(code, room)| match (&room.white, &room.black) {
+            (Some(_), None) => Some((code.clone(), room.white_account.clone(), PlayerColor::Black)),
+            (None, Some(_)) => Some((code.clone(), room.black_account.clone(), PlayerColor::White)),
+            _ => None,
+        })
+        .collect();
+    open.sort_by(|a, b| a.0.cmp(&b.0));
+    let total = open.len();
+    open.truncate(limit);
+
+    let accounts_guard = accounts.lock().await;
+    let listed = open
+        .into_iter()
+        .map(|(code, host, open_color)| RoomInfo {
+            code,
+            host_rating: host
+                .as_deref()
+                .and_then(|name| accounts_guard.entry(name))
+                .map(|entry| entry.rating),
+            host: host.unwrap_or_else(|| "Guest".to_string()),
+            open_color,
+        })
+        .collect();
+    (listed, total)
+}
+
+/// Handles a rematch offer or acceptance from `color`, returning an error
+/// for the player if it is not allowed right now.
+fn rematch(room: &mut Room, color: PlayerColor, msg: &GameMessage) -> Option<&'static str> {
+    if room.tournament.is_some() {
+        return Some("Rematches are not available in tournament games");
+    }
+    if room.correspondence {
+        return Some("Rematches are not available in correspondence games");
+    }
+    if room.game.result.is_none() {
+        return Some("The game is not over yet");
+    }
+    let opponent = match color {
+        PlayerColor::White => &room.black,
+        PlayerColor::Black => &room.white,
+    };
+    let Some(opponent) = opponent else {
+        return Some("Your opponent has left");
+    };
+
+    match msg {
+        GameMessage::OfferRematch if room.rematch_offer == Some(color.opposite()) => {
+            room.start_rematch();
+        }
+        GameMessage::OfferRematch => {
+            room.rematch_offer = Some(color);
+            opponent.send(GameMessage::OfferRematch).ok();
+        }
+        GameMessage::AcceptRematch if room.rematch_offer == Some(color.opposite()) => {
+            room.start_rematch();
+        }
+        _ => return Some("No rematch has been offered"),
+    }
+    room.last_active = Instant::now();
+    None
+}
+
+/// Handles a take-back request, acceptance or refusal from `color`,
+/// returning an error for the player if it is not allowed right now.
+fn takeback(room: &mut Room, color: PlayerColor, msg: &GameMessage) -> Option<&'static str> {
+    if room.tournament.is_some() {
+        return Some("Take-backs are not available in tournament games");
+    }
+    if room.correspondence {
+        return Some("Take-backs are not available in correspondence games");
+    }
+    if room.game.result.is_some() {
+        return Some("The game is over");
+    }
+    let opponent = match color {
+        PlayerColor::White => &room.black,
+        PlayerColor::Black => &room.white,
+    };
+    let Some(opponent) = opponent else {
+        return Some("Your opponent has left");
+    };
+
+    match msg {
+        GameMessage::RequestTakeback => {
+            if room.game.turn == color || room.last_turn_start(color).is_none() {
+                return Some(
+                    "You can only take back your last turn before your opponent finishes theirs",
+                );
+            }
+            room.takeback_offer = Some(color);
+            opponent.send(GameMessage::RequestTakeback).ok();
+        }
+        GameMessage::AcceptTakeback if room.takeback_offer == Some(color.opposite()) => {
+            room.take_back(color.opposite());
+        }
+        GameMessage::DeclineTakeback if room.takeback_offer == Some(color.opposite()) => {
+            room.takeback_offer = None;
+            opponent.send(GameMessage::DeclineTakeback).ok();
+        }
+        _ => return Some("No take-back has been requested"),
+    }
+    room.last_active = Instant::now();
+    None
+}
+
+async fn account_query_reply(accounts: &Mutex<AccountStore>, msg: GameMessage) -> GameMessage {
+    let accounts_guard = accounts.lock().await;
+    match msg {
+        GameMessage::QueryRating { name } => match accounts_guard.entry(&name) {
+            Some(entry) => GameMessage::Rating { entry },
+            None => GameMessage::Error {
+                message: format!("No account named {}", name),
+            },
+        },
+        GameMessage::QueryLeaderboard { limit } => GameMessage::Leaderboard {
+            entries: accounts_guard.leaderboard(limit),
+        },
+        _ => GameMessage::Error {
+            message: "Unsupported query".into(),
+        },
+    }
+}
+
+/// Updates both players' ratings once a game between two distinct logged-in
+/// accounts has finished, and sends each side their new rating.
+async fn record_rated_result(room: &Room, result: GameResult, accounts: &Mutex<AccountStore>) {
+    let (Some(white), Some(black)) = (&room.white_account, &room.black_account) else {
+        return;
+    };
+    if white == black {
+        return;
+    }
+
+    let mut accounts_guard = accounts.lock().await;
+    if let Err(e) = accounts_guard.record_result(white, black, result) {
+        error!("Failed to save rated result: {}", e);
+        return;
+    }
+    info!("Rated game finished: {} vs {} ({:?})", white, black, result);
+
+    for (tx, name) in [(&room.white, white), (&room.black, black)] {
+        if let (Some(tx), Some(entry)) = (tx, accounts_guard.entry(name)) {
+            tx.send(GameMessage::Rating { entry }).ok();
+        }
+    }
+}
+
+/// Answers a tournament request. Everything but querying standings needs a
+/// logged-in account.
+async fn tournament_reply(
+    tournaments: &Mutex<TournamentStore>,
+    accounts: &Mutex<AccountStore>,
+    account: Option<&str>,
+    msg: GameMessage,
+) -> GameMessage {
+    let result = match (msg, account) {
+        (GameMessage::QueryStandings { name }, _) => tournaments.lock().await.standings(&name),
+        (_, None) => Err("Log in to take part in tournaments".to_string()),
+        (GameMessage::CreateTournament { name, format }, Some(account)) => {
+            tournaments.lock().await.create(&name, account, format)
+        }
+        (GameMessage::JoinTournament { name }, Some(account)) => {
+            tournaments.lock().await.join(&name, account)
+        }
+        (GameMessage::StartTournament { name }, Some(account)) => {
+            let accounts_guard = accounts.lock().await;
+            let rating = |player: &str| accounts_guard.entry(player).map_or(0.0, |e| e.rating);
+            tournaments.lock().await.start(&name, account, rating)
+        }
+        _ => Err("Unsupported tournament request".to_string()),
+    };
+    match result {
+        Ok(standings) => GameMessage::Standings { standings },
+        Err(message) => GameMessage::Error { message },
+    }
+}
+
+/// Looks up who may sit in `room` if it belongs to a tournament pairing or a
+/// correspondence game.
+async fn reservation(
+    tournaments: &Mutex<TournamentStore>,
+    correspondence: &Mutex<CorrespondenceStore>,
+    room: &str,
+) -> Option<Reservation> {
+    if let Some((name, white, black)) = tournaments.lock().await.pairing_for_room(room) {
+        return Some(Reservation {
+            white,
+            black,
+            tournament: Some(name),
+            correspondence: None,
+        });
+    }
+    let snapshot = correspondence.lock().await.snapshot(room)?;
+    Some(Reservation {
+        white: snapshot.white_account.clone()?,
+        black: snapshot.black_account.clone()?,
+        tournament: None,
+        correspondence: Some(snapshot),
+    })
+}
+
+/// Answers a correspondence request from a logged-in account.
+async fn correspondence_reply(
+    correspondence: &Mutex<CorrespondenceStore>,
+    accounts: &Mutex<AccountStore>,
+    account: Option<&str>,
+    msg: GameMessage,
+) -> GameMessage {
+    let Some(account) = account else {
+        return GameMessage::Error {
+            message: "Log in to play correspondence games".into(),
+        };
+    };
+    let result = match msg {
+        GameMessage::CreateCorrespondence {
+            opponent,
+            days_per_move,
+            color,
+        } => {
+            if opponent == account {
+                Err("You cannot challenge yourself".to_string())
+            } else if accounts.lock().await.entry(&opponent).is_none() {
+                Err(format!("No account named {}", opponent))
+            } else {
+                let creator_white = match color.unwrap_or(ColorPreference::Random) {
+                    ColorPreference::White => true,
+                    ColorPreference::Black => false,
+                    ColorPreference::Random => rand::random(),
+                };
+                let (white, black) = if creator_white {
+                    (account, opponent.as_str())
+                } else {
+                    (opponent.as_str(), account)
+                };
+                let now = archive::unix_millis() / 1000;
+                correspondence
+                    .lock()
+                    .await
+                    .create(white, black, account, days_per_move, now)
+                    .map(|game| vec![game])
+            }
+        }
+        GameMessage::QueryCorrespondence { my_turn_only } => {
+            Ok(correspondence.lock().await.games_of(account, my_turn_only))
+        }
+        GameMessage::AcceptCorrespondence { room } => {
+            let now = archive::unix_millis() / 1000;
+            correspondence
+                .lock()
+                .await
+                .accept(&room, account, now)
+                .map(|game| vec![game])
+        }
+        GameMessage::DeclineCorrespondence { room } => {
+            let mut store = correspondence.lock().await;
+            store
+                .decline(&room, account)
+                .map(|()| store.games_of(account, false))
+        }
+        _ => Err("Unsupported correspondence request".to_string()),
+    };
+    match result {
+        Ok(games) => GameMessage::CorrespondenceGames { games },
+        Err(message) => GameMessage::Error { message },
+    }
+}
+
+/// Saves the action just applied in a correspondence game's room.
+async fn save_correspondence_action(
+    room: &Room,
+    room_name: &str,
+    correspondence: &Mutex<CorrespondenceStore>,
+) {
+    let Some(action) = room.actions.last() else {
+        return;
+    };
+    let now = archive::unix_millis() / 1000;
+    let saved = correspondence.lock().await.record_action(
+        room_name,
+        action.clone(),
+        room.game.turn,
+        room.game.result,
+        now,
+    );
+    if let Err(e) = saved {
+        error!("Failed to save correspondence game: {}", e);
+    }
+}
+
+/// Ends correspondence games whose player to move let the deadline pass,
+/// telling anyone in the room, and rates and archives them like any other
+/// finished game. Games in which nobody moved are closed unrated.
+async fn expire_correspondence_games(rooms: &mut HashMap<String, Room>, state: &ServerState) {
+    let now = archive::unix_millis() / 1000;
+    let expired = match state.correspondence.lock().await.expire(now) {
+        Ok(expired) => expired,
+        Err(e) => {
+            error!("Failed to save correspondence games: {}", e);
+            return;
+        }
+    };
+
+    for name in expired.aborted {
+        info!(room = %name, "Correspondence game aborted before the first move");
+        // Dropping the room ends the connections of anyone sitting in it.
+        if let Some(room) = rooms.remove(&name) {
+            for tx in [&room.white, &room.black].into_iter().flatten() {
+                tx.send(GameMessage::Error {
+                    message: "The game was aborted: nobody moved in time".into(),
+                })
+                .ok();
+            }
+        }
+    }
+
+    for (snapshot, result) in expired.forfeited {
+        let name = snapshot.name.clone();
+        let mut restored = None;
+        let room = match rooms.get_mut(&name) {
+            Some(room) => room,
+            None => match restored.insert(Room::restore(snapshot, Duration::ZERO)) {
+                Some(room) => room,
+                None => continue,
+            },
+        };
+        info!(room = %name, "Correspondence game lost on time");
+        room.game.result = Some(result);
+        if let GameResult::Win(winner) = result {
+            for tx in [&room.white, &room.black].into_iter().flatten() {
+                tx.send(GameMessage::TimeForfeit {
+                    color: winner.opposite(),
+                })
+                .ok();
+            }
+        }
+        state.metrics.games_finished.fetch_add(1, Ordering::Relaxed);
+        record_rated_result(room, result, &state.accounts).await;
+        archive_game(room, &name, result, &state.archive).await;
+    }
+}
+
+/// Who wins an abandoned tournament game: the only player still seated, or
+/// else the player not on move once the game is underway. `None` if both
+/// or neither stayed before anyone moved.
+fn forfeit_winner(room: &Room) -> Option<PlayerColor> {
+    match (&room.white, &room.black) {
+        (Some(_), None) => Some(PlayerColor::White),
+        (None, Some(_)) => Some(PlayerColor::Black),
+        _ if !room.actions.is_empty() => Some(room.game.turn.opposite()),
+        _ => None,
+    }
+}
+
+/// Forfeits the tournament games in `abandoned` rooms, each with its winner
+/// if any, and those whose room nobody opened within the room timeout of
+/// the round being paired, so a no-show cannot hold up the tournament.
+async fn forfeit_tournament_games(
+    rooms: &HashMap<String, Room>,
+    abandoned: Vec<(String, Option<PlayerColor>)>,
+    state: &ServerState,
+) {
+    let mut tournaments = state.tournaments.lock().await;
+    let forfeit = |tournaments: &mut TournamentStore, room: &str, winner| {
+        info!(room = %room, ?winner, "Tournament game forfeited");
+        if let Err(e) = tournaments.record_forfeit(room, winner) {
+            error!("Failed to save tournament result: {}", e);
+        }
+    };
+    for (room, winner) in abandoned {
+        forfeit(&mut tournaments, &room, winner);
+    }
+    let cutoff =
+        (archive::unix_millis() / 1000).saturating_sub(state.config.room_timeout.as_secs());
+    let no_shows: Vec<String> = tournaments
+        .rooms_paired_before(cutoff)
+        .into_iter()
+        .filter(|room| !rooms.contains_key(room))
+        .collect();
+    for room in no_shows {
+        forfeit(&mut tournaments, &room, None);
+    }
+}
+
+/// Records the result of a tournament game and sends both players the
+/// updated standings, which include their next pairing once the round is
+/// complete.
+async fn record_tournament_result(
+    room: &Room,
+    room_name: &str,
+    result: GameResult,
+    tournaments: &Mutex<TournamentStore>,
+) {
+    let Some(name) = &room.tournament else {
+        return;
+    };
+    let mut tournaments_guard = tournaments.lock().await;
+    if let Err(e) = tournaments_guard.record_result(room_name, result) {
+        error!("Failed to save tournament result: {}", e);
+        return;
+    }
+    if let Ok(standings) = tournaments_guard.standings(name) {
+        for tx in [&room.white, &room.black].into_iter().flatten() {
+            tx.send(GameMessage::Standings {
+                standings: standings.clone(),
+            })
+            .ok();
+        }
+    }
+}
+
+async fn archive_game(
+    room: &Room,
+    room_name: &str,
+    result: GameResult,
+    archive: &Mutex<GameArchive>,
+) {
+    let mut archive_guard = archive.lock().await;
+    let record = GameRecord {
+        summary: GameSummary {
+            id: archive_guard.next_id(),
+            white: room.white_account.clone(),
+            black: room.black_account.clone(),
+            result,
+            started_at: room.started_at,
+            finished_at: archive::unix_millis() / 1000,
+        },
+        room: room_name.to_string(),
+        white_points: room.game.white_points,
+        black_points: room.game.black_points,
+        actions: room.actions.clone(),
+    };
+    match archive_guard.save(&record) {
+        Ok(()) => info!(game = record.summary.id, "Archived game"),
+        Err(e) => error!("Failed to archive game: {}", e),
+    }
+}
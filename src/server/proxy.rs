@@ -0,0 +1,55 @@
+//! PROXY protocol (version 1) headers, which a load balancer such as fly.io's
+//! sends ahead of each connection to pass on the client's own address.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+// "PROXY TCP6 <39 chars> <39 chars> 65535 65535\r\n", see the specification.
+const MAX_HEADER_LEN: usize = 107;
+
+/// Reads the header off `socket`, returning the client's address, or `None`
+/// for connections the proxy made itself ("PROXY UNKNOWN").
+pub async fn read_source(socket: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut line = Vec::with_capacity(MAX_HEADER_LEN);
+    // Byte by byte, so nothing after the header is consumed.
+    while !line.ends_with(b"\r\n") {
+        if line.len() == MAX_HEADER_LEN {
+            return Err(invalid("header too long"));
+        }
+        line.push(socket.read_u8().await?);
+    }
+    parse(&line[..line.len() - 2])
+}
+
+fn parse(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line).map_err(|_| invalid("header is not text"))?;
+    let mut fields = line.split(' ');
+    if fields.next() != Some("PROXY") {
+        return Err(invalid("missing PROXY header"));
+    }
+    match fields.next() {
+        Some("UNKNOWN") => Ok(None),
+        Some("TCP4" | "TCP6") => {
+            let ip: IpAddr = field(fields.next(), "source address")?;
+            let _destination: IpAddr = field(fields.next(), "destination address")?;
+            let port: u16 = field(fields.next(), "source port")?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("unknown protocol")),
+    }
+}
+
+fn field<T: std::str::FromStr>(value: Option<&str>, name: &str) -> io::Result<T> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| invalid(&format!("invalid {}", name)))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("PROXY protocol: {}", message),
+    )
+}
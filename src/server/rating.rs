@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+const GLICKO_SCALE: f64 = 173.7178;
+const DEFAULT_RATING: f64 = 1500.0;
+const DEFAULT_DEVIATION: f64 = 350.0;
+const DEFAULT_VOLATILITY: f64 = 0.06;
+// System constant constraining volatility change, see Glickman's Glicko-2 paper.
+const TAU: f64 = 0.5;
+const CONVERGENCE_TOLERANCE: f64 = 0.000001;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: DEFAULT_RATING,
+            deviation: DEFAULT_DEVIATION,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+}
+
+impl Rating {
+    /// Rates a single game as its own rating period. `score` is 1.0 for a win,
+    /// 0.5 for a draw and 0.0 for a loss.
+    pub fn update(&self, opponent: &Rating, score: f64) -> Rating {
+        self.update_period(&[(*opponent, score)])
+    }
+
+    /// Rates every game of one rating period at once, as `(opponent, score)`.
+    fn update_period(&self, games: &[(Rating, f64)]) -> Rating {
+        let mu = (self.rating - DEFAULT_RATING) / GLICKO_SCALE;
+        let phi = self.deviation / GLICKO_SCALE;
+
+        let mut v_inverse = 0.0;
+        let mut improvement = 0.0;
+        for (opponent, score) in games {
+            let mu_j = (opponent.rating - DEFAULT_RATING) / GLICKO_SCALE;
+            let phi_j = opponent.deviation / GLICKO_SCALE;
+            let g = 1.0 / (1.0 + 3.0 * phi_j * phi_j / (PI * PI)).sqrt();
+            let expected = 1.0 / (1.0 + (-g * (mu - mu_j)).exp());
+            v_inverse += g * g * expected * (1.0 - expected);
+            improvement += g * (score - expected);
+        }
+        let v = 1.0 / v_inverse;
+        let delta = v * improvement;
+
+        let volatility = self.new_volatility(phi, v, delta);
+
+        let phi_star = (phi * phi + volatility * volatility).sqrt();
+        let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let new_mu = mu + new_phi * new_phi * improvement;
+
+        Rating {
+            rating: new_mu * GLICKO_SCALE + DEFAULT_RATING,
+            deviation: (new_phi * GLICKO_SCALE).min(DEFAULT_DEVIATION),
+            volatility,
+        }
+    }
+
+    fn new_volatility(&self, phi: f64, v: f64, delta: f64) -> f64 {
+        let a = (self.volatility * self.volatility).ln();
+        let f = |x: f64| {
+            let ex = x.exp();
+            let denom = phi * phi + v + ex;
+            ex * (delta * delta - phi * phi - v - ex) / (2.0 * denom * denom)
+                - (x - a) / (TAU * TAU)
+        };
+
+        let mut lower = a;
+        let mut upper = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * TAU) < 0.0 {
+                k += 1.0;
+            }
+            a - k * TAU
+        };
+
+        let mut f_lower = f(lower);
+        let mut f_upper = f(upper);
+        while (upper - lower).abs() > CONVERGENCE_TOLERANCE {
+            let c = lower + (lower - upper) * f_lower / (f_upper - f_lower);
+            let f_c = f(c);
+            if f_c * f_upper <= 0.0 {
+                lower = upper;
+                f_lower = f_upper;
+            } else {
+                f_lower /= 2.0;
+            }
+            upper = c;
+            f_upper = f_c;
+        }
+
+        (lower / 2.0).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            volatility: DEFAULT_VOLATILITY,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    /// The worked example from Glickman's "Example of the Glicko-2 system".
+    #[test]
+    fn matches_the_worked_example_from_the_paper() {
+        let player = rating(1500.0, 200.0);
+        let games = [
+            (rating(1400.0, 30.0), 1.0),
+            (rating(1550.0, 100.0), 0.0),
+            (rating(1700.0, 300.0), 0.0),
+        ];
+        let updated = player.update_period(&games);
+        assert_close(updated.rating, 1464.06, 0.01);
+        assert_close(updated.deviation, 151.52, 0.01);
+        assert_close(updated.volatility, 0.05999, 0.00001);
+    }
+
+    #[test]
+    fn a_single_game_is_a_period_of_its_own() {
+        let player = rating(1500.0, 200.0);
+        let opponent = rating(1400.0, 30.0);
+        assert_eq!(
+            player.update(&opponent, 1.0),
+            player.update_period(&[(opponent, 1.0)])
+        );
+    }
+
+    #[test]
+    fn winners_gain_what_losers_lose_between_equal_players() {
+        let white = Rating::default().update(&Rating::default(), 1.0);
+        let black = Rating::default().update(&Rating::default(), 0.0);
+        assert!(white.rating > DEFAULT_RATING);
+        assert_close(
+            white.rating - DEFAULT_RATING,
+            DEFAULT_RATING - black.rating,
+            1e-9,
+        );
+        assert!(white.deviation < DEFAULT_DEVIATION);
+
+        let drawn = Rating::default().update(&Rating::default(), 0.5);
+        assert_close(drawn.rating, DEFAULT_RATING, 1e-9);
+    }
+}
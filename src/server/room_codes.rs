@@ -0,0 +1,63 @@
+use rand::RngCore;
+use rand::seq::IndexedRandom;
+
+const CODE_WORDS: usize = 3;
+const MAX_ATTEMPTS: usize = 16;
+
+// 256 words, so a code of three distinct words is one of about 16.6 million.
+const WORDS: [&str; 256] = [
+    "acorn", "alder", "alpine", "amber", "anchor", "apple", "arrow", "aspen", "atlas", "aurora",
+    "autumn", "badge", "badger", "baker", "bamboo", "banjo", "barley", "basil", "beacon", "berry",
+    "birch", "bison", "blaze", "bloom", "blossom", "bolt", "bonfire", "bramble", "brass", "brave",
+    "breaker", "breeze", "brick", "bridge", "brook", "bubble", "cabin", "cactus", "cairn", "camel",
+    "canary", "candle", "canoe", "canyon", "caper", "carbon", "carrot", "cascade", "cashew",
+    "castle", "cedar", "cello", "chalk", "cherry", "chess", "chime", "cider", "cinder", "citrus",
+    "cliff", "cloud", "clover", "cobalt", "cobra", "cocoa", "comet", "condor", "copper", "coral",
+    "cosmos", "cotton", "crane", "crater", "cricket", "crow", "crystal", "cypress", "dagger",
+    "daisy", "dawn", "delta", "desert", "diamond", "dingo", "dolphin", "dove", "dragon", "drift",
+    "drum", "dune", "eagle", "echo", "ember", "emerald", "fable", "falcon", "feather", "fern",
+    "ferry", "fiddle", "fig", "finch", "fjord", "flame", "flint", "flute", "forest", "fossil",
+    "fox", "frost", "galaxy", "garden", "garnet", "gecko", "gem", "geyser", "ginger", "glacier",
+    "glade", "goblet", "gopher", "granite", "grape", "gravel", "grove", "gull", "halo", "harbor",
+    "harvest", "hawk", "hazel", "heath", "hedge", "heron", "hickory", "hollow", "honey", "horizon",
+    "hornet", "hyena", "ibis", "indigo", "ink", "iris", "island", "ivory", "jackal", "jade",
+    "jaguar", "jasmine", "jelly", "jester", "jewel", "jungle", "juniper", "kelp", "kettle", "kite",
+    "kiwi", "knight", "koala", "lagoon", "lake", "lantern", "lark", "laurel", "lava", "lemon",
+    "lemur", "lilac", "lily", "linen", "lion", "lotus", "lunar", "lynx", "magnet", "mango",
+    "maple", "marble", "marsh", "meadow", "melon", "mesa", "meteor", "mint", "mirror", "mole",
+    "monarch", "moon", "moose", "moss", "mountain", "narwhal", "nebula", "nectar", "newt",
+    "nickel", "nutmeg", "oak", "oasis", "ocean", "ocelot", "olive", "onyx", "opal", "orbit",
+    "orca", "orchid", "otter", "owl", "paddle", "panda", "panther", "papaya", "parrot", "peach",
+    "pearl", "pebble", "pecan", "pelican", "penguin", "pepper", "petal", "pilot", "pine", "planet",
+    "plum", "pony", "poppy", "prairie", "prism", "puma", "quail", "quartz", "quill", "rabbit",
+    "radish", "rain", "raven", "reed", "reef", "rhino", "ridge", "ripple", "river", "robin",
+    "rocket", "rose", "ruby", "rust", "saddle", "saffron", "sage", "salmon", "sand",
+];
+
+/// Picks a random code such as `amber-falcon-river` using the thread-local
+/// CSPRNG, retrying while `is_taken` reports a collision.
+pub fn generate(is_taken: impl Fn(&str) -> bool) -> Option<String> {
+    let mut rng = rand::rng();
+    (0..MAX_ATTEMPTS)
+        .map(|_| {
+            WORDS
+                .choose_multiple(&mut rng, CODE_WORDS)
+                .copied()
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .find(|code| !is_taken(code))
+}
+
+/// Whether `name` has the shape of a generated code, ignoring case.
+pub fn is_generated_code(name: &str) -> bool {
+    let parts: Vec<String> = name.split('-').map(str::to_lowercase).collect();
+    parts.len() == CODE_WORDS && parts.iter().all(|part| WORDS.contains(&part.as_str()))
+}
+
+/// A secret that gives its holder their seat back after a restart.
+pub fn rejoin_token() -> String {
+    let mut bytes = [0u8; 16];
+    rand::rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
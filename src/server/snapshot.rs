@@ -0,0 +1,49 @@
+use super::archive::RecordedAction;
+use crate::network::MatchScore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A room as written to disk at shutdown. The game itself is rebuilt on
+/// startup by replaying `actions`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RoomSnapshot {
+    pub name: String,
+    pub white_account: Option<String>,
+    pub black_account: Option<String>,
+    pub password: Option<String>,
+    pub started_at: u64,
+    pub actions: Vec<RecordedAction>,
+    /// Match score from the white seat's point of view.
+    #[serde(default)]
+    pub score: MatchScore,
+    #[serde(default)]
+    pub public: bool,
+    #[serde(default)]
+    pub tournament: Option<String>,
+    /// Rejoin tokens of the players seated at shutdown.
+    #[serde(default)]
+    pub white_token: Option<String>,
+    #[serde(default)]
+    pub black_token: Option<String>,
+}
+
+pub fn save(path: &Path, rooms: &[RoomSnapshot]) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, serde_json::to_vec_pretty(rooms)?)?;
+    fs::rename(tmp_path, path)
+}
+
+/// Reads and removes the snapshot at `path`, so a later crash cannot bring
+/// back rooms that have since moved on.
+pub fn take(path: &Path) -> io::Result<Vec<RoomSnapshot>> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let rooms = serde_json::from_slice(&data)?;
+    fs::remove_file(path)?;
+    Ok(rooms)
+}
@@ -0,0 +1,797 @@
+use super::archive;
+use crate::game::GameResult;
+use crate::network::{PairingInfo, StandingRow, TournamentFormat, TournamentStandings};
+use crate::types::PlayerColor;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const MAX_NAME_LEN: usize = 16;
+const MAX_PLAYERS: usize = 32;
+const MAX_SWISS_ROUNDS: u32 = 15;
+// Partners tried while looking for a round without rematches. The search is
+// exponential in the worst case and runs under the tournaments lock, so past
+// this the round falls back to pairing neighbours in the standings.
+const MAX_PAIRING_STEPS: u32 = 20_000;
+
+/// One game of a round. A `black` of `None` is a bye, scored as a win.
+#[derive(Serialize, Deserialize, Clone)]
+struct Pairing {
+    white: String,
+    black: Option<String>,
+    result: Option<GameResult>,
+    /// Decided without being played out: `result` goes to the only player
+    /// who turned up, and without one neither player scores.
+    #[serde(default)]
+    forfeit: bool,
+}
+
+impl Pairing {
+    fn is_finished(&self) -> bool {
+        self.result.is_some() || self.forfeit
+    }
+
+    /// Points scored by `player` in this game, if it is theirs and finished.
+    fn points_for(&self, player: &str) -> Option<f32> {
+        let color = if self.white == player {
+            PlayerColor::White
+        } else if self.black.as_deref() == Some(player) {
+            PlayerColor::Black
+        } else {
+            return None;
+        };
+        if !self.is_finished() {
+            return None;
+        }
+        // A forfeit without a winner is lost by both players.
+        match self.result.unwrap_or(GameResult::Win(color.opposite())) {
+            GameResult::Win(winner) if winner == color => Some(1.0),
+            GameResult::Win(_) => Some(0.0),
+            GameResult::Draw(_) => Some(0.5),
+        }
+    }
+
+    fn opponent_of(&self, player: &str) -> Option<&str> {
+        if self.white == player {
+            self.black.as_deref()
+        } else if self.black.as_deref() == Some(player) {
+            Some(&self.white)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Tournament {
+    name: String,
+    organizer: String,
+    format: TournamentFormat,
+    /// Registered players; sorted by rating when the tournament starts.
+    players: Vec<String>,
+    rounds: Vec<Vec<Pairing>>,
+    /// Unix time in seconds when the current round was paired.
+    #[serde(default = "unix_now")]
+    round_started_at: u64,
+}
+
+impl Tournament {
+    fn total_rounds(&self) -> u32 {
+        match self.format {
+            TournamentFormat::RoundRobin => {
+                let n = self.players.len() as u32;
+                if n.is_multiple_of(2) {
+                    n.saturating_sub(1)
+                } else {
+                    n
+                }
+            }
+            TournamentFormat::Swiss { rounds } => rounds,
+        }
+    }
+
+    fn round_complete(&self) -> bool {
+        self.rounds
+            .last()
+            .is_some_and(|round| round.iter().all(Pairing::is_finished))
+    }
+
+    fn finished(&self) -> bool {
+        self.rounds.len() as u32 == self.total_rounds() && self.round_complete()
+    }
+
+    fn room_name(&self, round: usize, board: usize) -> String {
+        format!("t-{}-{}-{}", self.name, round + 1, board + 1)
+    }
+
+    fn points(&self) -> HashMap<&str, f32> {
+        let mut points: HashMap<&str, f32> =
+            self.players.iter().map(|p| (p.as_str(), 0.0)).collect();
+        for pairing in self.rounds.iter().flatten() {
+            for player in [Some(pairing.white.as_str()), pairing.black.as_deref()]
+                .into_iter()
+                .flatten()
+            {
+                if let Some(scored) = pairing.points_for(player) {
+                    *points.entry(player).or_insert(0.0) += scored;
+                }
+            }
+        }
+        points
+    }
+
+    fn pair_next_round(&mut self) {
+        let round = match self.format {
+            TournamentFormat::RoundRobin => self.round_robin_round(self.rounds.len()),
+            TournamentFormat::Swiss { .. } => self.swiss_round(),
+        };
+        self.rounds.push(round);
+        self.round_started_at = unix_now();
+    }
+
+    /// Round `r` of a round robin by the circle method: the first player
+    /// stays put and alternates colors, everyone else rotates one seat.
+    fn round_robin_round(&self, r: usize) -> Vec<Pairing> {
+        let mut seats: Vec<Option<&String>> = self.players.iter().map(Some).collect();
+        if seats.len() % 2 == 1 {
+            seats.push(None);
+        }
+        let m = seats.len();
+        seats[1..].rotate_right(r % (m - 1));
+
+        (0..m / 2)
+            .filter_map(|i| {
+                let (a, b) = (seats[i], seats[m - 1 - i]);
+                let (white, black) = if i == 0 && r % 2 == 1 { (b, a) } else { (a, b) };
+                match (white, black) {
+                    (Some(white), Some(black)) => Some(pairing(white, Some(black))),
+                    (Some(player), None) | (None, Some(player)) => Some(pairing(player, None)),
+                    (None, None) => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Pairs players with equal or close scores who have not met yet, gives
+    /// any bye to the lowest ranked player without one, and balances colors.
+    fn swiss_round(&self) -> Vec<Pairing> {
+        let points = self.points();
+        let mut ranked: Vec<&str> = self.players.iter().map(String::as_str).collect();
+        // Stable sort keeps the rating order within a score group.
+        ranked.sort_by(|a, b| points[b].total_cmp(&points[a]));
+
+        let mut round = Vec::new();
+        if ranked.len() % 2 == 1 {
+            let had_bye = |player: &str| {
+                self.rounds
+                    .iter()
+                    .flatten()
+                    .any(|p| p.white == player && p.black.is_none())
+            };
+            let bye = ranked
+                .iter()
+                .rposition(|p| !had_bye(p))
+                .unwrap_or(ranked.len() - 1);
+            round.push(pairing(ranked.remove(bye), None));
+        }
+
+        let played: HashSet<(&str, &str)> = self
+            .rounds
+            .iter()
+            .flatten()
+            .filter_map(|p| Some((p.white.as_str(), p.black.as_deref()?)))
+            .flat_map(|(w, b)| [(w, b), (b, w)])
+            .collect();
+        let pairs = match_without_rematches(&ranked, &played)
+            .unwrap_or_else(|| ranked.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+
+        let mut boards: Vec<Pairing> = pairs
+            .into_iter()
+            .map(|(higher, lower)| {
+                let (white, black) = if self.prefers_white(lower, higher) {
+                    (lower, higher)
+                } else {
+                    (higher, lower)
+                };
+                pairing(white, Some(black))
+            })
+            .collect();
+        boards.append(&mut round);
+        boards
+    }
+
+    /// Whether `player` should get White against `opponent`: whoever has had
+    /// White less often gets it, then whoever had Black last.
+    fn prefers_white(&self, player: &str, opponent: &str) -> bool {
+        let history = |name: &str| {
+            let mut balance = 0i32;
+            let mut last = None;
+            for p in self.rounds.iter().flatten() {
+                if p.black.is_none() {
+                    continue;
+                }
+                if p.white == name {
+                    balance += 1;
+                    last = Some(PlayerColor::White);
+                } else if p.black.as_deref() == Some(name) {
+                    balance -= 1;
+                    last = Some(PlayerColor::Black);
+                }
+            }
+            (balance, last)
+        };
+        let (mine, my_last) = history(player);
+        let (theirs, their_last) = history(opponent);
+        if mine != theirs {
+            return mine < theirs;
+        }
+        my_last == Some(PlayerColor::Black) && their_last != Some(PlayerColor::Black)
+    }
+
+    fn standings(&self) -> TournamentStandings {
+        let points = self.points();
+        let mut rows: Vec<StandingRow> = self
+            .players
+            .iter()
+            .map(|player| {
+                let mut buchholz = 0.0;
+                let mut sonneborn_berger = 0.0;
+                let mut games = 0;
+                for p in self.rounds.iter().flatten() {
+                    let Some(scored) = p.points_for(player) else {
+                        continue;
+                    };
+                    games += 1;
+                    if let Some(opponent) = p.opponent_of(player) {
+                        buchholz += points[opponent];
+                        sonneborn_berger += points[opponent] * scored;
+                    }
+                }
+                StandingRow {
+                    rank: 0,
+                    name: player.clone(),
+                    points: points[player.as_str()],
+                    buchholz,
+                    sonneborn_berger,
+                    games,
+                }
+            })
+            .collect();
+        rows.sort_by(|a, b| {
+            b.points
+                .total_cmp(&a.points)
+                .then(b.buchholz.total_cmp(&a.buchholz))
+                .then(b.sonneborn_berger.total_cmp(&a.sonneborn_berger))
+                .then(a.name.cmp(&b.name))
+        });
+        for (i, row) in rows.iter_mut().enumerate() {
+            row.rank = i as u32 + 1;
+        }
+
+        let round = self.rounds.len();
+        let pairings = self
+            .rounds
+            .last()
+            .map(|pairings| {
+                pairings
+                    .iter()
+                    .enumerate()
+                    .map(|(board, p)| PairingInfo {
+                        room: self.room_name(round - 1, board),
+                        white: p.white.clone(),
+                        black: p.black.clone(),
+                        result: p.result,
+                        forfeit: p.forfeit,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        TournamentStandings {
+            name: self.name.clone(),
+            format: self.format,
+            organizer: self.organizer.clone(),
+            round: round as u32,
+            total_rounds: self.total_rounds(),
+            finished: self.finished(),
+            rows,
+            pairings,
+        }
+    }
+}
+
+fn pairing(white: &str, black: Option<&str>) -> Pairing {
+    Pairing {
+        white: white.to_string(),
+        black: black.map(str::to_string),
+        // A bye counts as a win for the player who gets it.
+        result: black
+            .is_none()
+            .then_some(GameResult::Win(PlayerColor::White)),
+        forfeit: false,
+    }
+}
+
+fn unix_now() -> u64 {
+    archive::unix_millis() / 1000
+}
+
+/// Pairs `ranked` top-down so nobody meets an earlier opponent, trying the
+/// closest ranked partner first. Returns `None` if that is impossible, or if
+/// no pairing turned up within `MAX_PAIRING_STEPS`.
+fn match_without_rematches<'a>(
+    ranked: &[&'a str],
+    played: &HashSet<(&str, &str)>,
+) -> Option<Vec<(&'a str, &'a str)>> {
+    let mut steps = MAX_PAIRING_STEPS;
+    search_pairs(ranked, played, &mut steps)
+}
+
+fn search_pairs<'a>(
+    ranked: &[&'a str],
+    played: &HashSet<(&str, &str)>,
+    steps: &mut u32,
+) -> Option<Vec<(&'a str, &'a str)>> {
+    let Some((&first, rest)) = ranked.split_first() else {
+        return Some(Vec::new());
+    };
+    for (i, &partner) in rest.iter().enumerate() {
+        if played.contains(&(first, partner)) {
+            continue;
+        }
+        if *steps == 0 {
+            return None;
+        }
+        *steps -= 1;
+        let remaining: Vec<&str> = rest
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, &p)| p)
+            .collect();
+        if let Some(mut pairs) = search_pairs(&remaining, played, steps) {
+            pairs.insert(0, (first, partner));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+/// Tournaments persisted as a single JSON file, rewritten on every change.
+pub struct TournamentStore {
+    path: PathBuf,
+    tournaments: HashMap<String, Tournament>,
+}
+
+impl TournamentStore {
+    pub fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let tournaments = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, tournaments })
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&self.tournaments)?)?;
+        fs::rename(tmp_path, &self.path)
+    }
+
+    pub fn create(
+        &mut self,
+        name: &str,
+        organizer: &str,
+        format: TournamentFormat,
+    ) -> Result<TournamentStandings, String> {
+        let name = name.to_lowercase();
+        if name.is_empty()
+            || name.len() > MAX_NAME_LEN
+            || !name.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return Err("Tournament names are 1 to 16 letters and digits".into());
+        }
+        if let TournamentFormat::Swiss { rounds } = format
+            && !(1..=MAX_SWISS_ROUNDS).contains(&rounds)
+        {
+            return Err(format!(
+                "A Swiss tournament has 1 to {} rounds",
+                MAX_SWISS_ROUNDS
+            ));
+        }
+        if self.tournaments.contains_key(&name) {
+            return Err("A tournament with that name already exists".into());
+        }
+
+        let tournament = Tournament {
+            name: name.clone(),
+            organizer: organizer.to_string(),
+            format,
+            players: Vec::new(),
+            rounds: Vec::new(),
+            round_started_at: 0,
+        };
+        let standings = tournament.standings();
+        self.tournaments.insert(name, tournament);
+        self.save().map_err(|e| e.to_string())?;
+        Ok(standings)
+    }
+
+    pub fn join(&mut self, name: &str, player: &str) -> Result<TournamentStandings, String> {
+        let tournament = self.get_mut(name)?;
+        if !tournament.rounds.is_empty() {
+            return Err("The tournament has already started".into());
+        }
+        if tournament.players.iter().any(|p| p == player) {
+            return Err("You are already registered".into());
+        }
+        if tournament.players.len() >= MAX_PLAYERS {
+            return Err("The tournament is full".into());
+        }
+        tournament.players.push(player.to_string());
+        let standings = tournament.standings();
+        self.save().map_err(|e| e.to_string())?;
+        Ok(standings)
+    }
+
+    /// Seeds players by `rating` and pairs the first round.
+    pub fn start(
+        &mut self,
+        name: &str,
+        organizer: &str,
+        rating: impl Fn(&str) -> f64,
+    ) -> Result<TournamentStandings, String> {
+        let tournament = self.get_mut(name)?;
+        if tournament.organizer != organizer {
+            return Err("Only the organizer can start the tournament".into());
+        }
+        if !tournament.rounds.is_empty() {
+            return Err("The tournament has already started".into());
+        }
+        if tournament.players.len() < 2 {
+            return Err("At least two players must register first".into());
+        }
+        tournament
+            .players
+            .sort_by(|a, b| rating(b).total_cmp(&rating(a)));
+        tournament.pair_next_round();
+        let standings = tournament.standings();
+        self.save().map_err(|e| e.to_string())?;
+        Ok(standings)
+    }
+
+    pub fn standings(&self, name: &str) -> Result<TournamentStandings, String> {
+        self.tournaments
+            .get(&name.to_lowercase())
+            .map(Tournament::standings)
+            .ok_or_else(|| "No tournament with that name".to_string())
+    }
+
+    /// Tournament name and the white and black players of the unfinished
+    /// game that `room` is reserved for.
+    pub fn pairing_for_room(&self, room: &str) -> Option<(String, String, String)> {
+        self.tournaments.values().find_map(|t| {
+            let round = t.rounds.len().checked_sub(1)?;
+            t.rounds[round]
+                .iter()
+                .enumerate()
+                .find(|(board, p)| !p.is_finished() && t.room_name(round, *board) == room)
+                .and_then(|(_, p)| Some((t.name.clone(), p.white.clone(), p.black.clone()?)))
+        })
+    }
+
+    /// Records the result of the game played in `room` and pairs the next
+    /// round once every game of the current one has finished.
+    pub fn record_result(&mut self, room: &str, result: GameResult) -> io::Result<()> {
+        self.finish(room, |pairing| pairing.result = Some(result))
+    }
+
+    /// Settles the game in `room` that was never played out, as a win for
+    /// `winner` or, without one, as a loss for both players.
+    pub fn record_forfeit(&mut self, room: &str, winner: Option<PlayerColor>) -> io::Result<()> {
+        self.finish(room, |pairing| {
+            pairing.result = winner.map(GameResult::Win);
+            pairing.forfeit = true;
+        })
+    }
+
+    /// Rooms of unfinished games in rounds paired at or before `cutoff`, in
+    /// Unix seconds.
+    pub fn rooms_paired_before(&self, cutoff: u64) -> Vec<String> {
+        self.tournaments
+            .values()
+            .filter(|t| t.round_started_at <= cutoff)
+            .flat_map(|t| {
+                let round = t.rounds.len().saturating_sub(1);
+                t.rounds
+                    .last()
+                    .into_iter()
+                    .flatten()
+                    .enumerate()
+                    .filter(|(_, p)| !p.is_finished())
+                    .map(move |(board, _)| t.room_name(round, board))
+            })
+            .collect()
+    }
+
+    fn finish(&mut self, room: &str, settle: impl FnOnce(&mut Pairing)) -> io::Result<()> {
+        let Some(tournament) = self.tournaments.values_mut().find(|t| {
+            t.rounds.len().checked_sub(1).is_some_and(|round| {
+                (0..t.rounds[round].len()).any(|b| t.room_name(round, b) == room)
+            })
+        }) else {
+            return Ok(());
+        };
+        let round = tournament.rounds.len() - 1;
+        let Some(board) =
+            (0..tournament.rounds[round].len()).find(|&b| tournament.room_name(round, b) == room)
+        else {
+            return Ok(());
+        };
+        let pairing = &mut tournament.rounds[round][board];
+        if pairing.is_finished() {
+            return Ok(());
+        }
+        settle(pairing);
+
+        if tournament.round_complete()
+            && (tournament.rounds.len() as u32) < tournament.total_rounds()
+        {
+            tournament.pair_next_round();
+        }
+        self.save()
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut Tournament, String> {
+        self.tournaments
+            .get_mut(&name.to_lowercase())
+            .ok_or_else(|| "No tournament with that name".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::DrawReason;
+
+    fn tournament(format: TournamentFormat, players: &[&str]) -> Tournament {
+        Tournament {
+            name: "test".into(),
+            organizer: players[0].into(),
+            format,
+            players: players.iter().map(|p| p.to_string()).collect(),
+            rounds: Vec::new(),
+            round_started_at: 0,
+        }
+    }
+
+    /// Plays out every round, the higher seeded player winning each game.
+    fn play_all(tournament: &mut Tournament) {
+        let seeds = tournament.players.clone();
+        let seed = |name: &str| seeds.iter().position(|p| p == name);
+        while (tournament.rounds.len() as u32) < tournament.total_rounds() {
+            tournament.pair_next_round();
+            let results: Vec<_> = tournament
+                .rounds
+                .last()
+                .unwrap()
+                .iter()
+                .map(|p| {
+                    p.result.or_else(|| {
+                        let white_wins = seed(&p.white) < seed(p.black.as_deref()?);
+                        Some(GameResult::Win(if white_wins {
+                            PlayerColor::White
+                        } else {
+                            PlayerColor::Black
+                        }))
+                    })
+                })
+                .collect();
+            for (pairing, result) in tournament
+                .rounds
+                .last_mut()
+                .unwrap()
+                .iter_mut()
+                .zip(results)
+            {
+                pairing.result = result;
+            }
+        }
+        assert!(tournament.finished());
+    }
+
+    fn games(tournament: &Tournament) -> Vec<(String, String)> {
+        tournament
+            .rounds
+            .iter()
+            .flatten()
+            .filter_map(|p| {
+                let black = p.black.clone()?;
+                Some(if p.white < black {
+                    (p.white.clone(), black)
+                } else {
+                    (black, p.white.clone())
+                })
+            })
+            .collect()
+    }
+
+    fn byes(tournament: &Tournament) -> Vec<&str> {
+        tournament
+            .rounds
+            .iter()
+            .flatten()
+            .filter(|p| p.black.is_none())
+            .map(|p| p.white.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn round_robin_pairs_everyone_exactly_once() {
+        for players in [&["a", "b", "c", "d"][..], &["a", "b", "c", "d", "e"]] {
+            let mut t = tournament(TournamentFormat::RoundRobin, players);
+            play_all(&mut t);
+
+            let n = players.len();
+            let mut games = games(&t);
+            games.sort();
+            let unique = games.len();
+            games.dedup();
+            assert_eq!(games.len(), unique, "a pair met twice");
+            assert_eq!(games.len(), n * (n - 1) / 2);
+            for round in &t.rounds {
+                let mut seated: Vec<&str> = round
+                    .iter()
+                    .flat_map(|p| [Some(p.white.as_str()), p.black.as_deref()])
+                    .flatten()
+                    .collect();
+                seated.sort();
+                assert_eq!(seated, players, "everyone plays once a round");
+            }
+        }
+    }
+
+    #[test]
+    fn round_robin_gives_each_player_one_bye_with_an_odd_field() {
+        let mut t = tournament(TournamentFormat::RoundRobin, &["a", "b", "c", "d", "e"]);
+        play_all(&mut t);
+        let mut byes = byes(&t);
+        byes.sort();
+        assert_eq!(byes, ["a", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn round_robin_alternates_the_first_seeds_colors() {
+        let mut t = tournament(TournamentFormat::RoundRobin, &["a", "b", "c", "d"]);
+        play_all(&mut t);
+        let first_board: Vec<bool> = t.rounds.iter().map(|r| r[0].white == "a").collect();
+        assert_eq!(first_board, [true, false, true]);
+    }
+
+    #[test]
+    fn swiss_rounds_avoid_rematches() {
+        let players = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let mut t = tournament(TournamentFormat::Swiss { rounds: 5 }, &players);
+        play_all(&mut t);
+
+        let mut games = games(&t);
+        assert_eq!(games.len(), 5 * players.len() / 2);
+        games.sort();
+        let played = games.len();
+        games.dedup();
+        assert_eq!(games.len(), played, "a pair met twice");
+    }
+
+    #[test]
+    fn swiss_byes_go_to_the_lowest_ranked_player_without_one() {
+        let mut t = tournament(
+            TournamentFormat::Swiss { rounds: 3 },
+            &["a", "b", "c", "d", "e"],
+        );
+        // With the higher seed always winning, the standings follow the seeds.
+        play_all(&mut t);
+        assert_eq!(byes(&t), ["e", "d", "c"]);
+    }
+
+    #[test]
+    fn pairing_search_gives_up_when_no_pairing_exists() {
+        let players: Vec<String> = (0..32).map(|i| format!("p{}", i)).collect();
+        let ranked: Vec<&str> = players.iter().map(String::as_str).collect();
+        // The first 17 players have all met, leaving 15 partners for 17 of
+        // them: no pairing exists, and the unbounded search would not finish.
+        let played: HashSet<(&str, &str)> = ranked[..17]
+            .iter()
+            .flat_map(|&a| ranked[..17].iter().map(move |&b| (a, b)))
+            .collect();
+        assert_eq!(match_without_rematches(&ranked, &played), None);
+    }
+
+    #[test]
+    fn standings_break_ties_by_buchholz_then_sonneborn_berger() {
+        let mut t = tournament(TournamentFormat::Swiss { rounds: 2 }, &["a", "b", "c", "d"]);
+        let draw = GameResult::Draw(DrawReason::ThreeFoldRepetition);
+        t.rounds = vec![
+            vec![
+                Pairing {
+                    white: "a".into(),
+                    black: Some("b".into()),
+                    result: Some(GameResult::Win(PlayerColor::White)),
+                    forfeit: false,
+                },
+                Pairing {
+                    white: "c".into(),
+                    black: Some("d".into()),
+                    result: Some(draw),
+                    forfeit: false,
+                },
+            ],
+            vec![
+                Pairing {
+                    white: "c".into(),
+                    black: Some("a".into()),
+                    result: Some(draw),
+                    forfeit: false,
+                },
+                Pairing {
+                    white: "d".into(),
+                    black: Some("b".into()),
+                    result: Some(GameResult::Win(PlayerColor::Black)),
+                    forfeit: false,
+                },
+            ],
+        ];
+
+        let standings = t.standings();
+        let rows: Vec<(&str, f32, f32, f32)> = standings
+            .rows
+            .iter()
+            .map(|r| (r.name.as_str(), r.points, r.buchholz, r.sonneborn_berger))
+            .collect();
+        // b and c both have a point and a Buchholz of 2, but c drew with the
+        // leader while b only beat the last placed player.
+        assert_eq!(
+            rows,
+            [
+                ("a", 1.5, 2.0, 1.5),
+                ("c", 1.0, 2.0, 1.0),
+                ("b", 1.0, 2.0, 0.5),
+                ("d", 0.5, 2.0, 0.5),
+            ]
+        );
+        assert!(standings.finished);
+    }
+
+    #[test]
+    fn forfeits_finish_the_round_and_score_only_for_the_player_present() {
+        let path =
+            std::env::temp_dir().join(format!("ascension-tournaments-{}.json", std::process::id()));
+        let mut store = TournamentStore::load(&path).unwrap();
+        store
+            .create("cup", "a", TournamentFormat::Swiss { rounds: 2 })
+            .unwrap();
+        for player in ["a", "b", "c", "d"] {
+            store.join("cup", player).unwrap();
+        }
+        store.start("cup", "a", |_| 0.0).unwrap();
+        let rooms = store.rooms_paired_before(unix_now());
+        assert_eq!(rooms, ["t-cup-1-1", "t-cup-1-2"]);
+
+        store
+            .record_forfeit(&rooms[0], Some(PlayerColor::Black))
+            .unwrap();
+        store.record_forfeit(&rooms[1], None).unwrap();
+        let standings = store.standings("cup").unwrap();
+        assert_eq!(standings.round, 2);
+        let points: Vec<(&str, f32)> = standings
+            .rows
+            .iter()
+            .map(|r| (r.name.as_str(), r.points))
+            .collect();
+        assert_eq!(points, [("b", 1.0), ("a", 0.0), ("c", 0.0), ("d", 0.0)]);
+        fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,122 @@
+use crate::network::{Encoding, GameMessage};
+use crate::tls::Stream as Socket;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+#[derive(Clone, Copy, Debug)]
+pub enum TransportKind {
+    Tcp,
+    WebSocket,
+}
+
+/// A client connection carrying `GameMessage`s, either as length-delimited
+/// frames over raw TCP or as WebSocket messages, optionally inside TLS.
+/// Messages are JSON until the handshake negotiates another encoding.
+pub struct Transport {
+    stream: Stream,
+    encoding: Encoding,
+}
+
+enum Stream {
+    Tcp(Framed<Box<dyn Socket>, LengthDelimitedCodec>),
+    WebSocket(WebSocketStream<Box<dyn Socket>>),
+}
+
+impl Transport {
+    pub async fn accept(
+        socket: TcpStream,
+        kind: TransportKind,
+        max_msg_size: usize,
+        tls: Option<&TlsAcceptor>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let socket: Box<dyn Socket> = match tls {
+            Some(acceptor) => Box::new(acceptor.accept(socket).await?),
+            None => Box::new(socket),
+        };
+        match kind {
+            TransportKind::Tcp => Ok(Self::tcp(socket, max_msg_size)),
+            TransportKind::WebSocket => Self::websocket(socket, max_msg_size).await,
+        }
+    }
+
+    pub fn tcp(socket: Box<dyn Socket>, max_msg_size: usize) -> Self {
+        let codec = LengthDelimitedCodec::builder()
+            .max_frame_length(max_msg_size)
+            .new_codec();
+        Transport {
+            stream: Stream::Tcp(Framed::new(socket, codec)),
+            encoding: Encoding::Json,
+        }
+    }
+
+    pub async fn websocket(
+        socket: Box<dyn Socket>,
+        max_msg_size: usize,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let config = WebSocketConfig::default()
+            .max_message_size(Some(max_msg_size))
+            .max_frame_size(Some(max_msg_size));
+        let ws = tokio_tungstenite::accept_async_with_config(socket, Some(config)).await?;
+        Ok(Transport {
+            stream: Stream::WebSocket(ws),
+            encoding: Encoding::Json,
+        })
+    }
+
+    /// Encoding of every message sent or decoded from now on.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn decode(
+        &self,
+        bytes: &[u8],
+    ) -> Result<GameMessage, Box<dyn std::error::Error + Send + Sync>> {
+        self.encoding.decode(bytes)
+    }
+
+    /// Returns the next message payload, or `None` once the peer has closed
+    /// the connection. Cancel-safe, so it can be used in `tokio::select!`.
+    pub async fn recv(
+        &mut self,
+    ) -> Option<Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>>> {
+        match &mut self.stream {
+            Stream::Tcp(framed) => framed
+                .next()
+                .await
+                .map(|result| result.map(|bytes| bytes.to_vec()).map_err(Into::into)),
+            Stream::WebSocket(ws) => loop {
+                match ws.next().await? {
+                    Ok(Message::Text(text)) => return Some(Ok(text.as_bytes().to_vec())),
+                    Ok(Message::Binary(bytes)) => return Some(Ok(bytes.to_vec())),
+                    Ok(Message::Close(_)) => return None,
+                    Ok(_) => continue,
+                    Err(e) => return Some(Err(e.into())),
+                }
+            },
+        }
+    }
+
+    pub async fn send(&mut self, msg: &GameMessage) -> Result<(), Box<dyn std::error::Error>> {
+        let bytes = self
+            .encoding
+            .encode(msg)
+            .map_err(|e| e as Box<dyn std::error::Error>)?;
+        match &mut self.stream {
+            Stream::Tcp(framed) => framed.send(bytes.into()).await?,
+            Stream::WebSocket(ws) => {
+                let frame = match self.encoding {
+                    Encoding::Json => Message::text(String::from_utf8(bytes)?),
+                    Encoding::MessagePack => Message::binary(bytes),
+                };
+                ws.send(frame).await?
+            }
+        }
+        Ok(())
+    }
+}
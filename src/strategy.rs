@@ -0,0 +1,119 @@
+//! Move selection for computer players such as the bot and the load tester.
+
+use crate::game::{Game, TurnPhase};
+use crate::network::GameAction;
+use crate::types::{PieceType, PlayerColor, Pos};
+use clap::ValueEnum;
+use rand::prelude::*;
+
+/// Chooses the bot's actions. `choose` is called whenever it is the bot's
+/// turn, including after an upgrade and while a promotion is pending.
+pub trait MoveSelector: Send {
+    fn choose(&mut self, game: &Game) -> Option<GameAction>;
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Strategy {
+    /// Plays a uniformly random legal move, sometimes upgrading first.
+    Random,
+}
+
+impl Strategy {
+    pub fn selector(self) -> Box<dyn MoveSelector> {
+        match self {
+            Strategy::Random => Box::new(RandomMover::default()),
+        }
+    }
+}
+
+/// Chance that the random mover spends its points on an upgrade when it can.
+const UPGRADE_CHANCE: f64 = 0.2;
+
+const PROMOTIONS: [PieceType; 9] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Hawk,
+    PieceType::Elephant,
+    PieceType::Archbishop,
+    PieceType::Cannon,
+    PieceType::Monarch,
+];
+
+pub struct RandomMover {
+    rng: StdRng,
+}
+
+impl Default for RandomMover {
+    fn default() -> Self {
+        Self {
+            rng: StdRng::from_os_rng(),
+        }
+    }
+}
+
+impl MoveSelector for RandomMover {
+    fn choose(&mut self, game: &Game) -> Option<GameAction> {
+        if let TurnPhase::Promoting(..) = game.phase {
+            let options: Vec<PieceType> = PROMOTIONS
+                .into_iter()
+                .filter(|&piece_type| game.can_promote_to(piece_type))
+                .collect();
+            let piece_type = *options.choose(&mut self.rng)?;
+            return Some(GameAction::Promote { piece_type });
+        }
+
+        let moves = legal_moves(game);
+        if game.phase == TurnPhase::Normal && self.rng.random_bool(UPGRADE_CHANCE) {
+            // Only upgrade a piece if another one can still move afterwards.
+            let upgrades: Vec<Pos> = upgradable(game)
+                .into_iter()
+                .filter(|&pos| moves.iter().any(|&(from, _)| from != pos))
+                .collect();
+            if let Some(&pos) = upgrades.choose(&mut self.rng) {
+                return Some(GameAction::Upgrade { pos });
+            }
+        }
+
+        let &(from, to) = moves.choose(&mut self.rng)?;
+        Some(GameAction::Move { from, to })
+    }
+}
+
+/// Every legal move for the side to move in the current phase.
+fn legal_moves(game: &Game) -> Vec<(Pos, Pos)> {
+    let mut moves = Vec::new();
+    for from in squares() {
+        for to in game.board.get_legal_moves(from) {
+            if game.is_legal_move(from, to) {
+                moves.push((from, to));
+            }
+        }
+    }
+    moves
+}
+
+/// Squares holding a piece the side to move can afford to upgrade now.
+fn upgradable(game: &Game) -> Vec<Pos> {
+    if game.board.is_in_check(game.turn) {
+        return Vec::new();
+    }
+    let points = match game.turn {
+        PlayerColor::White => game.white_points,
+        PlayerColor::Black => game.black_points,
+    };
+    squares()
+        .filter(|&pos| {
+            game.board.get_piece(pos).is_some_and(|piece| {
+                piece.color == game.turn
+                    && piece.upgraded_type().is_some()
+                    && piece.upgrade_cost().is_some_and(|cost| points >= cost)
+            })
+        })
+        .collect()
+}
+
+fn squares() -> impl Iterator<Item = Pos> {
+    (0..8).flat_map(|y| (0..8).map(move |x| Pos::new(x, y)))
+}
@@ -0,0 +1,342 @@
+use ascension_chess::game::{DrawReason, GameResult};
+use ascension_chess::network::{
+    ColorPreference, CorrespondenceInfo, Encoding, GameAction, GameMessage, MatchScore,
+    PairingInfo, RatingEntry, RoomInfo, StandingRow, TournamentFormat, TournamentStandings,
+};
+use ascension_chess::types::{PieceType, PlayerColor, Pos};
+use std::collections::HashSet;
+
+const ENCODINGS: [Encoding; 2] = [Encoding::Json, Encoding::MessagePack];
+
+fn rating(name: &str) -> RatingEntry {
+    RatingEntry {
+        name: name.into(),
+        rating: 1612.5,
+        deviation: 87.25,
+        wins: 12,
+        losses: 7,
+        draws: 3,
+    }
+}
+
+/// One message of every variant, with optional fields both set and unset.
+/// `variant_index` fails to compile when a variant is added, as a reminder
+/// to add it here too.
+fn every_variant() -> Vec<GameMessage> {
+    vec![
+        GameMessage::hello("tests"),
+        GameMessage::ServerHello {
+            protocol_version: 2,
+            server_name: "ascension-chess-server".into(),
+            capabilities: vec!["heartbeat".into(), "msgpack".into()],
+        },
+        GameMessage::UpgradeRequired {
+            min_version: 2,
+            message: "Please update".into(),
+        },
+        GameMessage::Join {
+            room: Some("amber-fox".into()),
+            password: Some("hunter2".into()),
+            color: Some(ColorPreference::Black),
+            public: true,
+            rejoin_token: Some("9f86d081884c7d65".into()),
+        },
+        GameMessage::Join {
+            room: None,
+            password: None,
+            color: None,
+            public: false,
+            rejoin_token: None,
+        },
+        GameMessage::RoomCode {
+            code: "amber-fox".into(),
+        },
+        GameMessage::Welcome {
+            color: PlayerColor::Black,
+            rejoin_token: Some("9f86d081884c7d65".into()),
+        },
+        GameMessage::Move {
+            from: Pos::new(4, 6),
+            to: Pos::new(4, 4),
+        },
+        GameMessage::Upgrade {
+            pos: Pos::new(0, 7),
+        },
+        GameMessage::Promote {
+            piece_type: PieceType::Archbishop,
+        },
+        GameMessage::Error {
+            message: "Illegal action".into(),
+        },
+        GameMessage::OpponentDisconnected,
+        GameMessage::Register {
+            name: "alice".into(),
+        },
+        GameMessage::Registered {
+            name: "alice".into(),
+            token: "0123abcd".into(),
+        },
+        GameMessage::Login {
+            name: "alice".into(),
+            token: "0123abcd".into(),
+        },
+        GameMessage::QueryRating {
+            name: "alice".into(),
+        },
+        GameMessage::Rating {
+            entry: rating("alice"),
+        },
+        GameMessage::QueryLeaderboard { limit: 10 },
+        GameMessage::Leaderboard {
+            entries: vec![rating("alice"), rating("bob")],
+        },
+        GameMessage::Ping { nonce: u64::MAX },
+        GameMessage::Pong { nonce: 0 },
+        GameMessage::ServerRestarting {
+            message: "Back in a minute".into(),
+            room: Some("amber-fox".into()),
+            rejoin_token: Some("9f86d081884c7d65".into()),
+        },
+        GameMessage::CreateTournament {
+            name: "spring".into(),
+            format: TournamentFormat::Swiss { rounds: 5 },
+        },
+        GameMessage::CreateTournament {
+            name: "club".into(),
+            format: TournamentFormat::RoundRobin,
+        },
+        GameMessage::JoinTournament {
+            name: "spring".into(),
+        },
+        GameMessage::StartTournament {
+            name: "spring".into(),
+        },
+        GameMessage::QueryStandings {
+            name: "spring".into(),
+        },
+        GameMessage::Standings {
+            standings: TournamentStandings {
+                name: "spring".into(),
+                format: TournamentFormat::Swiss { rounds: 5 },
+                organizer: "alice".into(),
+                round: 2,
+                total_rounds: 5,
+                finished: false,
+                rows: vec![StandingRow {
+                    rank: 1,
+                    name: "alice".into(),
+                    points: 1.5,
+                    buchholz: 2.0,
+                    sonneborn_berger: 1.25,
+                    games: 2,
+                }],
+                pairings: vec![
+                    PairingInfo {
+                        room: "t-spring-2-1".into(),
+                        white: "alice".into(),
+                        black: Some("bob".into()),
+                        result: Some(GameResult::Draw(DrawReason::ThreeFoldRepetition)),
+                        forfeit: false,
+                    },
+                    PairingInfo {
+                        room: "t-spring-2-2".into(),
+                        white: "carol".into(),
+                        black: None,
+                        result: None,
+                        forfeit: true,
+                    },
+                ],
+            },
+        },
+        GameMessage::ListRooms,
+        GameMessage::RoomList {
+            rooms: vec![
+                RoomInfo {
+                    code: "amber-fox".into(),
+                    host: "alice".into(),
+                    host_rating: Some(1612.5),
+                    open_color: PlayerColor::White,
+                },
+                RoomInfo {
+                    code: "brisk-owl".into(),
+                    host: "Guest".into(),
+                    host_rating: None,
+                    open_color: PlayerColor::Black,
+                },
+            ],
+        },
+        GameMessage::OfferRematch,
+        GameMessage::AcceptRematch,
+        GameMessage::RematchStarted {
+            color: PlayerColor::White,
+            score: MatchScore {
+                you: 1.5,
+                opponent: 0.5,
+                games: 2,
+            },
+        },
+        GameMessage::CreateCorrespondence {
+            opponent: "bob".into(),
+            days_per_move: 3,
+            color: Some(ColorPreference::Random),
+        },
+        GameMessage::QueryCorrespondence { my_turn_only: true },
+        GameMessage::CorrespondenceGames {
+            games: vec![CorrespondenceInfo {
+                room: "c-1".into(),
+                white: "alice".into(),
+                black: "bob".into(),
+                days_per_move: 3,
+                turn: PlayerColor::Black,
+                deadline: 1_790_000_000,
+                moves: 14,
+                result: Some(GameResult::Win(PlayerColor::White)),
+                challenger: None,
+            }],
+        },
+        GameMessage::TimeForfeit {
+            color: PlayerColor::Black,
+        },
+        GameMessage::RequestTakeback,
+        GameMessage::AcceptTakeback,
+        GameMessage::DeclineTakeback,
+        GameMessage::TakenBack {
+            actions: vec![
+                GameAction::Move {
+                    from: Pos::new(4, 1),
+                    to: Pos::new(4, 3),
+                },
+                GameAction::Upgrade {
+                    pos: Pos::new(0, 7),
+                },
+            ],
+        },
+        GameMessage::AcceptCorrespondence { room: "c-1".into() },
+        GameMessage::DeclineCorrespondence { room: "c-2".into() },
+    ]
+}
+
+fn variant_index(msg: &GameMessage) -> usize {
+    match msg {
+        GameMessage::Hello { .. } => 0,
+        GameMessage::ServerHello { .. } => 1,
+        GameMessage::UpgradeRequired { .. } => 2,
+        GameMessage::Join { .. } => 3,
+        GameMessage::RoomCode { .. } => 4,
+        GameMessage::Welcome { .. } => 5,
+        GameMessage::Move { .. } => 6,
+        GameMessage::Upgrade { .. } => 7,
+        GameMessage::Promote { .. } => 8,
+        GameMessage::Error { .. } => 9,
+        GameMessage::OpponentDisconnected => 10,
+        GameMessage::Register { .. } => 11,
+        GameMessage::Registered { .. } => 12,
+        GameMessage::Login { .. } => 13,
+        GameMessage::QueryRating { .. } => 14,
+        GameMessage::Rating { .. } => 15,
+        GameMessage::QueryLeaderboard { .. } => 16,
+        GameMessage::Leaderboard { .. } => 17,
+        GameMessage::Ping { .. } => 18,
+        GameMessage::Pong { .. } => 19,
+        GameMessage::ServerRestarting { .. } => 20,
+        GameMessage::CreateTournament { .. } => 21,
+        GameMessage::JoinTournament { .. } => 22,
+        GameMessage::StartTournament { .. } => 23,
+        GameMessage::QueryStandings { .. } => 24,
+        GameMessage::Standings { .. } => 25,
+        GameMessage::ListRooms => 26,
+        GameMessage::RoomList { .. } => 27,
+        GameMessage::OfferRematch => 28,
+        GameMessage::AcceptRematch => 29,
+        GameMessage::RematchStarted { .. } => 30,
+        GameMessage::CreateCorrespondence { .. } => 31,
+        GameMessage::QueryCorrespondence { .. } => 32,
+        GameMessage::CorrespondenceGames { .. } => 33,
+        GameMessage::TimeForfeit { .. } => 34,
+        GameMessage::RequestTakeback => 35,
+        GameMessage::AcceptTakeback => 36,
+        GameMessage::DeclineTakeback => 37,
+        GameMessage::TakenBack { .. } => 38,
+        GameMessage::AcceptCorrespondence { .. } => 39,
+        GameMessage::DeclineCorrespondence { .. } => 40,
+    }
+}
+
+const VARIANTS: usize = 41;
+
+/// `GameMessage` has no `PartialEq`, so messages are compared through their
+/// JSON value.
+fn value(msg: &GameMessage) -> serde_json::Value {
+    serde_json::to_value(msg).unwrap()
+}
+
+#[test]
+fn samples_cover_every_variant() {
+    let covered: HashSet<usize> = every_variant().iter().map(variant_index).collect();
+    assert_eq!(covered.len(), VARIANTS);
+}
+
+#[test]
+fn every_variant_round_trips_in_both_encodings() {
+    for encoding in ENCODINGS {
+        for msg in every_variant() {
+            let bytes = encoding.encode(&msg).unwrap();
+            let decoded = encoding
+                .decode(&bytes)
+                .unwrap_or_else(|e| panic!("{:?} {} did not decode: {}", encoding, msg.kind(), e));
+            assert_eq!(
+                value(&decoded),
+                value(&msg),
+                "{:?} {}",
+                encoding,
+                msg.kind()
+            );
+        }
+    }
+}
+
+#[test]
+fn message_pack_is_smaller_than_json() {
+    for msg in every_variant() {
+        let json = Encoding::Json.encode(&msg).unwrap();
+        let packed = Encoding::MessagePack.encode(&msg).unwrap();
+        assert!(
+            packed.len() < json.len(),
+            "{}: {} bytes packed, {} as JSON",
+            msg.kind(),
+            packed.len(),
+            json.len()
+        );
+    }
+}
+
+#[test]
+fn defaulted_fields_may_be_left_out_in_both_encodings() {
+    #[derive(serde::Serialize)]
+    enum OldJoin {
+        Join { room: Option<String> },
+    }
+    let old = OldJoin::Join {
+        room: Some("amber-fox".into()),
+    };
+    let frames = [
+        serde_json::to_vec(&old).unwrap(),
+        rmp_serde::to_vec_named(&old).unwrap(),
+    ];
+    for (encoding, bytes) in ENCODINGS.into_iter().zip(frames) {
+        match encoding.decode(&bytes).unwrap() {
+            GameMessage::Join {
+                room,
+                password,
+                color,
+                public,
+                rejoin_token,
+            } => {
+                assert_eq!(room.as_deref(), Some("amber-fox"));
+                assert_eq!((password, color, public), (None, None, false));
+                assert_eq!(rejoin_token, None);
+            }
+            other => panic!("{:?} decoded {}", encoding, other.kind()),
+        }
+    }
+}
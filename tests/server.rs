@@ -0,0 +1,1505 @@
+use ascension_chess::client::{Client, ClientError, DisconnectReason, Event, JoinOptions, Joined};
+use ascension_chess::game::{Game, GameResult};
+use ascension_chess::network::{
+    ColorPreference, CorrespondenceInfo, DISCOVERY_QUERY, GameAction, GameMessage,
+    MIN_PROTOCOL_VERSION, MatchScore, ServerAnnouncement, TournamentFormat, TournamentStandings,
+};
+use ascension_chess::server::{Args, Config, Server};
+use ascension_chess::tls::ClientTls;
+use ascension_chess::types::{PlayerColor, Pos};
+use clap::Parser;
+use futures::{SinkExt, StreamExt};
+use std::collections::HashSet;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+const WAIT: Duration = Duration::from_secs(5);
+
+/// A server running in the test's runtime on ephemeral ports, with its
+/// files in a fresh directory.
+struct TestServer {
+    addr: SocketAddr,
+    ws_addr: SocketAddr,
+    http_addr: SocketAddr,
+    discovery_addr: SocketAddr,
+    dir: PathBuf,
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<std::io::Result<()>>,
+}
+
+impl TestServer {
+    async fn start(extra_args: &[&str]) -> Self {
+        Self::start_in(fresh_dir(), extra_args).await
+    }
+
+    async fn start_in(dir: PathBuf, extra_args: &[&str]) -> Self {
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let mut args = vec![
+            "server".to_string(),
+            "--bind=127.0.0.1:0".into(),
+            "--ws-bind=127.0.0.1:0".into(),
+            "--http-bind=127.0.0.1:0".into(),
+            "--lan-discovery=true".into(),
+            "--discovery-bind=127.0.0.1:0".into(),
+            format!("--accounts-file={}", path("accounts.json")),
+            format!("--games-dir={}", path("games")),
+            format!("--rooms-file={}", path("rooms.json")),
+            format!("--tournaments-file={}", path("tournaments.json")),
+            format!("--correspondence-file={}", path("correspondence.json")),
+        ];
+        args.extend(extra_args.iter().map(|arg| arg.to_string()));
+        let config = Config::load(Args::parse_from(args)).unwrap();
+
+        let server = Server::bind(config).await.unwrap();
+        let addr = server.tcp_addr().unwrap();
+        let ws_addr = server.ws_addr().unwrap();
+        let http_addr = server.http_addr().unwrap();
+        let discovery_addr = server.discovery_addr().unwrap().unwrap();
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let task = tokio::spawn(server.run(async {
+            shutdown_rx.await.ok();
+        }));
+        TestServer {
+            addr,
+            ws_addr,
+            http_addr,
+            discovery_addr,
+            dir,
+            shutdown: Some(shutdown),
+            task,
+        }
+    }
+
+    async fn connect(&self) -> Client {
+        Client::connect(self.addr, "test", None).await.unwrap()
+    }
+
+    /// Connects and takes a seat in `room`, or in a new room if `None`.
+    async fn seat(&self, room: Option<&str>, color: ColorPreference) -> (Client, String) {
+        let (client, joined) = self.seat_info(room, color).await;
+        (client, joined.room)
+    }
+
+    async fn seat_info(&self, room: Option<&str>, color: ColorPreference) -> (Client, Joined) {
+        let mut client = self.connect().await;
+        let joined = client
+            .join(JoinOptions {
+                room: room.map(str::to_string),
+                color: Some(color),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        (client, joined)
+    }
+
+    /// Connects and takes back the seat `rejoin_token` was issued for.
+    async fn rejoin(&self, room: &str, rejoin_token: &str) -> (Client, Joined) {
+        let mut client = self.connect().await;
+        let joined = client
+            .join(JoinOptions {
+                room: Some(room.to_string()),
+                rejoin_token: Some(rejoin_token.to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        (client, joined)
+    }
+
+    /// Stops the server the way the binary does on SIGTERM, returning its
+    /// directory for a restart.
+    async fn stop(mut self) -> PathBuf {
+        self.shutdown.take().unwrap().send(()).ok();
+        timeout(WAIT, &mut self.task)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        std::mem::take(&mut self.dir)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        if !self.dir.as_os_str().is_empty() {
+            fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+}
+
+/// An empty directory of its own for each server.
+fn fresh_dir() -> PathBuf {
+    static NEXT_DIR: AtomicU32 = AtomicU32::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "ascension-test-{}-{}",
+        std::process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The next message from the server, skipping heartbeat latency updates.
+async fn next_message(client: &mut Client) -> GameMessage {
+    loop {
+        match timeout(WAIT, client.next_event()).await.unwrap() {
+            Some(Event::Message(msg)) => return msg,
+            Some(Event::Latency(_)) => {}
+            other => panic!("expected a message, got {:?}", other),
+        }
+    }
+}
+
+async fn next_disconnect(client: &mut Client) -> DisconnectReason {
+    loop {
+        match timeout(WAIT, client.next_event()).await.unwrap() {
+            Some(Event::Disconnected(reason)) => return reason,
+            Some(_) => {}
+            None => panic!("event stream ended without a Disconnected event"),
+        }
+    }
+}
+
+fn mv(from: (i32, i32), to: (i32, i32)) -> GameAction {
+    GameAction::Move {
+        from: Pos::new(from.0, from.1),
+        to: Pos::new(to.0, to.1),
+    }
+}
+
+/// The quickest checkmate: 1. f3 e5 2. g4 Qh4#.
+fn fools_mate() -> [GameAction; 4] {
+    [
+        mv((5, 1), (5, 2)),
+        mv((4, 6), (4, 4)),
+        mv((6, 1), (6, 3)),
+        mv((3, 7), (7, 3)),
+    ]
+}
+
+#[tokio::test]
+async fn creating_a_room_assigns_a_code_and_seats() {
+    let server = TestServer::start(&[]).await;
+    let mut white = server.connect().await;
+    let created = white
+        .join(JoinOptions {
+            color: Some(ColorPreference::Black),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(created.created);
+    assert_eq!(created.color, PlayerColor::Black);
+    assert_eq!(created.room.split('-').count(), 3);
+
+    let (_, room) = server
+        .seat(Some(&created.room), ColorPreference::Black)
+        .await;
+    assert_eq!(room, created.room);
+
+    let mut second = server.connect().await;
+    let joined = second
+        .join(JoinOptions {
+            room: Some("practice".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(joined.created);
+    let mut third = server.connect().await;
+    let joined = third
+        .join(JoinOptions {
+            room: Some("practice".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(!joined.created);
+    assert_eq!(joined.color, PlayerColor::Black);
+}
+
+#[tokio::test]
+async fn full_game_is_relayed_rated_and_archived() {
+    let server = TestServer::start(&[]).await;
+    let mut accounts = Vec::new();
+    for name in ["alice", "bob"] {
+        let mut client = server.connect().await;
+        let token = client.register(name).await.unwrap();
+        client.disconnect().await;
+        accounts.push((name, token));
+    }
+
+    let mut players = Vec::new();
+    for (name, token) in &accounts {
+        let mut client = server.connect().await;
+        client.login(name, token).await.unwrap();
+        client
+            .join(JoinOptions {
+                room: Some("rated".into()),
+                color: Some(ColorPreference::White),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        players.push(client);
+    }
+
+    let mut game = Game::new();
+    for (ply, action) in fools_mate().into_iter().enumerate() {
+        let (mover, opponent) = if ply % 2 == 0 { (0, 1) } else { (1, 0) };
+        players[mover].send_action(action).unwrap();
+        let relayed = next_message(&mut players[opponent]).await;
+        assert_eq!(GameAction::from_message(&relayed), Some(action));
+        action.apply(&mut game);
+    }
+    assert_eq!(game.result, Some(GameResult::Win(PlayerColor::Black)));
+
+    for (player, (name, _)) in players.iter_mut().zip(&accounts) {
+        match next_message(player).await {
+            GameMessage::Rating { entry } => {
+                assert_eq!(entry.name, *name);
+                assert_eq!(entry.wins + entry.losses + entry.draws, 1);
+            }
+            other => panic!("expected a rating, got {:?}", other),
+        }
+    }
+    let index = fs::read_to_string(server.dir.join("games/index.jsonl")).unwrap();
+    assert_eq!(index.lines().count(), 1);
+}
+
+/// Registers `white` and `black`, seats them in a new room and plays Fool's
+/// Mate, returning once the server has rated and archived the game.
+async fn play_rated_game(server: &TestServer, white_name: &str, black_name: &str) {
+    let mut white = server.connect().await;
+    white.register(white_name).await.unwrap();
+    let mut black = server.connect().await;
+    black.register(black_name).await.unwrap();
+    let room = white
+        .join(JoinOptions {
+            color: Some(ColorPreference::White),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .room;
+    black
+        .join(JoinOptions {
+            room: Some(room),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    play_turns(&mut white, &mut black, &fools_mate()).await;
+
+    for player in [&mut white, &mut black] {
+        assert!(matches!(
+            next_message(player).await,
+            GameMessage::Rating { .. }
+        ));
+    }
+    // Black's mating move is archived before the server reads its next
+    // message, so an answer to this one means the archive is up to date.
+    black
+        .send(GameMessage::QueryRating {
+            name: black_name.into(),
+        })
+        .unwrap();
+    next_message(&mut black).await;
+}
+
+/// Sends a bare HTTP/1.1 GET with any `headers` and returns the status code
+/// and body.
+async fn http_get(addr: SocketAddr, path: &str, headers: &[&str]) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let mut request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+        path, addr
+    );
+    for header in headers {
+        request.push_str(header);
+        request.push_str("\r\n");
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = String::new();
+    timeout(WAIT, stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
+
+async fn list_games(server: &TestServer, query: &str) -> Vec<serde_json::Value> {
+    let (status, body) = http_get(server.http_addr, &format!("/games?{}", query), &[]).await;
+    assert_eq!(status, 200);
+    serde_json::from_str(&body).unwrap()
+}
+
+#[tokio::test]
+async fn finished_games_are_listed_and_downloaded_over_http() {
+    let server = TestServer::start(&[]).await;
+    play_rated_game(&server, "alice", "bob").await;
+
+    let games = list_games(&server, "player=bob").await;
+    assert_eq!(games.len(), 1);
+    let game = &games[0];
+    assert_eq!(game["white"], "alice");
+    assert_eq!(game["black"], "bob");
+    assert_eq!(
+        game["result"],
+        serde_json::to_value(GameResult::Win(PlayerColor::Black)).unwrap()
+    );
+    assert!(list_games(&server, "player=carol").await.is_empty());
+
+    let finished_at = game["finished_at"].as_u64().unwrap();
+    let window = format!("since={}&until={}", finished_at, finished_at);
+    assert_eq!(list_games(&server, &window).await.len(), 1);
+    let later = format!("since={}", finished_at + 1);
+    assert!(list_games(&server, &later).await.is_empty());
+    let earlier = format!("until={}", finished_at - 1);
+    assert!(list_games(&server, &earlier).await.is_empty());
+
+    let id = game["id"].as_u64().unwrap();
+    let (status, body) = http_get(server.http_addr, &format!("/games/{}", id), &[]).await;
+    assert_eq!(status, 200);
+    let record: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(record["id"], id);
+    assert_eq!(record["white"], "alice");
+    assert_eq!(
+        record["actions"].as_array().unwrap().len(),
+        fools_mate().len()
+    );
+
+    let (status, _) = http_get(server.http_addr, &format!("/games/{}", id + 1), &[]).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn metrics_count_started_and_finished_games() {
+    let server = TestServer::start(&[]).await;
+    play_rated_game(&server, "alice", "bob").await;
+
+    let (status, body) = http_get(server.http_addr, "/metrics", &[]).await;
+    assert_eq!(status, 200);
+    for line in [
+        "ascension_games_started_total 1",
+        "ascension_games_finished_total 1",
+    ] {
+        assert!(body.lines().any(|l| l == line), "missing {:?}", line);
+    }
+}
+
+#[tokio::test]
+async fn status_page_needs_the_admin_token() {
+    let server = TestServer::start(&[]).await;
+    let (status, _) = http_get(server.http_addr, "/status", &[]).await;
+    assert_eq!(status, 404);
+
+    let server = TestServer::start(&["--admin-token=secret"]).await;
+    let (_white, room) = server.seat(None, ColorPreference::White).await;
+    for headers in [&[][..], &["Authorization: Bearer wrong"]] {
+        let (status, _) = http_get(server.http_addr, "/status", headers).await;
+        assert_eq!(status, 401);
+    }
+    let (status, body) = http_get(
+        server.http_addr,
+        "/status",
+        &["Authorization: Bearer secret"],
+    )
+    .await;
+    assert_eq!(status, 200);
+    let page: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let rooms = page["rooms"].as_array().unwrap();
+    assert_eq!(rooms.len(), 1);
+    assert_eq!(rooms[0]["name"], room.as_str());
+    assert_eq!(rooms[0]["white"]["connected"], true);
+    assert_eq!(rooms[0]["black"]["connected"], false);
+}
+
+#[tokio::test]
+async fn illegal_and_out_of_turn_actions_are_refused() {
+    let server = TestServer::start(&[]).await;
+    let (mut white, room) = server.seat(None, ColorPreference::White).await;
+    let (mut black, _) = server.seat(Some(&room), ColorPreference::White).await;
+
+    black.send_action(mv((4, 6), (4, 4))).unwrap();
+    assert!(matches!(
+        next_message(&mut black).await,
+        GameMessage::Error { .. }
+    ));
+    white.send_action(mv((4, 1), (4, 5))).unwrap();
+    assert!(matches!(
+        next_message(&mut white).await,
+        GameMessage::Error { .. }
+    ));
+}
+
+/// Plays `actions` alternately from `white` and `black`, waiting for each to
+/// be relayed.
+async fn play_turns(white: &mut Client, black: &mut Client, actions: &[GameAction]) {
+    for (ply, &action) in actions.iter().enumerate() {
+        let (mover, opponent) = if ply % 2 == 0 {
+            (&mut *white, &mut *black)
+        } else {
+            (&mut *black, &mut *white)
+        };
+        mover.send_action(action).unwrap();
+        let relayed = next_message(opponent).await;
+        assert_eq!(GameAction::from_message(&relayed), Some(action));
+    }
+}
+
+#[tokio::test]
+async fn rematches_swap_colors_and_keep_the_match_score() {
+    let server = TestServer::start(&[]).await;
+    let (mut alice, joined) = server.seat_info(None, ColorPreference::White).await;
+    let room = joined.room;
+    let alice_token = joined.rejoin_token.unwrap();
+    let (mut bob, joined) = server.seat_info(Some(&room), ColorPreference::White).await;
+    let bob_token = joined.rejoin_token.unwrap();
+    // Bob mates as Black.
+    play_turns(&mut alice, &mut bob, &fools_mate()).await;
+
+    alice.send(GameMessage::OfferRematch).unwrap();
+    assert!(matches!(
+        next_message(&mut bob).await,
+        GameMessage::OfferRematch
+    ));
+    bob.send(GameMessage::AcceptRematch).unwrap();
+    let expected = [
+        (&mut alice, PlayerColor::Black, (0.0, 1.0)),
+        (&mut bob, PlayerColor::White, (1.0, 0.0)),
+    ];
+    for (player, expected_color, (you, opponent)) in expected {
+        match next_message(player).await {
+            GameMessage::RematchStarted { color, score } => {
+                assert_eq!(color, expected_color);
+                assert_eq!(
+                    score,
+                    MatchScore {
+                        you,
+                        opponent,
+                        games: 1
+                    }
+                );
+            }
+            other => panic!("expected RematchStarted, got {:?}", other),
+        }
+    }
+
+    // The rejoin tokens follow the players to their new seats.
+    let server = TestServer::start_in(server.stop().await, &[]).await;
+    let (mut alice, joined) = server.rejoin(&room, &alice_token).await;
+    assert_eq!(joined.color, PlayerColor::Black);
+    let (mut bob, joined) = server.rejoin(&room, &bob_token).await;
+    assert_eq!(joined.color, PlayerColor::White);
+
+    // Bob opens the second game, and this time Alice mates him.
+    play_turns(&mut bob, &mut alice, &fools_mate()).await;
+    alice.send(GameMessage::OfferRematch).unwrap();
+    next_message(&mut bob).await;
+    bob.send(GameMessage::OfferRematch).unwrap();
+    match next_message(&mut alice).await {
+        GameMessage::RematchStarted { color, score } => {
+            assert_eq!(color, PlayerColor::White);
+            assert_eq!(
+                score,
+                MatchScore {
+                    you: 1.0,
+                    opponent: 1.0,
+                    games: 2
+                }
+            );
+        }
+        other => panic!("expected RematchStarted, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn accepted_take_backs_undo_the_last_turn_for_both_players() {
+    let server = TestServer::start(&[]).await;
+    let (mut white, room) = server.seat(None, ColorPreference::White).await;
+    let (mut black, _) = server.seat(Some(&room), ColorPreference::White).await;
+
+    white.send(GameMessage::RequestTakeback).unwrap();
+    assert!(matches!(
+        next_message(&mut white).await,
+        GameMessage::Error { .. }
+    ));
+
+    let played = [mv((4, 1), (4, 3)), mv((4, 6), (4, 4)), mv((6, 0), (5, 2))];
+    play_turns(&mut white, &mut black, &played).await;
+    white.send(GameMessage::RequestTakeback).unwrap();
+    assert!(matches!(
+        next_message(&mut black).await,
+        GameMessage::RequestTakeback
+    ));
+    black.send(GameMessage::AcceptTakeback).unwrap();
+
+    for player in [&mut white, &mut black] {
+        match next_message(player).await {
+            GameMessage::TakenBack { actions } => {
+                assert_eq!(actions, played[..2]);
+                assert_eq!(GameAction::replay(&actions).turn, PlayerColor::White);
+            }
+            other => panic!("expected TakenBack, got {:?}", other),
+        }
+    }
+
+    // White moves again, and a second acceptance finds nothing to accept.
+    play_turns(&mut white, &mut black, &[mv((3, 1), (3, 3))]).await;
+    black.send(GameMessage::AcceptTakeback).unwrap();
+    assert!(matches!(
+        next_message(&mut black).await,
+        GameMessage::Error { .. }
+    ));
+}
+
+#[tokio::test]
+async fn declined_take_backs_leave_the_game_as_it_was() {
+    let server = TestServer::start(&[]).await;
+    let (mut white, room) = server.seat(None, ColorPreference::White).await;
+    let (mut black, _) = server.seat(Some(&room), ColorPreference::White).await;
+
+    play_turns(&mut white, &mut black, &[mv((4, 1), (4, 3))]).await;
+    white.send(GameMessage::RequestTakeback).unwrap();
+    assert!(matches!(
+        next_message(&mut black).await,
+        GameMessage::RequestTakeback
+    ));
+    black.send(GameMessage::DeclineTakeback).unwrap();
+    assert!(matches!(
+        next_message(&mut white).await,
+        GameMessage::DeclineTakeback
+    ));
+
+    black.send_action(mv((4, 6), (4, 4))).unwrap();
+    let relayed = next_message(&mut white).await;
+    assert_eq!(GameAction::from_message(&relayed), Some(mv((4, 6), (4, 4))));
+}
+
+#[tokio::test]
+async fn take_backs_the_opponent_plays_past_are_declined() {
+    let server = TestServer::start(&[]).await;
+    let (mut white, room) = server.seat(None, ColorPreference::White).await;
+    let (mut black, _) = server.seat(Some(&room), ColorPreference::White).await;
+
+    play_turns(&mut white, &mut black, &[mv((4, 1), (4, 3))]).await;
+    white.send(GameMessage::RequestTakeback).unwrap();
+    assert!(matches!(
+        next_message(&mut black).await,
+        GameMessage::RequestTakeback
+    ));
+    black.send_action(mv((4, 6), (4, 4))).unwrap();
+    assert!(matches!(
+        next_message(&mut white).await,
+        GameMessage::DeclineTakeback
+    ));
+    let relayed = next_message(&mut white).await;
+    assert_eq!(GameAction::from_message(&relayed), Some(mv((4, 6), (4, 4))));
+
+    black.send(GameMessage::AcceptTakeback).unwrap();
+    assert!(matches!(
+        next_message(&mut black).await,
+        GameMessage::Error { .. }
+    ));
+}
+
+#[tokio::test]
+async fn players_always_get_opposite_colors_whatever_they_ask_for() {
+    use ColorPreference::{Black, Random, White};
+    let server = TestServer::start(&["--max-requests-per-window=200"]).await;
+    let mut creator_colors = HashSet::new();
+    for round in 0..48 {
+        let creator_wants = [Random, White, Black][round % 3];
+        let joiner_wants = [White, Black, Random, White][round % 4];
+        let (_creator, created) = server.seat_info(None, creator_wants).await;
+        let (_joiner, joined) = server.seat_info(Some(&created.room), joiner_wants).await;
+        assert_eq!(joined.color, created.color.opposite());
+        match creator_wants {
+            White => assert_eq!(created.color, PlayerColor::White),
+            Black => assert_eq!(created.color, PlayerColor::Black),
+            Random => {
+                creator_colors.insert(created.color);
+            }
+        }
+    }
+    // Sixteen random picks all landing on one color would be a 1 in 32768 fluke.
+    assert_eq!(creator_colors.len(), 2);
+}
+
+#[tokio::test]
+async fn third_player_finds_the_room_full() {
+    let server = TestServer::start(&[]).await;
+    let (_white, room) = server.seat(None, ColorPreference::White).await;
+    let (_black, _) = server.seat(Some(&room), ColorPreference::White).await;
+
+    let mut third = server.connect().await;
+    let error = third
+        .join(JoinOptions {
+            room: Some(room),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ClientError::Server(ref message) if message == "Room full"));
+}
+
+#[tokio::test]
+async fn wrong_password_is_refused() {
+    let server = TestServer::start(&[]).await;
+    let mut host = server.connect().await;
+    host.join(JoinOptions {
+        room: Some("locked".into()),
+        password: Some("secret".into()),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    let mut guest = server.connect().await;
+    let error = guest
+        .join(JoinOptions {
+            room: Some("locked".into()),
+            password: Some("guess".into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ClientError::Server(_)));
+}
+
+#[tokio::test]
+async fn invalid_room_names_are_dropped() {
+    let server = TestServer::start(&["--room-name-max-len=8"]).await;
+    for name in ["a b", "waytoolong", "semi;co"] {
+        let mut client = server.connect().await;
+        let error = client
+            .join(JoinOptions {
+                room: Some(name.into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, ClientError::Disconnected(_)),
+            "{}: {:?}",
+            name,
+            error
+        );
+    }
+}
+
+#[tokio::test]
+async fn connections_over_the_rate_limit_are_refused() {
+    let server = TestServer::start(&["--max-requests-per-window=2"]).await;
+    let _first = server.connect().await;
+    let _second = server.connect().await;
+    assert!(Client::connect(server.addr, "test", None).await.is_err());
+}
+
+#[tokio::test]
+async fn message_floods_are_throttled_then_dropped() {
+    let server = TestServer::start(&[
+        "--messages-per-sec=1",
+        "--message-burst=3",
+        "--max-rate-violations=3",
+    ])
+    .await;
+    let mut client = server.connect().await;
+    for _ in 0..10 {
+        client.send(GameMessage::ListRooms).unwrap();
+    }
+
+    let mut throttled = 0;
+    let reason = loop {
+        match timeout(WAIT, client.next_event()).await.unwrap() {
+            Some(Event::Message(GameMessage::Error { message })) => {
+                assert_eq!(message, "Too many messages, slow down");
+                throttled += 1;
+            }
+            Some(Event::Disconnected(reason)) => break reason,
+            Some(_) => {}
+            None => panic!("event stream ended without a Disconnected event"),
+        }
+    };
+    assert!(throttled > 0);
+    assert_eq!(reason, DisconnectReason::ServerClosed);
+}
+
+#[tokio::test]
+async fn leaving_tells_the_opponent_and_frees_the_room() {
+    let server = TestServer::start(&[]).await;
+    let (white, room) = server.seat(Some("freed"), ColorPreference::White).await;
+    let (mut black, _) = server.seat(Some(&room), ColorPreference::White).await;
+
+    white.disconnect().await;
+    assert!(matches!(
+        next_message(&mut black).await,
+        GameMessage::OpponentDisconnected
+    ));
+    black.disconnect().await;
+
+    // The room is removed with its last player, so joining creates it anew
+    // once the server has seen both leave.
+    let joined = timeout(WAIT, async {
+        loop {
+            let (client, joined) = server
+                .seat_info(Some("freed"), ColorPreference::Black)
+                .await;
+            if joined.created {
+                break joined;
+            }
+            client.disconnect().await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert!(joined.created);
+    assert_eq!(joined.color, PlayerColor::Black);
+}
+
+#[tokio::test]
+async fn public_rooms_leave_the_lobby_once_taken() {
+    let server = TestServer::start(&[]).await;
+    let mut host = server.connect().await;
+    let hosted = host
+        .join(JoinOptions {
+            public: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let mut browser = server.connect().await;
+    let rooms = browser.list_rooms().await.unwrap();
+    assert_eq!(rooms.len(), 1);
+    assert_eq!(rooms[0].code, hosted.room);
+    assert_eq!(rooms[0].open_color, PlayerColor::Black);
+
+    let (_guest, _) = server
+        .seat(Some(&hosted.room), ColorPreference::White)
+        .await;
+    assert!(browser.list_rooms().await.unwrap().is_empty());
+}
+
+/// The next tournament standings sent to `client`, skipping rating updates.
+async fn next_standings(client: &mut Client) -> TournamentStandings {
+    loop {
+        match next_message(client).await {
+            GameMessage::Standings { standings } => return standings,
+            GameMessage::Rating { .. } => {}
+            other => panic!("expected Standings, got {}", other.kind()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn tournament_rounds_move_on_after_games_and_no_shows() {
+    let server = TestServer::start(&["--room-timeout-secs=1"]).await;
+    let mut players = Vec::new();
+    for name in ["alice", "bob", "carol", "dave"] {
+        let mut client = server.connect().await;
+        client.register(name).await.unwrap();
+        players.push((name, client));
+    }
+    let cup = || "cup".to_string();
+    players[0]
+        .1
+        .send(GameMessage::CreateTournament {
+            name: cup(),
+            format: TournamentFormat::Swiss { rounds: 2 },
+        })
+        .unwrap();
+    next_standings(&mut players[0].1).await;
+    for (_, client) in &mut players {
+        client
+            .send(GameMessage::JoinTournament { name: cup() })
+            .unwrap();
+        next_standings(client).await;
+    }
+    players[0]
+        .1
+        .send(GameMessage::StartTournament { name: cup() })
+        .unwrap();
+    let round = next_standings(&mut players[0].1).await;
+    assert_eq!(round.round, 1);
+
+    let mut take = |name: &str| {
+        let i = players.iter().position(|(n, _)| *n == name).unwrap();
+        players.remove(i).1
+    };
+    let games: Vec<(Client, Client, String)> = round
+        .pairings
+        .iter()
+        .map(|p| {
+            let black = p.black.as_deref().unwrap();
+            (take(&p.white), take(black), p.room.clone())
+        })
+        .collect();
+    let mut joined = Vec::new();
+    for (mut white, black, room) in games {
+        let options = JoinOptions {
+            room: Some(room),
+            ..Default::default()
+        };
+        white.join(options.clone()).await.unwrap();
+        joined.push((white, black, options));
+    }
+
+    // The first board is played out; Black on the second never turns up.
+    let (mut white, mut black, options) = joined.remove(0);
+    black.join(options).await.unwrap();
+    play_turns(&mut white, &mut black, &fools_mate()).await;
+    let after_game = next_standings(&mut black).await;
+    assert_eq!(after_game.round, 1);
+    let (mut waiting, _, _) = joined.remove(0);
+    next_disconnect(&mut waiting).await;
+
+    let mut observer = server.connect().await;
+    let standings = timeout(WAIT, async {
+        loop {
+            observer
+                .send(GameMessage::QueryStandings { name: cup() })
+                .unwrap();
+            let standings = next_standings(&mut observer).await;
+            if standings.round == 2 {
+                return standings;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let winners: HashSet<&str> = standings
+        .rows
+        .iter()
+        .filter(|row| row.points == 1.0)
+        .map(|row| row.name.as_str())
+        .collect();
+    let expected: HashSet<&str> = [
+        round.pairings[0].black.as_deref().unwrap(),
+        round.pairings[1].white.as_str(),
+    ]
+    .into();
+    assert_eq!(winners, expected);
+    assert_eq!(standings.pairings.len(), 2);
+    assert!(standings.pairings.iter().all(|p| p.result.is_none()));
+}
+
+/// The correspondence games listed in the next message to `client`.
+async fn next_correspondence(client: &mut Client) -> Vec<CorrespondenceInfo> {
+    match next_message(client).await {
+        GameMessage::CorrespondenceGames { games } => games,
+        other => panic!("expected CorrespondenceGames, got {}", other.kind()),
+    }
+}
+
+#[tokio::test]
+async fn correspondence_games_start_only_once_the_challenge_is_accepted() {
+    let server = TestServer::start(&[]).await;
+    let mut alice = server.connect().await;
+    let alice_token = alice.register("alice").await.unwrap();
+    let mut bob = server.connect().await;
+    let bob_token = bob.register("bob").await.unwrap();
+    let join_as = |name: &'static str, token: String, room: String| {
+        let server = &server;
+        async move {
+            let mut client = server.connect().await;
+            client.login(name, &token).await.unwrap();
+            let joined = client
+                .join(JoinOptions {
+                    room: Some(room),
+                    ..Default::default()
+                })
+                .await;
+            (client, joined)
+        }
+    };
+
+    alice
+        .send(GameMessage::CreateCorrespondence {
+            opponent: "bob".into(),
+            days_per_move: 3,
+            color: Some(ColorPreference::White),
+        })
+        .unwrap();
+    let challenge = next_correspondence(&mut alice).await.remove(0);
+    assert_eq!(challenge.challenger.as_deref(), Some("alice"));
+    assert!(!challenge.is_turn_of("alice"));
+    let (_, refused) = join_as("alice", alice_token.clone(), challenge.room.clone()).await;
+    assert!(matches!(refused, Err(ClientError::Server(_))));
+
+    bob.send(GameMessage::AcceptCorrespondence {
+        room: challenge.room.clone(),
+    })
+    .unwrap();
+    let game = next_correspondence(&mut bob).await.remove(0);
+    assert_eq!(game.challenger, None);
+    assert!(game.is_turn_of("alice"));
+
+    let (mut white, joined) = join_as("alice", alice_token, game.room.clone()).await;
+    assert_eq!(joined.unwrap().color, PlayerColor::White);
+    let (mut black, joined) = join_as("bob", bob_token, game.room.clone()).await;
+    assert_eq!(joined.unwrap().color, PlayerColor::Black);
+    play_turns(&mut white, &mut black, &[mv((4, 1), (4, 3))]).await;
+
+    // A declined challenge is dropped and can no longer be accepted.
+    alice
+        .send(GameMessage::CreateCorrespondence {
+            opponent: "bob".into(),
+            days_per_move: 3,
+            color: None,
+        })
+        .unwrap();
+    let declined = next_correspondence(&mut alice).await.remove(0);
+    bob.send(GameMessage::DeclineCorrespondence {
+        room: declined.room.clone(),
+    })
+    .unwrap();
+    let remaining = next_correspondence(&mut bob).await;
+    assert_eq!(
+        remaining.iter().map(|g| &g.room).collect::<Vec<_>>(),
+        [&game.room]
+    );
+    bob.send(GameMessage::AcceptCorrespondence {
+        room: declined.room,
+    })
+    .unwrap();
+    assert!(matches!(
+        next_message(&mut bob).await,
+        GameMessage::Error { .. }
+    ));
+}
+
+#[tokio::test]
+async fn shutdown_saves_rooms_for_the_next_start() {
+    let server = TestServer::start(&[]).await;
+    let (mut white, joined_white) = server.seat_info(None, ColorPreference::White).await;
+    let room = joined_white.room;
+    let (mut black, _) = server.seat(Some(&room), ColorPreference::White).await;
+    let opening = mv((4, 1), (4, 3));
+    white.send_action(opening).unwrap();
+    next_message(&mut black).await;
+
+    let dir = server.stop().await;
+    let mut tokens = Vec::new();
+    for player in [&mut white, &mut black] {
+        match next_message(player).await {
+            GameMessage::ServerRestarting {
+                room: notice_room,
+                rejoin_token,
+                ..
+            } => {
+                assert_eq!(notice_room.as_deref(), Some(room.as_str()));
+                tokens.push(rejoin_token.unwrap());
+            }
+            other => panic!("expected ServerRestarting, got {:?}", other),
+        }
+        assert_eq!(
+            next_disconnect(player).await,
+            DisconnectReason::ServerClosed
+        );
+    }
+    assert_eq!(joined_white.rejoin_token.as_ref(), Some(&tokens[0]));
+
+    let server = TestServer::start_in(dir, &[]).await;
+    // Both seats are kept for their players, so a newcomer finds none.
+    let mut stranger = server.connect().await;
+    assert!(
+        stranger
+            .join(JoinOptions {
+                room: Some(room.clone()),
+                ..Default::default()
+            })
+            .await
+            .is_err()
+    );
+
+    // Black comes back first, leaves again, and the room waits for White.
+    let (mut black, joined) = server.rejoin(&room, &tokens[1]).await;
+    assert_eq!(joined.color, PlayerColor::Black);
+    let replayed = next_message(&mut black).await;
+    assert_eq!(GameAction::from_message(&replayed), Some(opening));
+    black.disconnect().await;
+
+    let (mut white, joined) = server.rejoin(&room, &tokens[0]).await;
+    assert_eq!(joined.color, PlayerColor::White);
+    let replayed = next_message(&mut white).await;
+    assert_eq!(GameAction::from_message(&replayed), Some(opening));
+    let (_black, joined) = server.rejoin(&room, &tokens[1]).await;
+    assert_eq!(joined.color, PlayerColor::Black);
+}
+
+#[tokio::test]
+async fn discovery_queries_are_answered_with_the_game_port_and_open_rooms() {
+    let server = TestServer::start(&["--display-name=Club night"]).await;
+    let mut host = server.connect().await;
+    let hosted = host
+        .join(JoinOptions {
+            public: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket
+        .send_to(b"hello", server.discovery_addr)
+        .await
+        .unwrap();
+    socket
+        .send_to(DISCOVERY_QUERY, server.discovery_addr)
+        .await
+        .unwrap();
+    let mut buf = [0; 8192];
+    let (len, from) = timeout(WAIT, socket.recv_from(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(from, server.discovery_addr);
+    let announcement: ServerAnnouncement = serde_json::from_slice(&buf[..len]).unwrap();
+    assert_eq!(announcement.server_name, "Club night");
+    assert_eq!(announcement.port, server.addr.port());
+    assert_eq!(announcement.open_room_count, 1);
+    assert_eq!(announcement.open_rooms[0].code, hosted.room);
+}
+
+#[tokio::test]
+async fn discovery_counts_open_rooms_beyond_the_lobby_listing() {
+    let server = TestServer::start(&["--max-requests-per-window=200"]).await;
+    let mut hosts = Vec::new();
+    for _ in 0..45 {
+        let mut host = server.connect().await;
+        host.join(JoinOptions {
+            public: true,
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        hosts.push(host);
+    }
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket
+        .send_to(DISCOVERY_QUERY, server.discovery_addr)
+        .await
+        .unwrap();
+    let mut buf = [0; 8192];
+    let (len, _) = timeout(WAIT, socket.recv_from(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    let announcement: ServerAnnouncement = serde_json::from_slice(&buf[..len]).unwrap();
+    assert_eq!(announcement.open_room_count, 45);
+    assert!(announcement.open_rooms.len() < 45);
+}
+
+#[tokio::test]
+async fn discovery_queries_do_not_use_up_connections() {
+    let server = TestServer::start(&["--max-requests-per-window=2"]).await;
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut buf = [0; 8192];
+    for _ in 0..5 {
+        socket
+            .send_to(DISCOVERY_QUERY, server.discovery_addr)
+            .await
+            .unwrap();
+        timeout(WAIT, socket.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    server.seat(None, ColorPreference::White).await;
+    server.seat(None, ColorPreference::White).await;
+}
+
+#[test]
+fn lan_discovery_is_off_unless_enabled_or_hosting() {
+    let config = Config::load(Args::parse_from(["server"])).unwrap();
+    assert_eq!(config.discovery_bind, None);
+    let config = Config::load(Args::parse_from(["server", "--lan-discovery=true"])).unwrap();
+    assert!(config.discovery_bind.is_some());
+
+    let dir = fresh_dir();
+    let hosted = Config::hosted("127.0.0.1:0".parse().unwrap(), "Host", &dir).unwrap();
+    assert!(hosted.discovery_bind.is_some());
+    fs::remove_dir_all(dir).ok();
+}
+
+/// Sends one frame of JSON, as clients without the `msgpack` capability do.
+async fn send_json(conn: &mut Framed<TcpStream, LengthDelimitedCodec>, msg: &GameMessage) {
+    conn.send(serde_json::to_vec(msg).unwrap().into())
+        .await
+        .unwrap();
+}
+
+async fn next_json(conn: &mut Framed<TcpStream, LengthDelimitedCodec>) -> GameMessage {
+    let frame = timeout(WAIT, conn.next()).await.unwrap().unwrap().unwrap();
+    serde_json::from_slice(&frame).unwrap()
+}
+
+async fn connect_json(server: &TestServer) -> Framed<TcpStream, LengthDelimitedCodec> {
+    Framed::new(
+        TcpStream::connect(server.addr).await.unwrap(),
+        LengthDelimitedCodec::new(),
+    )
+}
+
+#[tokio::test]
+async fn clients_older_than_the_minimum_version_are_told_to_upgrade() {
+    let server = TestServer::start(&[]).await;
+    let mut old = connect_json(&server).await;
+    let hello = GameMessage::Hello {
+        protocol_version: MIN_PROTOCOL_VERSION - 1,
+        client_name: "ancient".into(),
+        capabilities: Vec::new(),
+    };
+    send_json(&mut old, &hello).await;
+    match next_json(&mut old).await {
+        GameMessage::UpgradeRequired { min_version, .. } => {
+            assert_eq!(min_version, MIN_PROTOCOL_VERSION);
+        }
+        other => panic!("expected UpgradeRequired, got {}", other.kind()),
+    }
+    assert!(timeout(WAIT, old.next()).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn unknown_messages_are_answered_with_an_error_and_ignored() {
+    let server = TestServer::start(&[]).await;
+    let mut newer = connect_json(&server).await;
+    let unknown = br#"{"Emote":{"name":"wave"}}"#;
+
+    newer.send(unknown.to_vec().into()).await.unwrap();
+    assert!(matches!(
+        next_json(&mut newer).await,
+        GameMessage::Error { .. }
+    ));
+    let hello = GameMessage::Hello {
+        protocol_version: 2,
+        client_name: "newer".into(),
+        capabilities: Vec::new(),
+    };
+    send_json(&mut newer, &hello).await;
+    assert!(matches!(
+        next_json(&mut newer).await,
+        GameMessage::ServerHello { .. }
+    ));
+    let join = GameMessage::Join {
+        room: None,
+        password: None,
+        color: Some(ColorPreference::White),
+        public: false,
+        rejoin_token: None,
+    };
+    send_json(&mut newer, &join).await;
+    let GameMessage::RoomCode { code } = next_json(&mut newer).await else {
+        panic!("expected RoomCode");
+    };
+    next_json(&mut newer).await;
+
+    // Still tolerated once seated, and the game carries on.
+    newer.send(unknown.to_vec().into()).await.unwrap();
+    assert!(matches!(
+        next_json(&mut newer).await,
+        GameMessage::Error { .. }
+    ));
+    let (mut black, _) = server.seat(Some(&code), ColorPreference::Black).await;
+    let white_move = fools_mate()[0];
+    send_json(&mut newer, &white_move.to_message()).await;
+    let relayed = next_message(&mut black).await;
+    assert_eq!(GameAction::from_message(&relayed), Some(white_move));
+}
+
+#[tokio::test]
+async fn clients_that_stop_answering_pings_are_disconnected() {
+    let server =
+        TestServer::start(&["--heartbeat-interval-secs=1", "--max-missed-heartbeats=2"]).await;
+    let (mut white, room) = server.seat(None, ColorPreference::White).await;
+
+    let mut silent = connect_json(&server).await;
+    let hello = GameMessage::Hello {
+        protocol_version: 2,
+        client_name: "silent".into(),
+        capabilities: vec!["heartbeat".into()],
+    };
+    send_json(&mut silent, &hello).await;
+    next_json(&mut silent).await;
+    let join = GameMessage::Join {
+        room: Some(room),
+        password: None,
+        color: None,
+        public: false,
+        rejoin_token: None,
+    };
+    send_json(&mut silent, &join).await;
+    let started = std::time::Instant::now();
+
+    // The first ping goes out at once, then one per interval until two in a
+    // row have gone unanswered.
+    let mut pings = 0;
+    while let Some(frame) = timeout(WAIT, silent.next()).await.unwrap() {
+        let Ok(frame) = frame else { break };
+        if let GameMessage::Ping { .. } = serde_json::from_slice(&frame).unwrap() {
+            pings += 1;
+        }
+    }
+    assert_eq!(pings, 2);
+    assert!(started.elapsed() >= Duration::from_millis(1900));
+    assert!(matches!(
+        next_message(&mut white).await,
+        GameMessage::OpponentDisconnected
+    ));
+}
+
+/// Connects as a load balancer would, announcing `client` as the source.
+async fn connect_proxied(
+    server: &TestServer,
+    client: &str,
+) -> Framed<TcpStream, LengthDelimitedCodec> {
+    let mut socket = TcpStream::connect(server.addr).await.unwrap();
+    let header = format!("PROXY TCP4 {} 127.0.0.1 40000 8080\r\n", client);
+    socket.write_all(header.as_bytes()).await.unwrap();
+    Framed::new(socket, LengthDelimitedCodec::new())
+}
+
+#[tokio::test]
+async fn bans_apply_to_the_client_named_in_the_proxy_header() {
+    let server = TestServer::start(&["--proxy-protocol=true", "--ban-ip=203.0.113.7"]).await;
+    let hello = GameMessage::hello("proxied");
+
+    let mut allowed = connect_proxied(&server, "198.51.100.2").await;
+    send_json(&mut allowed, &hello).await;
+    assert!(matches!(
+        next_json(&mut allowed).await,
+        GameMessage::ServerHello { .. }
+    ));
+
+    let mut banned = connect_proxied(&server, "203.0.113.7").await;
+    send_json(&mut banned, &hello).await;
+    // Closed, possibly with a reset since the hello was never read.
+    assert!(!matches!(
+        timeout(WAIT, banned.next()).await.unwrap(),
+        Some(Ok(_))
+    ));
+}
+
+#[tokio::test]
+async fn clients_without_msgpack_play_clients_with_it_in_json() {
+    let server = TestServer::start(&[]).await;
+    let mut old = connect_json(&server).await;
+    let hello = GameMessage::Hello {
+        protocol_version: 2,
+        client_name: "old".into(),
+        capabilities: vec!["accounts".into()],
+    };
+    send_json(&mut old, &hello).await;
+    match next_json(&mut old).await {
+        GameMessage::ServerHello { capabilities, .. } => {
+            assert!(!capabilities.iter().any(|c| c == "msgpack"));
+        }
+        other => panic!("expected ServerHello, got {}", other.kind()),
+    }
+    let join = GameMessage::Join {
+        room: None,
+        password: None,
+        color: Some(ColorPreference::White),
+        public: false,
+        rejoin_token: None,
+    };
+    send_json(&mut old, &join).await;
+    let GameMessage::RoomCode { code } = next_json(&mut old).await else {
+        panic!("expected RoomCode");
+    };
+    assert!(matches!(
+        next_json(&mut old).await,
+        GameMessage::Welcome {
+            color: PlayerColor::White,
+            ..
+        }
+    ));
+
+    let (mut new, _) = server.seat(Some(&code), ColorPreference::Black).await;
+    assert!(
+        new.server_info()
+            .capabilities
+            .iter()
+            .any(|c| c == "msgpack")
+    );
+    let [white_move, black_move, ..] = fools_mate();
+    send_json(&mut old, &white_move.to_message()).await;
+    let relayed = next_message(&mut new).await;
+    assert_eq!(GameAction::from_message(&relayed), Some(white_move));
+    new.send_action(black_move).unwrap();
+    let relayed = next_json(&mut old).await;
+    assert_eq!(GameAction::from_message(&relayed), Some(black_move));
+}
+
+type WebSocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>;
+
+async fn send_ws(ws: &mut WebSocket, msg: &GameMessage) {
+    let text = serde_json::to_string(msg).unwrap();
+    ws.send(tokio_tungstenite::tungstenite::Message::text(text))
+        .await
+        .unwrap();
+}
+
+async fn next_ws(ws: &mut WebSocket) -> GameMessage {
+    let frame = timeout(WAIT, ws.next()).await.unwrap().unwrap().unwrap();
+    serde_json::from_str(frame.to_text().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn websocket_and_tcp_clients_play_each_other() {
+    let server = TestServer::start(&[]).await;
+    let (mut tcp, room) = server.seat(None, ColorPreference::White).await;
+
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}", server.ws_addr))
+        .await
+        .unwrap();
+    send_ws(
+        &mut ws,
+        &GameMessage::Hello {
+            protocol_version: 2,
+            client_name: "browser".into(),
+            capabilities: vec!["accounts".into()],
+        },
+    )
+    .await;
+    assert!(matches!(
+        next_ws(&mut ws).await,
+        GameMessage::ServerHello { .. }
+    ));
+    send_ws(
+        &mut ws,
+        &GameMessage::Join {
+            room: Some(room),
+            password: None,
+            color: None,
+            public: false,
+            rejoin_token: None,
+        },
+    )
+    .await;
+    assert!(matches!(
+        next_ws(&mut ws).await,
+        GameMessage::Welcome {
+            color: PlayerColor::Black,
+            ..
+        }
+    ));
+
+    for (ply, action) in fools_mate().into_iter().enumerate() {
+        if ply % 2 == 0 {
+            tcp.send_action(action).unwrap();
+            let relayed = next_ws(&mut ws).await;
+            assert_eq!(GameAction::from_message(&relayed), Some(action));
+        } else {
+            send_ws(&mut ws, &action.to_message()).await;
+            let relayed = next_message(&mut tcp).await;
+            assert_eq!(GameAction::from_message(&relayed), Some(action));
+        }
+    }
+}
+
+/// Writes a new self-signed certificate and its key into `dir`, returning
+/// their paths.
+fn self_signed_cert(dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+    let rcgen::CertifiedKey { cert, signing_key } =
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_path = dir.join(format!("{}.pem", name));
+    let key_path = dir.join(format!("{}.key", name));
+    fs::write(&cert_path, cert.pem()).unwrap();
+    fs::write(&key_path, signing_key.serialize_pem()).unwrap();
+    (cert_path, key_path)
+}
+
+async fn start_tls() -> (TestServer, PathBuf) {
+    let dir = fresh_dir();
+    let (cert, key) = self_signed_cert(&dir, "server");
+    let server = TestServer::start_in(
+        dir,
+        &[
+            &format!("--tls-cert={}", cert.display()),
+            &format!("--tls-key={}", key.display()),
+        ],
+    )
+    .await;
+    (server, cert)
+}
+
+#[tokio::test]
+async fn tls_clients_trusting_the_certificate_can_play() {
+    let (server, cert) = start_tls().await;
+    let tls = ClientTls::pinned(&cert).unwrap();
+    let addr = server.addr.to_string();
+    let mut white = Client::connect_tls(&addr, &tls, "test", None)
+        .await
+        .unwrap();
+    let room = white
+        .join(JoinOptions {
+            color: Some(ColorPreference::White),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+        .room;
+    let mut black = Client::connect_tls(&addr, &tls, "test", None)
+        .await
+        .unwrap();
+    black
+        .join(JoinOptions {
+            room: Some(room),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let opening = mv((4, 1), (4, 3));
+    white.send_action(opening).unwrap();
+    let relayed = next_message(&mut black).await;
+    assert_eq!(GameAction::from_message(&relayed), Some(opening));
+}
+
+#[tokio::test]
+async fn tls_servers_refuse_plain_clients_and_untrusted_certificates_are_refused() {
+    let (server, _) = start_tls().await;
+    let addr = server.addr.to_string();
+    assert!(Client::connect(server.addr, "test", None).await.is_err());
+
+    let (other_cert, _) = self_signed_cert(&server.dir, "other");
+    let pinned_elsewhere = ClientTls::pinned(&other_cert).unwrap();
+    assert!(matches!(
+        Client::connect_tls(&addr, &pinned_elsewhere, "test", None).await,
+        Err(ClientError::Io(_))
+    ));
+    // A self-signed certificate is not one the public authorities vouch for.
+    let localhost = format!("localhost:{}", server.addr.port());
+    assert!(matches!(
+        Client::connect_tls(&localhost, &ClientTls::public(), "test", None).await,
+        Err(ClientError::Io(_))
+    ));
+}
+
+#[test]
+fn tls_cert_and_key_must_be_set_together() {
+    let error = Config::load(Args::parse_from(["server", "--tls-cert=server.pem"])).unwrap_err();
+    assert!(error.contains("tls_cert and tls_key"), "{}", error);
+}